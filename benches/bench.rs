@@ -36,6 +36,18 @@ fn bench_sets(density: f64, num_elements: usize) {
         set.remove(x); // ensure there is room for one more
         (rng.gen_range(0..mx), set)
     };
+    let gen32b = move || {
+        let mut rng = rand::thread_rng();
+        let mx = (num_elements as f64 / density) as u32 + 1;
+        let mut set = tinyset::SetU32b::new();
+        while set.len() < num_elements {
+            set.insert(rng.gen_range(0..mx));
+        }
+        let x = rng.gen_range(0..mx);
+        set.insert(x);
+        set.remove(x); // ensure there is room for one more
+        (rng.gen_range(0..mx), set)
+    };
     let genroaring = move || {
         let mut rng = rand::thread_rng();
         let mx = (num_elements as f64 / density) as u32 + 1;
@@ -110,17 +122,18 @@ fn bench_sets(density: f64, num_elements: usize) {
     };
 
     println!(
-        "\n{:5}, {:3}:        {:>7} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}",
-        density, num_elements, "u32", "roaring", "std32", "u64", "old64", "std", "id-set"
+        "\n{:5}, {:3}:        {:>7} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}",
+        density, num_elements, "u32", "u32b", "roaring", "std32", "u64", "old64", "std", "id-set"
     );
 
     // ensure that we've used the random number generator at least
     // once, so we won't be confused by any memory that it allocates.
     gen32();
     println!(
-        "{:>17}: {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b",
+        "{:>17}: {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b {:6.0}b",
         "size",
         (0..100).map(|_| mem_used(|| gen32().1).1).sum::<usize>() as f64 / 100.0,
+        (0..100).map(|_| mem_used(|| gen32b().1).1).sum::<usize>() as f64 / 100.0,
         (0..100)
             .map(|_| mem_used(|| genroaring().1).1)
             .sum::<usize>() as f64
@@ -139,9 +152,10 @@ fn bench_sets(density: f64, num_elements: usize) {
     );
 
     println!(
-        "{:>17}: {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns",
+        "{:>17}: {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns",
         ".contains(ran)",
         bench_gen_env(gen32, |(idx, set)| { set.contains(*idx) }).ns_per_iter,
+        bench_gen_env(gen32b, |(idx, set)| { set.contains(*idx) }).ns_per_iter,
         bench_gen_env(genroaring, |(idx, set)| { set.contains(*idx) }).ns_per_iter,
         bench_gen_env(genstd32, |(idx, set)| { set.contains(idx) }).ns_per_iter,
         bench_gen_env(gen, |(idx, set)| { set.contains(*idx) }).ns_per_iter,
@@ -150,9 +164,10 @@ fn bench_sets(density: f64, num_elements: usize) {
         bench_gen_env(gen_idset, |(idx, set)| { set.contains(*idx) }).ns_per_iter,
     );
     println!(
-        "{:>17}: {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns",
+        "{:>17}: {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns",
         ".remove(ran)",
         bench_gen_env(gen32, |(idx, set)| { set.remove(*idx) }).ns_per_iter,
+        bench_gen_env(gen32b, |(idx, set)| { set.remove(*idx) }).ns_per_iter,
         bench_gen_env(genroaring, |(idx, set)| { set.remove(*idx) }).ns_per_iter,
         bench_gen_env(genstd32, |(idx, set)| { set.remove(idx) }).ns_per_iter,
         bench_gen_env(gen, |(idx, set)| { set.remove(*idx) }).ns_per_iter,
@@ -161,9 +176,10 @@ fn bench_sets(density: f64, num_elements: usize) {
         bench_gen_env(gen_idset, |(idx, set)| { set.remove(*idx) }).ns_per_iter,
     );
     println!(
-        "{:>17}: {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns",
+        "{:>17}: {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns {:5.0}ns",
         ".insert(ran)",
         bench_gen_env(gen32, |(idx, set)| { set.insert(*idx) }).ns_per_iter,
+        bench_gen_env(gen32b, |(idx, set)| { set.insert(*idx) }).ns_per_iter,
         bench_gen_env(genroaring, |(idx, set)| { set.insert(*idx) }).ns_per_iter,
         bench_gen_env(genstd32, |(idx, set)| { set.insert(*idx) }).ns_per_iter,
         bench_gen_env(gen, |(idx, set)| { set.insert(*idx) }).ns_per_iter,
@@ -176,8 +192,8 @@ fn bench_sets(density: f64, num_elements: usize) {
 fn bench_collect(density: f64) {
     assert!(density <= 1.0);
     println!(
-        "\ncollect {:5}:{:>12} {:>13} {:>13} {:>13} {:>13} {:>13}",
-        density, "setu32", "seturoaring", "std32", "setu64", "set64", "std"
+        "\ncollect {:5}:{:>12} {:>13} {:>13} {:>13} {:>13} {:>13} {:>13}",
+        density, "setu32", "setu32b", "seturoaring", "std32", "setu64", "set64", "std"
     );
     for &sz in SIZES.iter() {
         let mut gen = move || {
@@ -206,6 +222,10 @@ fn bench_collect(density: f64) {
                     v.iter().cloned().collect::<tinyset::SetU32>().len()
                 })
                 .ns_per_iter,
+                bench_gen_env(&mut gen32, |v| {
+                    v.iter().cloned().collect::<tinyset::SetU32b>().len()
+                })
+                .ns_per_iter,
                 bench_gen_env(&mut gen32, |v| {
                     v.iter().cloned().collect::<roaring::RoaringBitmap>().len()
                 })
@@ -244,6 +264,13 @@ fn bench_collect(density: f64) {
                     })
                     .sum::<usize>() as f64
                     / nsize as f64,
+                (0..nsize)
+                    .map(|_| {
+                        let v = gen32();
+                        mem_used(|| v.iter().cloned().collect::<tinyset::SetU32b>()).1
+                    })
+                    .sum::<usize>() as f64
+                    / nsize as f64,
                 (0..nsize)
                     .map(|_| {
                         let v = gen32();
@@ -374,8 +401,8 @@ fn print_times(n: usize, times: &[f64]) {
 fn bench_fill_with_inserts(density: f64) {
     assert!(density <= 1.0);
     println!(
-        "\ninserts {:5}:{:>12} {:>13} {:>13} {:>13} {:>13} {:>13} {:>13}",
-        density, "setu32", "roaring", "std32", "setu64", "set64", "std", "id-set"
+        "\ninserts {:5}:{:>12} {:>13} {:>13} {:>13} {:>13} {:>13} {:>13} {:>13}",
+        density, "setu32", "setu32b", "roaring", "std32", "setu64", "set64", "std", "id-set"
     );
     for &sz in SIZES.iter() {
         let mut gen = move || {
@@ -417,6 +444,14 @@ fn bench_fill_with_inserts(density: f64) {
                     s.len()
                 })
                 .ns_per_iter,
+                bench_gen_env(&mut gen32, |v| {
+                    let mut s = tinyset::SetU32b::new();
+                    for x in v.iter().cloned() {
+                        s.insert(x);
+                    }
+                    s.len()
+                })
+                .ns_per_iter,
                 bench_gen_env(&mut gen32, |v| {
                     let mut s = roaring::RoaringBitmap::new();
                     for x in v.iter().cloned() {
@@ -484,6 +519,20 @@ fn bench_fill_with_inserts(density: f64) {
                     })
                     .sum::<usize>() as f64
                     / nsize as f64,
+                (0..nsize)
+                    .map(|_| {
+                        let v = gen32();
+                        mem_used(|| {
+                            let mut s = tinyset::SetU32b::new();
+                            for x in v.iter().cloned() {
+                                s.insert(x);
+                            }
+                            s
+                        })
+                        .1
+                    })
+                    .sum::<usize>() as f64
+                    / nsize as f64,
                 (0..nsize)
                     .map(|_| {
                         let v = gen32();
@@ -898,6 +947,87 @@ fn bench_sum(density: f64) {
     );
 }
 
+fn bench_dense_ops(density: f64) {
+    assert!(density <= 1.0);
+    println!(
+        "\ndense ops {:5}:{:>9} {:>13} {:>13} {:>13} {:>13}",
+        density, "union", "intersect", "difference", "eq", "sum"
+    );
+    for &sz in [100, 1000, 10000, 100000].iter() {
+        let gen = move || {
+            let mut rng = rand::thread_rng();
+            let mx = (sz as f64 / density) as u64 + 1;
+            let mut pair = (tinyset::SetU64::new(), tinyset::SetU64::new());
+            while pair.0.len() < sz {
+                pair.0.insert(rng.gen_range(0..mx));
+            }
+            while pair.1.len() < sz {
+                pair.1.insert(rng.gen_range(0..mx));
+            }
+            let (a, b) = pair;
+            (a.clone(), a, b)
+        };
+        print_times(
+            sz,
+            &[
+                bench_gen_env(gen, |(_, a, b)| (&*a | &*b).len()).ns_per_iter,
+                bench_gen_env(gen, |(_, a, b)| (&*a & &*b).len()).ns_per_iter,
+                bench_gen_env(gen, |(_, a, b)| (&*a - &*b).len()).ns_per_iter,
+                bench_gen_env(gen, |(c, a, _)| c == a).ns_per_iter,
+                bench_gen_env(gen, |(_, a, _)| a.iter().sum::<u64>()).ns_per_iter,
+            ],
+        );
+    }
+}
+
+/// Compares hash tables placing elements by their own value with those
+/// mixing in a key, for elements `elem(x)` where `x` is random.
+#[cfg(feature = "keyed_hashing")]
+fn bench_keyed(name: &str, elem: fn(u64) -> u64) {
+    println!(
+        "\nkeyed {:>9}:{:>9} {:>13} {:>13} {:>13} {:>13} {:>13}",
+        name, "contains", "keyed", "insert", "keyed", "collect", "keyed"
+    );
+    for &sz in [10, 100, 1000, 10000, 100000].iter() {
+        let mx = 4 * sz as u64;
+        let gen = move |keyed| {
+            move || {
+                tinyset::set_keyed_hashing(keyed);
+                let mut rng = rand::thread_rng();
+                let set: tinyset::SetU64 = (0..sz).map(|_| elem(rng.gen_range(0..mx))).collect();
+                (elem(rng.gen_range(0..mx)), set)
+            }
+        };
+        let gen_vec = move |keyed| {
+            move || {
+                tinyset::set_keyed_hashing(keyed);
+                let mut rng = rand::thread_rng();
+                (0..sz)
+                    .map(|_| elem(rng.gen_range(0..mx)))
+                    .collect::<Vec<_>>()
+            }
+        };
+        print_times(
+            sz,
+            &[
+                bench_gen_env(gen(false), |(x, s)| s.contains(*x)).ns_per_iter,
+                bench_gen_env(gen(true), |(x, s)| s.contains(*x)).ns_per_iter,
+                bench_gen_env(gen(false), |(x, s)| s.insert(*x)).ns_per_iter,
+                bench_gen_env(gen(true), |(x, s)| s.insert(*x)).ns_per_iter,
+                bench_gen_env(gen_vec(false), |v| {
+                    v.iter().cloned().collect::<tinyset::SetU64>()
+                })
+                .ns_per_iter,
+                bench_gen_env(gen_vec(true), |v| {
+                    v.iter().cloned().collect::<tinyset::SetU64>()
+                })
+                .ns_per_iter,
+            ],
+        );
+    }
+    tinyset::set_keyed_hashing(true);
+}
+
 fn bench_scaling(density: f64, min: usize) {
     assert!(density <= 1.0);
     let mut gen = move |num_elements| {
@@ -1003,10 +1133,20 @@ fn main() {
     bench_last(0.05);
     bench_last(0.8);
 
+    bench_dense_ops(0.05);
+    bench_dense_ops(0.5);
+    bench_dense_ops(0.99);
+
     bench_scaling(0.05, 8);
     bench_scaling(0.5, 8);
     bench_scaling(0.8, 8);
 
+    #[cfg(feature = "keyed_hashing")]
+    {
+        bench_keyed("heap", |x| x * 16);
+        bench_keyed("big", |x| x.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    }
+
     use tinyset::Fits64;
     println!(
         "i64<->u64: {}",
@@ -0,0 +1,251 @@
+//! Generators of random sets for property tests, with the `proptest` and
+//! `quickcheck` features.
+//!
+//! The elements are drawn from a few shapes, each of which leads to a
+//! different representation of the set: a few small numbers (stored on
+//! the stack), many numbers in a short range (a dense bitmap), evenly
+//! spaced numbers at a random offset (a hash table of bitmaps, with a
+//! number of bits that depends on the spacing), arbitrary numbers (a
+//! hash table of elements), and numbers right below `u64::MAX`.  The
+//! narrower set types truncate these elements, so they see the same
+//! shapes near `u32::MAX`.
+//!
+//! A [`Set64`] of integers likewise keeps the low bits of the encoding
+//! from [`Fits64::to_u64`], so that every shape is a valid set of the
+//! type.  A `Set64<char>` leaves out the elements that are not chars.
+//! These are the only element types with an `Arbitrary` set.
+
+use crate::{Fits64, Set64, SetU32, SetU32b, SetU64, SetUsize};
+
+/// Decodes `v` as a set of `T`, keeping the low `bits` of each element.
+///
+/// # Safety
+///
+/// Every number below `2^bits` must be the encoding of some `T`.
+#[cfg(any(feature = "proptest", feature = "quickcheck"))]
+unsafe fn truncated<T: Fits64>(v: Vec<u64>, bits: u32) -> Set64<T> {
+    let mask = u64::MAX >> (64 - bits);
+    Set64::from_encoded(v.into_iter().map(|x| x & mask).collect())
+}
+
+/// Decodes `v` as a set of chars, wrapping around above `char::MAX` and
+/// leaving out the surrogates.
+#[cfg(any(feature = "proptest", feature = "quickcheck"))]
+fn chars(v: Vec<u64>) -> Set64<char> {
+    let n = char::MAX as u64 + 1;
+    v.into_iter()
+        .filter_map(|x| char::from_u32((x % n) as u32))
+        .collect()
+}
+
+#[cfg(feature = "proptest")]
+mod proptest_impls {
+    use super::*;
+    use proptest::arbitrary::{any, Arbitrary};
+    use proptest::collection::vec;
+    use proptest::strategy::{BoxedStrategy, Strategy};
+
+    fn elements() -> impl Strategy<Value = Vec<u64>> {
+        proptest::prop_oneof![
+            vec(0u64..16, 0..4),
+            vec(0u64..1024, 0..600),
+            (1u64..1 << 16, any::<u32>(), vec(0u64..256, 1..300)).prop_map(|(step, start, v)| {
+                v.into_iter().map(|x| start as u64 + x * step).collect()
+            }),
+            vec(any::<u64>(), 1..100),
+            (vec(0u64..64, 1..50), vec(0u64..64, 0..4)).prop_map(|(high, low)| high
+                .into_iter()
+                .map(|x| u64::MAX - x)
+                .chain(low)
+                .collect()),
+        ]
+    }
+
+    macro_rules! arbitrary_set {
+        ($ty: ty, $item: ty) => {
+            impl Arbitrary for $ty {
+                type Parameters = ();
+                type Strategy = BoxedStrategy<$ty>;
+                fn arbitrary_with(_: ()) -> Self::Strategy {
+                    elements()
+                        .prop_map(|v| v.into_iter().map(|x| x as $item).collect())
+                        .boxed()
+                }
+            }
+        };
+    }
+
+    arbitrary_set!(SetU64, u64);
+    arbitrary_set!(SetU32, u32);
+    arbitrary_set!(SetU32b, u32);
+    arbitrary_set!(SetUsize, usize);
+
+    macro_rules! arbitrary_set64 {
+        ($($ty: ty),*) => {$(
+            impl Arbitrary for Set64<$ty> {
+                type Parameters = ();
+                type Strategy = BoxedStrategy<Set64<$ty>>;
+                fn arbitrary_with(_: ()) -> Self::Strategy {
+                    // Safety: the encodings of the integers of a width are
+                    // exactly the numbers of that many bits.
+                    elements()
+                        .prop_map(|v| unsafe { truncated(v, <$ty>::BITS) })
+                        .boxed()
+                }
+            }
+        )*};
+    }
+
+    arbitrary_set64!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+    impl Arbitrary for Set64<char> {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Set64<char>>;
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            elements().prop_map(chars).boxed()
+        }
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+mod quickcheck_impls {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+
+    fn below<G: Gen>(g: &mut G, n: u64) -> u64 {
+        g.next_u64() % n
+    }
+
+    fn elements<G: Gen>(g: &mut G) -> Vec<u64> {
+        match below(g, 5) {
+            0 => (0..below(g, 4)).map(|_| below(g, 16)).collect(),
+            1 => (0..below(g, 600)).map(|_| below(g, 1024)).collect(),
+            2 => {
+                let step = 1 + below(g, 1 << 16);
+                let start = below(g, 1 << 32);
+                (0..1 + below(g, 300))
+                    .map(|_| start + below(g, 256) * step)
+                    .collect()
+            }
+            3 => (0..1 + below(g, 100)).map(|_| g.next_u64()).collect(),
+            _ => {
+                let mut v: Vec<u64> = (0..1 + below(g, 50))
+                    .map(|_| u64::MAX - below(g, 64))
+                    .collect();
+                for _ in 0..below(g, 4) {
+                    v.push(below(g, 64));
+                }
+                v
+            }
+        }
+    }
+
+    macro_rules! arbitrary_set {
+        ($ty: ty, $item: ty) => {
+            impl Arbitrary for $ty {
+                fn arbitrary<G: Gen>(g: &mut G) -> Self {
+                    elements(g).into_iter().map(|x| x as $item).collect()
+                }
+                fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                    let v: Vec<$item> = self.iter_sorted().collect();
+                    Box::new(v.shrink().map(|v| v.into_iter().collect()))
+                }
+            }
+        };
+    }
+
+    arbitrary_set!(SetU64, u64);
+    arbitrary_set!(SetU32, u32);
+    arbitrary_set!(SetU32b, u32);
+    arbitrary_set!(SetUsize, usize);
+
+    macro_rules! arbitrary_set64 {
+        ($ty: ty, |$v: ident| $decode: expr) => {
+            impl Arbitrary for Set64<$ty> {
+                fn arbitrary<G: Gen>(g: &mut G) -> Self {
+                    let $v = elements(g);
+                    $decode
+                }
+                fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                    let v: Vec<$ty> = self.iter().collect();
+                    Box::new(v.shrink().map(|v| v.into_iter().collect()))
+                }
+            }
+        };
+        ($($ty: ty),*) => {$(
+            // Safety: the encodings of the integers of a width are exactly
+            // the numbers of that many bits.
+            arbitrary_set64!($ty, |v| unsafe { truncated(v, <$ty>::BITS) });
+        )*};
+    }
+
+    arbitrary_set64!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+    arbitrary_set64!(char, |v| chars(v));
+}
+
+/// Check that the generated sets include every representation, and
+/// several numbers of bits for the hash tables of bitmaps.
+#[cfg(test)]
+fn check_layouts(sets: impl Iterator<Item = SetU64>) {
+    use std::collections::BTreeSet;
+    let mut layouts = BTreeSet::new();
+    let mut near_max = false;
+    for s in sets {
+        layouts.insert(s.layout());
+        near_max |= s.contains(u64::MAX) || s.contains(u64::MAX - 1);
+    }
+    let kinds: BTreeSet<_> = layouts.iter().map(|l| l.0).collect();
+    assert_eq!(
+        kinds,
+        ["big", "dense", "empty", "heap", "stack"]
+            .iter()
+            .cloned()
+            .collect()
+    );
+    assert!(layouts.iter().filter(|l| l.0 == "heap").count() >= 3);
+    assert!(near_max);
+}
+
+#[cfg(all(test, feature = "proptest"))]
+#[test]
+fn proptest_layouts() {
+    use proptest::strategy::{Strategy, ValueTree};
+    let mut runner = proptest::test_runner::TestRunner::deterministic();
+    let strategy = proptest::arbitrary::any::<SetU64>();
+    check_layouts((0..1000).map(|_| strategy.new_tree(&mut runner).unwrap().current()));
+    let strategy = proptest::arbitrary::any::<Set64<i64>>();
+    check_layouts((0..1000).map(|_| strategy.new_tree(&mut runner).unwrap().current().0));
+}
+
+#[cfg(all(test, feature = "quickcheck"))]
+#[test]
+fn quickcheck_layouts() {
+    let mut g = quickcheck::StdThreadGen::new(100);
+    check_layouts((0..1000).map(|_| quickcheck::Arbitrary::arbitrary(&mut g)));
+    check_layouts((0..1000).map(|_| {
+        let s: Set64<i64> = quickcheck::Arbitrary::arbitrary(&mut g);
+        s.0
+    }));
+}
+
+#[cfg(all(test, feature = "proptest"))]
+proptest::proptest! {
+    #[test]
+    fn proptest_sets_are_valid(s: SetU64, t: SetU32, u: Set64<i16>, c: Set64<char>) {
+        crate::copyset::check_set::<SetU64>(&s.iter().collect::<Vec<_>>());
+        crate::copyset::check_set::<SetU32>(&t.iter().collect::<Vec<_>>());
+        proptest::prop_assert_eq!(u.clone(), u.iter().collect());
+        proptest::prop_assert_eq!(c.clone(), c.iter().collect());
+    }
+}
+
+#[cfg(all(test, feature = "quickcheck"))]
+quickcheck::quickcheck! {
+    fn quickcheck_sets_are_valid(s: SetU64, t: SetU32b, u: Set64<i16>, c: Set64<char>) -> bool {
+        s.len() == s.iter().count()
+            && t.len() == t.iter().count()
+            && u == u.iter().collect()
+            && c == c.iter().collect()
+            && quickcheck::Arbitrary::shrink(&s).all(|x| x.len() <= s.len())
+    }
+}
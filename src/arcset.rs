@@ -0,0 +1,619 @@
+//! Reference-counted sets with copy-on-write.
+//!
+//! Cloning a [`SetU64`] copies its heap storage, which is expensive for
+//! large sets.  An [`ArcSetU64`] shares that storage between clones, so
+//! cloning is O(1), and the first mutation of a shared set makes a
+//! private copy.  Small sets that need no heap storage are not shared
+//! at all, since they are as cheap to copy as a pointer.
+
+use crate::{Fits64, SetU64};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+#[derive(Clone)]
+enum Repr {
+    /// A set with no heap storage.
+    Inline(SetU64),
+    Shared(Arc<SetU64>),
+}
+
+/// A set of `u64` with O(1) clone.
+///
+/// An `ArcSetU64` dereferences to a [`SetU64`], so every method that does
+/// not modify the set is available.
+///
+/// ```
+/// use tinyset::ArcSetU64;
+///
+/// let a: ArcSetU64 = (0..10000).collect();
+/// let mut b = a.clone();
+/// assert!(ArcSetU64::ptr_eq(&a, &b));
+/// b.insert(10000);
+/// assert!(!ArcSetU64::ptr_eq(&a, &b));
+/// assert_eq!(a.len(), 10000);
+/// assert_eq!(b.len(), 10001);
+/// ```
+#[derive(Clone)]
+pub struct ArcSetU64(Repr);
+
+impl Default for ArcSetU64 {
+    fn default() -> Self {
+        ArcSetU64::new()
+    }
+}
+
+impl std::ops::Deref for ArcSetU64 {
+    type Target = SetU64;
+    #[inline]
+    fn deref(&self) -> &SetU64 {
+        match &self.0 {
+            Repr::Inline(s) => s,
+            Repr::Shared(s) => s,
+        }
+    }
+}
+
+impl From<SetU64> for ArcSetU64 {
+    fn from(s: SetU64) -> Self {
+        if s.capacity() == 0 {
+            ArcSetU64(Repr::Inline(s))
+        } else {
+            ArcSetU64(Repr::Shared(Arc::new(s)))
+        }
+    }
+}
+
+impl From<ArcSetU64> for SetU64 {
+    /// Unwraps the set, copying it only if it is shared.
+    fn from(s: ArcSetU64) -> Self {
+        match s.0 {
+            Repr::Inline(s) => s,
+            Repr::Shared(s) => Arc::try_unwrap(s).unwrap_or_else(|s| (*s).clone()),
+        }
+    }
+}
+
+impl ArcSetU64 {
+    /// An empty set
+    #[inline]
+    pub const fn new() -> Self {
+        ArcSetU64(Repr::Inline(SetU64::new()))
+    }
+    /// Create an empty set with capacity to hold the provided set.
+    pub fn with_capacity_of(other: &Self) -> Self {
+        SetU64::with_capacity_of(other).into()
+    }
+    /// Returns true if the two sets share their storage, in which case
+    /// they are certainly equal.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        match (&a.0, &b.0) {
+            (Repr::Shared(a), Repr::Shared(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+    /// Returns a mutable reference to the underlying set, first making a
+    /// copy of it if it is shared.
+    pub fn make_mut(&mut self) -> &mut SetU64 {
+        match &mut self.0 {
+            Repr::Inline(s) => s,
+            Repr::Shared(s) => Arc::make_mut(s),
+        }
+    }
+    /// Tally up how much memory is in use, including the set itself.
+    pub fn mem_used(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_bytes()
+    }
+    /// The number of bytes allocated on the heap for this set.  Storage
+    /// that is shared between clones is counted in full by each of them.
+    pub fn heap_bytes(&self) -> usize {
+        match &self.0 {
+            Repr::Inline(s) => s.heap_bytes(),
+            // The allocation of an `Arc` begins with two reference counts.
+            Repr::Shared(s) => 2 * std::mem::size_of::<usize>() + s.mem_used(),
+        }
+    }
+    #[inline]
+    fn modify<R>(&mut self, f: impl FnOnce(&mut SetU64) -> R) -> R {
+        match &mut self.0 {
+            Repr::Inline(s) => {
+                let r = f(s);
+                if s.capacity() > 0 {
+                    let s = std::mem::take(s);
+                    self.0 = Repr::Shared(Arc::new(s));
+                }
+                r
+            }
+            Repr::Shared(s) => f(Arc::make_mut(s)),
+        }
+    }
+    /// Insert and return true if it was not present.
+    #[inline]
+    pub fn insert(&mut self, e: u64) -> bool {
+        if self.contains(e) {
+            return false;
+        }
+        self.modify(|s| s.insert(e))
+    }
+    /// Remove and return true if it was present.
+    #[inline]
+    pub fn remove(&mut self, e: u64) -> bool {
+        if !self.contains(e) {
+            return false;
+        }
+        self.modify(|s| s.remove(e))
+    }
+    /// Clears the set, returning all elements in an iterator.
+    #[inline]
+    pub fn drain(&mut self) -> impl Iterator<Item = u64> + 'static {
+        SetU64::from(std::mem::take(self)).into_iter()
+    }
+    /// Inserts every value in `range`, returning how many were not
+    /// already present.
+    ///
+    /// See [`SetU64::insert_range`].
+    pub fn insert_range<R: std::ops::RangeBounds<u64>>(&mut self, range: R) -> usize {
+        let full = match crate::copyset::inclusive_bounds(&range, u64::MAX, |&x| x) {
+            // Only a range that is no longer than the set can be in it.
+            Some((lo, hi)) => hi - lo < self.len() as u64 && (lo..=hi).all(|x| self.contains(x)),
+            None => true,
+        };
+        if full {
+            return 0;
+        }
+        self.modify(|s| s.insert_range(range))
+    }
+    /// Removes every value in `range`, returning how many were present.
+    pub fn remove_range<R: std::ops::RangeBounds<u64>>(&mut self, range: R) -> usize {
+        if !self.holds_any(&range) {
+            return 0;
+        }
+        self.modify(|s| s.remove_range(range))
+    }
+    /// Removes every value in `range`, returning them in an iterator.
+    pub fn drain_range<R: std::ops::RangeBounds<u64>>(
+        &mut self,
+        range: R,
+    ) -> crate::setu64::IntoIter {
+        if !self.holds_any(&range) {
+            return SetU64::new().into_iter();
+        }
+        self.modify(|s| s.drain_range(range))
+    }
+    /// Returns true if any value in `range` is in the set, looking up
+    /// either each value or each element, whichever are fewer.
+    fn holds_any<R: std::ops::RangeBounds<u64>>(&self, range: &R) -> bool {
+        match crate::copyset::inclusive_bounds(range, u64::MAX, |&x| x) {
+            Some((lo, hi)) if hi - lo < self.len() as u64 => (lo..=hi).any(|x| self.contains(x)),
+            Some((lo, hi)) => self.iter().any(|x| lo <= x && x <= hi),
+            None => false,
+        }
+    }
+}
+
+use crate::copyset::{impl_set_methods, FastOps};
+impl_set_methods!(ArcSetU64, FastOps);
+
+impl FastOps for ArcSetU64 {
+    fn fast_eq(&self, other: &Self) -> Option<bool> {
+        if ArcSetU64::ptr_eq(self, other) {
+            return Some(true);
+        }
+        (**self).fast_eq(other)
+    }
+    fn fast_union(&self, other: &Self) -> Option<Self> {
+        (**self).fast_union(other).map(ArcSetU64::from)
+    }
+    fn fast_intersection(&self, other: &Self) -> Option<Self> {
+        (**self).fast_intersection(other).map(ArcSetU64::from)
+    }
+    fn fast_difference(&self, other: &Self) -> Option<Self> {
+        (**self).fast_difference(other).map(ArcSetU64::from)
+    }
+}
+
+impl std::iter::FromIterator<u64> for ArcSetU64 {
+    fn from_iter<T: IntoIterator<Item = u64>>(iter: T) -> Self {
+        iter.into_iter().collect::<SetU64>().into()
+    }
+}
+
+impl Extend<u64> for ArcSetU64 {
+    fn extend<T: IntoIterator<Item = u64>>(&mut self, iter: T) {
+        self.modify(|s| s.extend(iter))
+    }
+}
+
+impl IntoIterator for ArcSetU64 {
+    type Item = u64;
+    type IntoIter = crate::setu64::IntoIter;
+    fn into_iter(self) -> Self::IntoIter {
+        SetU64::from(self).into_iter()
+    }
+}
+
+/// A set of small `Copy` values with O(1) clone.
+///
+/// This is to [`Set64`](crate::Set64) what [`ArcSetU64`] is to
+/// [`SetU64`].
+#[derive(Clone)]
+pub struct ArcSet64<T: Fits64>(ArcSetU64, PhantomData<T>);
+
+impl<T: Fits64> Default for ArcSet64<T> {
+    fn default() -> Self {
+        ArcSet64(ArcSetU64::new(), PhantomData)
+    }
+}
+
+impl<T: Fits64> From<crate::Set64<T>> for ArcSet64<T> {
+    fn from(s: crate::Set64<T>) -> Self {
+        ArcSet64(s.0.into(), PhantomData)
+    }
+}
+
+impl<T: Fits64> From<ArcSet64<T>> for crate::Set64<T> {
+    fn from(s: ArcSet64<T>) -> Self {
+        let mut out = crate::Set64::new();
+        out.0 = s.0.into();
+        out
+    }
+}
+
+impl<T: Fits64> ArcSet64<T> {
+    /// Creates an empty set..
+    pub const fn new() -> Self {
+        ArcSet64(ArcSetU64::new(), PhantomData)
+    }
+    /// Creates an empty set with capacity to hold the provided set.
+    pub fn with_capacity_of(other: &Self) -> Self {
+        ArcSet64(ArcSetU64::with_capacity_of(&other.0), PhantomData)
+    }
+    /// Returns true if the two sets share their storage.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        ArcSetU64::ptr_eq(&a.0, &b.0)
+    }
+    /// Adds a value to the set.
+    ///
+    /// If the set did not have this value present, `true` is returned.
+    ///
+    /// If the set did have this value present, `false` is returned.
+    pub fn insert(&mut self, elem: T) -> bool {
+        self.0.insert(elem.to_u64())
+    }
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    /// Tally up how much memory is in use, including the set itself.
+    pub fn mem_used(&self) -> usize {
+        self.0.mem_used()
+    }
+    /// The number of bytes allocated on the heap for this set.  Storage
+    /// that is shared between clones is counted in full by each of them.
+    pub fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+    /// Returns true if the set contains a value.
+    pub fn contains<R: std::borrow::Borrow<T>>(&self, value: R) -> bool {
+        self.0.contains(value.borrow().to_u64())
+    }
+    /// Removes an element, and returns true if that element was present.
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.0.remove(value.to_u64())
+    }
+    /// Iterate
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = T> + 'a {
+        self.0.iter().map(|x| unsafe { T::from_u64(x) })
+    }
+    /// Drain
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + 'static {
+        self.0.drain().map(|x| unsafe { T::from_u64(x) })
+    }
+    /// Runs `f` on the elements as a [`Set64`](crate::Set64), copying
+    /// them first if they are shared.
+    fn modify<U>(&mut self, f: impl FnOnce(&mut crate::Set64<T>) -> U) -> U {
+        self.0.modify(|s| {
+            // Safety: the elements of the set were all made by `T::to_u64`.
+            let mut set = unsafe { crate::Set64::from_encoded(std::mem::take(s)) };
+            let r = f(&mut set);
+            *s = set.0;
+            r
+        })
+    }
+    /// Removes every value in `range`, returning how many were present.
+    ///
+    /// See [`Set64::remove_range`](crate::Set64::remove_range).
+    pub fn remove_range<R: std::ops::RangeBounds<T>>(&mut self, range: R) -> usize
+    where
+        T: PartialOrd,
+    {
+        if !self.iter().any(|x| range.contains(&x)) {
+            return 0;
+        }
+        self.modify(|s| s.remove_range(range))
+    }
+    /// Removes every value in `range`, returning them in an iterator.
+    ///
+    /// See [`Set64::drain_range`](crate::Set64::drain_range).
+    pub fn drain_range<R: std::ops::RangeBounds<T>>(
+        &mut self,
+        range: R,
+    ) -> crate::set64::IntoIter<T>
+    where
+        T: PartialOrd,
+    {
+        if !self.iter().any(|x| range.contains(&x)) {
+            return crate::Set64::new().into_iter();
+        }
+        self.modify(|s| s.drain_range(range))
+    }
+}
+
+macro_rules! impl_insert_range {
+    ($($ty: ty),*) => {$(
+impl ArcSet64<$ty> {
+    /// Adds every value in `range`, returning how many were not already
+    /// present.
+    ///
+    /// See [`Set64::insert_range`](crate::Set64::insert_range).
+    pub fn insert_range<R: std::ops::RangeBounds<$ty>>(&mut self, range: R) -> usize {
+        crate::Set64::<$ty>::insert_codes(range, |lo, hi| self.0.insert_range(lo..=hi))
+    }
+}
+    )*};
+}
+impl_insert_range!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, char);
+
+impl<T: Fits64> PartialEq for ArcSet64<T> {
+    fn eq(&self, other: &ArcSet64<T>) -> bool {
+        self.0 == other.0
+    }
+}
+impl<T: Fits64> Eq for ArcSet64<T> {}
+
+impl<T: Fits64 + Ord> PartialOrd for ArcSet64<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Fits64 + Ord> Ord for ArcSet64<T> {
+    /// Compares the elements in ascending order of `T`, just like a
+    /// `BTreeSet`.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        crate::set64::cmp_as::<T>(&self.0, &other.0)
+    }
+}
+
+impl<T: Fits64> std::hash::Hash for ArcSet64<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T: Fits64> std::iter::FromIterator<T> for ArcSet64<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        ArcSet64(iter.into_iter().map(|x| x.to_u64()).collect(), PhantomData)
+    }
+}
+
+impl<T: Fits64> Extend<T> for ArcSet64<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(|x| x.to_u64()))
+    }
+}
+
+impl<T: Fits64> IntoIterator for ArcSet64<T> {
+    type Item = T;
+    type IntoIter = crate::set64::IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        crate::Set64::from(self).into_iter()
+    }
+}
+
+impl<T: Fits64 + std::fmt::Debug> std::fmt::Debug for ArcSet64<T> {
+    /// Writes the elements themselves rather than their encodings.
+    ///
+    /// ```
+    /// use tinyset::ArcSet64;
+    ///
+    /// let a: ArcSet64<i8> = [-3].iter().cloned().collect();
+    /// assert_eq!(format!("{:?}", a), "{-3}");
+    /// ```
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Fits64> std::ops::BitOr<&ArcSet64<T>> for &ArcSet64<T> {
+    type Output = ArcSet64<T>;
+
+    /// Returns the union of `self` and `rhs` as a new `ArcSet64<T>`.
+    ///
+    /// ```
+    /// use tinyset::ArcSet64;
+    ///
+    /// let a: ArcSet64<i32> = (-3..1).collect();
+    /// let b: ArcSet64<i32> = (0..3).collect();
+    /// assert_eq!(&a | &b, (-3..3).collect());
+    /// ```
+    fn bitor(self, rhs: &ArcSet64<T>) -> ArcSet64<T> {
+        ArcSet64(&self.0 | &rhs.0, PhantomData)
+    }
+}
+
+impl<T: Fits64> std::ops::BitAnd<&ArcSet64<T>> for &ArcSet64<T> {
+    type Output = ArcSet64<T>;
+
+    /// Returns the intersection of `self` and `rhs` as a new `ArcSet64<T>`.
+    ///
+    /// ```
+    /// use tinyset::ArcSet64;
+    ///
+    /// let a: ArcSet64<i32> = (-3..1).collect();
+    /// let b: ArcSet64<i32> = (0..3).collect();
+    /// assert_eq!(&a & &b, [0].iter().cloned().collect());
+    /// ```
+    fn bitand(self, rhs: &ArcSet64<T>) -> ArcSet64<T> {
+        ArcSet64(&self.0 & &rhs.0, PhantomData)
+    }
+}
+
+impl<T: Fits64> std::ops::Sub<&ArcSet64<T>> for &ArcSet64<T> {
+    type Output = ArcSet64<T>;
+
+    /// Returns the difference of `self` and `rhs` as a new `ArcSet64<T>`.
+    ///
+    /// ```
+    /// use tinyset::ArcSet64;
+    ///
+    /// let a: ArcSet64<i32> = (-3..1).collect();
+    /// let b: ArcSet64<i32> = (0..3).collect();
+    /// assert_eq!(&a - &b, (-3..0).collect());
+    /// ```
+    fn sub(self, rhs: &ArcSet64<T>) -> ArcSet64<T> {
+        ArcSet64(&self.0 - &rhs.0, PhantomData)
+    }
+}
+
+#[cfg(test)]
+impl crate::copyset::CopySet for ArcSetU64 {
+    type Item = u64;
+    type Iter = crate::setu64::IntoIter;
+    fn ins(&mut self, e: u64) -> bool {
+        self.insert(e)
+    }
+    fn rem(&mut self, e: u64) -> bool {
+        self.remove(e)
+    }
+    fn con(&self, e: u64) -> bool {
+        self.contains(e)
+    }
+    fn vec(&self) -> Vec<u64> {
+        self.iter().collect()
+    }
+    fn ln(&self) -> usize {
+        self.len()
+    }
+    fn it(self) -> Self::Iter {
+        self.into_iter()
+    }
+}
+
+#[test]
+fn copy_on_write() {
+    let a: ArcSetU64 = (0..1000).map(|x| x * 3).collect();
+    let mut b = a.clone();
+    assert!(ArcSetU64::ptr_eq(&a, &b));
+    // Operations that change nothing do not copy.
+    assert!(!b.insert(3));
+    assert!(!b.remove(4));
+    assert!(ArcSetU64::ptr_eq(&a, &b));
+    assert!(b.remove(3));
+    assert!(!ArcSetU64::ptr_eq(&a, &b));
+    assert!(a.contains(3));
+    assert!(!b.contains(3));
+
+    let mut c = b.clone();
+    assert_eq!(c.drain().count(), 999);
+    assert!(c.is_empty());
+    assert_eq!(b.len(), 999);
+    assert_eq!(SetU64::from(b.clone()), *b);
+}
+
+#[test]
+fn small_sets_are_inline() {
+    let mut a = ArcSetU64::new();
+    a.insert(1);
+    let b = a.clone();
+    assert!(!ArcSetU64::ptr_eq(&a, &b));
+    assert_eq!(a, b);
+    a.extend(0..1000);
+    assert_eq!(a.len(), 1000);
+    assert_eq!(b.len(), 1);
+    assert!(matches!(a.0, Repr::Shared(_)));
+}
+
+#[test]
+fn arc_set64() {
+    let a: ArcSet64<i8> = (-100..100).collect();
+    let mut b = a.clone();
+    assert!(ArcSet64::ptr_eq(&a, &b));
+    b.remove(&-100);
+    assert_eq!(a.len(), 200);
+    assert_eq!(b.len(), 199);
+    assert!(a.contains(-100));
+    assert!(!b.contains(-100));
+    let s: crate::Set64<i8> = b.into();
+    assert_eq!(s.len(), 199);
+}
+
+#[test]
+fn ranges_copy_on_write() {
+    let a: ArcSetU64 = (0..1000).collect();
+    let mut b = a.clone();
+    assert_eq!(b.insert_range(500..2000), 1000);
+    assert_eq!(b.remove_range(..100), 100);
+    assert_eq!(b.drain_range(1500..).count(), 500);
+    assert_eq!(a, (0..1000).collect());
+    assert_eq!(b, (100..1500).collect());
+
+    let a: ArcSet64<i8> = (-100..100).collect();
+    let mut b = a.clone();
+    assert_eq!(b.remove_range(..0), 100);
+    assert_eq!(b.insert_range(100..=127), 28);
+    assert_eq!(b.drain_range(50..).count(), 78);
+    assert_eq!(a.len(), 200);
+    assert_eq!(b, (0..50).collect());
+
+    // Ranges that change nothing leave the storage shared.
+    let mut c = a.clone();
+    assert_eq!(c.remove_range(100..), 0);
+    assert_eq!(c.drain_range(..-100).count(), 0);
+    assert_eq!(c.insert_range(-50..=50), 0);
+    assert_eq!(c.insert_range(5..5), 0);
+    assert!(ArcSet64::ptr_eq(&a, &c));
+
+    let a: ArcSetU64 = (0..1000).map(|x| x * 2).collect();
+    let mut c = a.clone();
+    assert_eq!(c.insert_range(10..=10), 0);
+    assert_eq!(c.insert_range(7..7), 0);
+    assert_eq!(c.remove_range(2001..), 0);
+    assert_eq!(c.remove_range(11..=11), 0);
+    assert_eq!(c.drain_range(..0).count(), 0);
+    assert!(ArcSetU64::ptr_eq(&a, &c));
+    assert_eq!(c.insert_range(10..=11), 1);
+    assert!(!ArcSetU64::ptr_eq(&a, &c));
+}
+
+#[test]
+fn arc_set64_ops() {
+    let a: ArcSet64<i8> = (-100..50).collect();
+    let b: ArcSet64<i8> = (0..100).collect();
+    assert_eq!(&a | &b, (-100..100).collect());
+    assert_eq!(&a & &b, (0..50).collect());
+    assert_eq!(&a - &b, (-100..0).collect());
+    let mut v: Vec<i8> = a.clone().into_iter().collect();
+    v.sort();
+    assert_eq!(v, (-100..50).collect::<Vec<_>>());
+    let c = ArcSet64::<i8>::with_capacity_of(&a);
+    assert_eq!(c.len(), 0);
+    assert!(c.heap_bytes() >= a.heap_bytes());
+    let d: ArcSet64<i8> = [-1].iter().cloned().collect();
+    assert_eq!(format!("{:?}", d), "{-1}");
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn check_random_sets(slice in proptest::collection::vec(1u64..5, 1usize..10)) {
+        crate::copyset::check_set::<ArcSetU64>(&slice);
+    }
+    #[test]
+    fn check_medium_sets(slice in proptest::collection::vec(1u64..255, 1usize..100)) {
+        crate::copyset::check_set::<ArcSetU64>(&slice);
+    }
+    #[test]
+    fn check_big_sets(slice: Vec<u64>) {
+        crate::copyset::check_set::<ArcSetU64>(&slice);
+    }
+}
@@ -0,0 +1,235 @@
+//! Conversions to and from the standard collections, and between the
+//! set types of this crate.
+
+use crate::{Fits64, Set64, SetU32, SetU64, SetUsize};
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+
+macro_rules! std_conversions {
+    ($ty: ty, $item: ty) => {
+        impl<S: BuildHasher> From<HashSet<$item, S>> for $ty {
+            fn from(s: HashSet<$item, S>) -> Self {
+                s.into_iter().collect()
+            }
+        }
+        impl<S: BuildHasher> From<&HashSet<$item, S>> for $ty {
+            fn from(s: &HashSet<$item, S>) -> Self {
+                s.iter().cloned().collect()
+            }
+        }
+        impl From<BTreeSet<$item>> for $ty {
+            fn from(s: BTreeSet<$item>) -> Self {
+                s.into_iter().collect()
+            }
+        }
+        impl From<&BTreeSet<$item>> for $ty {
+            fn from(s: &BTreeSet<$item>) -> Self {
+                s.iter().cloned().collect()
+            }
+        }
+        impl<const N: usize> From<[$item; N]> for $ty {
+            fn from(a: [$item; N]) -> Self {
+                a.iter().cloned().collect()
+            }
+        }
+        impl From<&[$item]> for $ty {
+            fn from(a: &[$item]) -> Self {
+                a.iter().cloned().collect()
+            }
+        }
+        impl<S: BuildHasher + Default> From<$ty> for HashSet<$item, S> {
+            fn from(s: $ty) -> Self {
+                s.into_iter().collect()
+            }
+        }
+        impl<S: BuildHasher + Default> From<&$ty> for HashSet<$item, S> {
+            fn from(s: &$ty) -> Self {
+                s.iter().collect()
+            }
+        }
+        impl From<$ty> for BTreeSet<$item> {
+            fn from(s: $ty) -> Self {
+                s.iter_sorted().collect()
+            }
+        }
+        impl From<&$ty> for BTreeSet<$item> {
+            fn from(s: &$ty) -> Self {
+                s.iter_sorted().collect()
+            }
+        }
+    };
+}
+
+std_conversions!(SetU64, u64);
+std_conversions!(SetU32, u32);
+std_conversions!(SetUsize, usize);
+
+impl<T: Fits64, S: BuildHasher> From<HashSet<T, S>> for Set64<T> {
+    fn from(s: HashSet<T, S>) -> Self {
+        s.into_iter().collect()
+    }
+}
+impl<T: Fits64, S: BuildHasher> From<&HashSet<T, S>> for Set64<T> {
+    fn from(s: &HashSet<T, S>) -> Self {
+        s.iter().cloned().collect()
+    }
+}
+impl<T: Fits64> From<BTreeSet<T>> for Set64<T> {
+    fn from(s: BTreeSet<T>) -> Self {
+        s.into_iter().collect()
+    }
+}
+impl<T: Fits64> From<&BTreeSet<T>> for Set64<T> {
+    fn from(s: &BTreeSet<T>) -> Self {
+        s.iter().cloned().collect()
+    }
+}
+impl<T: Fits64, const N: usize> From<[T; N]> for Set64<T> {
+    fn from(a: [T; N]) -> Self {
+        a.iter().cloned().collect()
+    }
+}
+impl<T: Fits64> From<&[T]> for Set64<T> {
+    fn from(a: &[T]) -> Self {
+        a.iter().cloned().collect()
+    }
+}
+impl<T: Fits64 + Hash + Eq, S: BuildHasher + Default> From<Set64<T>> for HashSet<T, S> {
+    fn from(s: Set64<T>) -> Self {
+        s.into_iter().collect()
+    }
+}
+impl<T: Fits64 + Hash + Eq, S: BuildHasher + Default> From<&Set64<T>> for HashSet<T, S> {
+    fn from(s: &Set64<T>) -> Self {
+        s.iter().collect()
+    }
+}
+impl<T: Fits64 + Ord> From<Set64<T>> for BTreeSet<T> {
+    fn from(s: Set64<T>) -> Self {
+        s.into_iter().collect()
+    }
+}
+impl<T: Fits64 + Ord> From<&Set64<T>> for BTreeSet<T> {
+    fn from(s: &Set64<T>) -> Self {
+        s.iter().collect()
+    }
+}
+
+impl From<SetU32> for SetU64 {
+    /// Widens the set, copying dense and hash-table storage word for word.
+    fn from(s: SetU32) -> Self {
+        s.to_setu64()
+    }
+}
+impl From<&SetU32> for SetU64 {
+    fn from(s: &SetU32) -> Self {
+        s.to_setu64()
+    }
+}
+impl TryFrom<SetU64> for SetU32 {
+    /// The original set, if any element is too large for a `u32`.
+    type Error = SetU64;
+    fn try_from(s: SetU64) -> Result<Self, SetU64> {
+        SetU32::from_setu64(&s).ok_or(s)
+    }
+}
+
+impl From<SetUsize> for Set64<usize> {
+    #[cfg(target_pointer_width = "64")]
+    fn from(s: SetUsize) -> Self {
+        let mut out = Set64::new();
+        out.0 = s.0;
+        out
+    }
+    #[cfg(target_pointer_width = "32")]
+    fn from(s: SetUsize) -> Self {
+        let mut out = Set64::new();
+        out.0 = s.0.to_setu64();
+        out
+    }
+}
+impl From<Set64<usize>> for SetUsize {
+    #[cfg(target_pointer_width = "64")]
+    fn from(s: Set64<usize>) -> Self {
+        SetUsize(s.0)
+    }
+    #[cfg(target_pointer_width = "32")]
+    fn from(s: Set64<usize>) -> Self {
+        SetUsize(SetU32::from_setu64(&s.0).unwrap())
+    }
+}
+
+impl<T: Fits64> From<Set64<T>> for SetU64 {
+    /// The [`Fits64::to_u64`] encodings of the elements.
+    fn from(s: Set64<T>) -> Self {
+        s.0
+    }
+}
+impl From<SetU64> for Set64<u64> {
+    fn from(s: SetU64) -> Self {
+        let mut out = Set64::new();
+        out.0 = s;
+        out
+    }
+}
+
+#[test]
+fn std_round_trip() {
+    let v: Vec<u64> = (0..1000).map(|x| x * x).collect();
+    let h: HashSet<u64> = v.iter().cloned().collect();
+    let b: BTreeSet<u64> = v.iter().cloned().collect();
+    let s = SetU64::from(&v[..]);
+    assert_eq!(s, SetU64::from(&h));
+    assert_eq!(s, SetU64::from(b.clone()));
+    assert_eq!(HashSet::<u64>::from(&s), h);
+    assert_eq!(BTreeSet::from(s), b);
+    assert_eq!(SetU32::from([3, 1, 2]), (1..4).collect());
+    assert_eq!(SetUsize::from([3, 1, 2]), (1..4).collect());
+    let s: Set64<i8> = Set64::from([-1, 1, -1]);
+    assert_eq!(BTreeSet::from(&s), [-1, 1].iter().cloned().collect());
+    assert_eq!(Set64::from(HashSet::<i8>::from(s.clone())), s);
+}
+
+#[cfg(test)]
+fn check_widen(v: &[u32]) {
+    let narrow: SetU32 = v.iter().cloned().collect();
+    let wide = SetU64::from(&narrow);
+    assert_eq!(wide.len(), narrow.len());
+    assert_eq!(wide, v.iter().map(|&x| x as u64).collect());
+    let mut grown = wide.clone();
+    grown.insert(1 << 40);
+    grown.remove(1 << 40);
+    assert_eq!(grown, wide);
+    assert_eq!(SetU32::try_from(wide).unwrap(), narrow);
+}
+
+#[test]
+fn widen_and_narrow() {
+    check_widen(&[]);
+    check_widen(&[1, 2, 3]);
+    check_widen(&(0..10000).collect::<Vec<_>>());
+    check_widen(&(0..10000).map(|x| x * 1000).collect::<Vec<_>>());
+    check_widen(&[0, u32::MAX]);
+    let wide: SetU64 = [1, 1 << 40].iter().cloned().collect();
+    assert_eq!(SetU32::try_from(wide.clone()), Err(wide));
+
+    let s: SetUsize = (0..100).collect();
+    let t = Set64::<usize>::from(s.clone());
+    assert_eq!(t, (0..100).collect());
+    assert_eq!(SetUsize::from(t), s);
+    let t: Set64<u64> = SetU64::from(Set64::<u8>::from([7, 8])).into();
+    assert_eq!(t, Set64::from([7, 8]));
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn widen_matches_collect(v: Vec<u32>) {
+        check_widen(&v);
+    }
+    #[test]
+    fn widen_matches_collect_small(v in proptest::collection::vec(0u32..5000, 0usize..500)) {
+        check_widen(&v);
+    }
+}
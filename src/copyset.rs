@@ -10,13 +10,171 @@ pub trait CopySet: Default + Clone {
     fn it(self) -> Self::Iter;
 }
 
+/// Set operations that a type can perform wholesale for some layouts,
+/// without visiting each element.  Each returns `None` (or `false`) when
+/// the generic implementation should be used instead.
+pub(crate) trait FastOps: Sized {
+    fn fast_eq(&self, _other: &Self) -> Option<bool> {
+        None
+    }
+    fn fast_union(&self, _other: &Self) -> Option<Self> {
+        None
+    }
+    fn fast_intersection(&self, _other: &Self) -> Option<Self> {
+        None
+    }
+    fn fast_difference(&self, _other: &Self) -> Option<Self> {
+        None
+    }
+    fn fast_union_with(&mut self, _other: &Self) -> bool {
+        false
+    }
+    fn fast_difference_with(&mut self, _other: &Self) -> bool {
+        false
+    }
+}
+
+/// Hashes a summary of `elems` that does not depend on their order, so
+/// that equal sets hash equally however they are stored.
+pub(crate) fn hash_unordered<H: std::hash::Hasher>(
+    elems: impl Iterator<Item = u64>,
+    state: &mut H,
+) {
+    let mut len = 0usize;
+    let mut sum = 0u64;
+    for x in elems {
+        // The splitmix64 finalizer, so that sets which differ only in a
+        // few low bits still have quite different sums.
+        let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        sum = sum.wrapping_add(z ^ (z >> 31));
+        len += 1;
+    }
+    state.write_usize(len);
+    state.write_u64(sum);
+}
+
+/// Compares two sets as their sorted elements would compare, as a
+/// `BTreeSet` does, given each set's elements in any order and a test of
+/// membership in each.
+///
+/// Sorted sequences first differ at the smallest element that is in only
+/// one of the sets.  The set holding it comes first, unless the other set
+/// has nothing beyond it and so is a prefix.  Finding that element needs
+/// neither sorting nor allocation.
+pub(crate) fn cmp_unsorted<T, A, B>(
+    a: impl Fn() -> A,
+    b: impl Fn() -> B,
+    in_a: impl Fn(T) -> bool,
+    in_b: impl Fn(T) -> bool,
+) -> std::cmp::Ordering
+where
+    T: Ord + Copy,
+    A: Iterator<Item = T>,
+    B: Iterator<Item = T>,
+{
+    use std::cmp::Ordering;
+    let only_a = a().filter(|&e| !in_b(e)).min();
+    let only_b = b().filter(|&e| !in_a(e)).min();
+    let a_first = match (only_a, only_b) {
+        (None, None) => return Ordering::Equal,
+        (Some(d), None) => b().any(|x| x > d),
+        (Some(d), Some(e)) if d < e => b().any(|x| x > d),
+        (_, Some(e)) => !a().any(|x| x > e),
+    };
+    if a_first {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+/// The number of ranges that `Display` prints before giving a count of
+/// the remaining elements, unless a precision is given.
+pub(crate) const DISPLAY_RANGES: usize = 16;
+
+/// Writes `sorted` as `{1..=5, 9, 12..=40}`, merging consecutive
+/// elements into ranges, and stopping after `max_ranges` ranges with a
+/// count of the `len` elements that were left out.
+pub(crate) fn fmt_ranges(
+    f: &mut std::fmt::Formatter,
+    sorted: impl Iterator<Item = u64>,
+    len: usize,
+    max_ranges: usize,
+) -> std::fmt::Result {
+    let mut sorted = sorted.peekable();
+    let mut ranges = 0;
+    let mut written = 0;
+    f.write_str("{")?;
+    while let Some(lo) = sorted.next() {
+        if ranges > 0 {
+            f.write_str(", ")?;
+        }
+        if ranges == max_ranges {
+            write!(f, "... {} more", len - written)?;
+            break;
+        }
+        let mut hi = lo;
+        while hi < u64::MAX && sorted.peek() == Some(&(hi + 1)) {
+            hi += 1;
+            sorted.next();
+        }
+        match hi - lo {
+            0 => write!(f, "{}", lo)?,
+            1 => write!(f, "{}, {}", lo, hi)?,
+            _ => write!(f, "{}..={}", lo, hi)?,
+        }
+        ranges += 1;
+        written += (hi - lo) as usize + 1;
+    }
+    f.write_str("}")
+}
+
+/// Formats every element of a set with [`fmt_ranges`], for `Debug`,
+/// which should not hide elements from `assert_eq!` or `dbg!`.
+pub(crate) struct Ranges<'a, S>(pub(crate) &'a S);
+
+/// Turns `range` into inclusive bounds no larger than `max`, using `conv`
+/// to widen its endpoints, or `None` if it holds nothing.
+pub(crate) fn inclusive_bounds<T, R: std::ops::RangeBounds<T>>(
+    range: &R,
+    max: u64,
+    conv: impl Fn(&T) -> u64,
+) -> Option<(u64, u64)> {
+    use std::ops::Bound;
+    let lo = match range.start_bound() {
+        Bound::Included(x) => conv(x),
+        Bound::Excluded(x) => conv(x).checked_add(1)?,
+        Bound::Unbounded => 0,
+    };
+    let hi = match range.end_bound() {
+        Bound::Included(x) => conv(x),
+        Bound::Excluded(x) => conv(x).checked_sub(1)?,
+        Bound::Unbounded => max,
+    }
+    .min(max);
+    if lo <= hi {
+        Some((lo, hi))
+    } else {
+        None
+    }
+}
+
 macro_rules! impl_set_methods {
     ($ty: ty) => {
+impl crate::copyset::FastOps for $ty {}
+impl_set_methods!($ty, FastOps);
+    };
+    ($ty: ty, FastOps) => {
 impl PartialEq for $ty {
     fn eq(&self, other: &Self) -> bool {
         if self.len() != other.len() {
             return false;
         }
+        if let Some(eq) = crate::copyset::FastOps::fast_eq(self, other) {
+            return eq;
+        }
         for i in self.iter() {
             if !other.contains(i) {
                 return false;
@@ -27,10 +185,89 @@ impl PartialEq for $ty {
 }
 impl Eq for $ty {}
 
+impl PartialOrd for $ty {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for $ty {
+    /// Compares the elements in ascending order, just like a `BTreeSet`.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        crate::copyset::cmp_unsorted(
+            || self.iter(),
+            || other.iter(),
+            |x| self.contains(x),
+            |x| other.contains(x),
+        )
+    }
+}
+
+impl std::hash::Hash for $ty {
+    /// The hash does not depend on the order of the elements, so equal
+    /// sets hash equally regardless of their layout or capacity.
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        crate::copyset::hash_unordered(self.iter().map(|x| x as u64), state)
+    }
+}
+
+impl std::fmt::Display for $ty {
+    #[doc = concat!(
+        "Writes the elements in ascending order, with runs of consecutive
+elements as ranges.  Only the first 16 ranges are written
+(or as many as the precision) followed by a count of the rest.
+
+# Examples
+
+```
+let s: tinyset::",  stringify!($ty), " = [9, 1, 2, 3, 4, 5, 12, 13].iter().cloned().collect();
+assert_eq!(s.to_string(), \"{1..=5, 9, 12, 13}\");
+assert_eq!(format!(\"{:.2}\", s), \"{1..=5, 9, ... 2 more}\");
+```
+"
+    )]
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let max_ranges = f.precision().unwrap_or(crate::copyset::DISPLAY_RANGES);
+        crate::copyset::fmt_ranges(f, self.iter_sorted().map(|x| x as u64), self.len(), max_ranges)
+    }
+}
+
+impl std::fmt::Debug for crate::copyset::Ranges<'_, $ty> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        crate::copyset::fmt_ranges(f, self.0.iter_sorted().map(|x| x as u64), self.0.len(), usize::MAX)
+    }
+}
+
 impl std::fmt::Debug for $ty {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        write!(f, concat!(stringify!($ty), " {:?}"), self.iter().collect::<Vec<_>>())?;
-        Ok(())
+    #[doc = concat!(
+        "Writes the type followed by every element, with runs of consecutive
+elements as ranges as with `Display`, or with `{:#?}` also the way the set
+is stored: its format, the number of bits of each entry in its table, and
+its length and capacity.
+
+# Examples
+
+```
+let s: tinyset::",  stringify!($ty), " = (1..=5).collect();
+assert_eq!(format!(\"{:?}\", s), \"", stringify!($ty), " {1..=5}\");
+println!(\"{:#?}\", s);
+```
+"
+    )]
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if f.alternate() {
+            let (format, bits) = self.layout();
+            f.debug_struct(stringify!($ty))
+                .field("format", &format)
+                .field("bits", &bits)
+                .field("len", &self.len())
+                .field("capacity", &self.capacity())
+                .field("elements", &crate::copyset::Ranges(self))
+                .finish()
+        } else {
+            write!(f, concat!(stringify!($ty), " "))?;
+            std::fmt::Debug::fmt(&crate::copyset::Ranges(self), f)
+        }
     }
 }
 
@@ -50,6 +287,9 @@ assert_eq!(&a - &b, (1..3).collect());
 "
     )]
     fn sub(self, rhs: &$ty) -> $ty {
+        if let Some(s) = crate::copyset::FastOps::fast_difference(self, rhs) {
+            return s;
+        }
         let mut s = <$ty>::with_capacity_of(&self);
         for v in self.iter() {
             if !rhs.contains(v) {
@@ -76,6 +316,9 @@ assert_eq!(a - &b, (1..3).collect());
 "
     )]
     fn sub(mut self, rhs: &$ty) -> $ty {
+        if crate::copyset::FastOps::fast_difference_with(&mut self, rhs) {
+            return self;
+        }
         for v in rhs.iter() {
             self.remove(v);
         }
@@ -99,6 +342,9 @@ assert_eq!(&a | &b, (1..6).collect());
 "
     )]
     fn bitor(self, rhs: & $ty) -> $ty {
+        if let Some(s) = crate::copyset::FastOps::fast_union(self, rhs) {
+            return s;
+        }
         let mut s: $ty = if self.len() > rhs.len() {
             <$ty>::with_capacity_of(&self)
         } else {
@@ -130,6 +376,9 @@ assert_eq!(a | &b, (1..6).collect());
 "
     )]
     fn bitor(mut self, rhs: & $ty) -> $ty {
+        if crate::copyset::FastOps::fast_union_with(&mut self, rhs) {
+            return self;
+        }
         for x in rhs.iter() {
             self.insert(x);
         }
@@ -137,6 +386,40 @@ assert_eq!(a | &b, (1..6).collect());
     }
 }
 
+impl<'a, 'b> std::ops::BitAnd<&'b $ty> for &'a $ty {
+    type Output = $ty;
+    #[doc = concat!(
+        "Returns the intersection of `self` and `rhs` as a new `", stringify!($ty), "`.
+
+# Examples
+
+```
+let a: tinyset::",  stringify!($ty), " = (1..4).collect();
+let b: tinyset::",  stringify!($ty), " = (3..6).collect();
+
+assert_eq!(&a & &b, (3..4).collect());
+```
+"
+    )]
+    fn bitand(self, rhs: & $ty) -> $ty {
+        if let Some(s) = crate::copyset::FastOps::fast_intersection(self, rhs) {
+            return s;
+        }
+        let (small, big) = if self.len() < rhs.len() {
+            (self, rhs)
+        } else {
+            (rhs, self)
+        };
+        let mut s = <$ty>::with_capacity_of(small);
+        for x in small.iter() {
+            if big.contains(x) {
+                s.insert(x);
+            }
+        }
+        s
+    }
+}
+
 }
 }
 
@@ -263,3 +546,58 @@ pub fn check_set<T: CopySet>(elems: &[T::Item]) {
     }
     assert_eq!(s.ln(), 0);
 }
+
+#[test]
+fn display_ranges() {
+    use crate::{ArcSetU64, SetU32, SetU32b, SetU64, SetUsize};
+    let s: SetU64 = [9, 1, 2, 3, 4, 5, 12, 13, 40].iter().cloned().collect();
+    assert_eq!(s.to_string(), "{1..=5, 9, 12, 13, 40}");
+    assert_eq!(format!("{:?}", s), "SetU64 {1..=5, 9, 12, 13, 40}");
+    assert_eq!(format!("{:.2}", s), "{1..=5, 9, ... 3 more}");
+    assert_eq!(format!("{:.0}", s), "{... 9 more}");
+    assert_eq!(SetU64::new().to_string(), "{}");
+    let s: SetU64 = [u64::MAX, u64::MAX - 1, u64::MAX - 2, 0]
+        .iter()
+        .cloned()
+        .collect();
+    assert_eq!(
+        s.to_string(),
+        format!("{{0, {}..={}}}", u64::MAX - 2, u64::MAX)
+    );
+
+    let v: Vec<u32> = (0..1000).filter(|x| x % 10 != 0).collect();
+    let expected = format!("{{1..=9, 11..=19, ... {} more}}", v.len() - 18);
+    assert_eq!(
+        format!("{:.2}", v.iter().cloned().collect::<SetU32>()),
+        expected
+    );
+    assert_eq!(
+        format!("{:.2}", v.iter().cloned().collect::<SetU32b>()),
+        expected
+    );
+    assert_eq!(
+        format!("{:.2}", v.iter().map(|&x| x as usize).collect::<SetUsize>()),
+        expected
+    );
+    assert_eq!(
+        format!("{:.2}", v.iter().map(|&x| x as u64).collect::<ArcSetU64>()),
+        expected
+    );
+    let s: SetU64 = v.iter().map(|&x| x as u64).collect();
+    assert_eq!(s.to_string().matches("..=").count(), DISPLAY_RANGES);
+    assert_eq!(format!("{:.1000}", s).matches("..=").count(), 100);
+    // Debug never leaves elements out.
+    assert_eq!(format!("{:?}", s).matches("..=").count(), 100);
+    assert_eq!(format!("{:.2?}", s).matches("..=").count(), 100);
+    assert_eq!(format!("{:#?}", s).matches("..=").count(), 100);
+
+    let s: SetU64 = (0..100).map(|x| x * 1000).collect();
+    let (format, bits) = s.layout();
+    let alternate = format!("{:#?}", s);
+    assert!(alternate.starts_with("SetU64 {\n"));
+    assert!(alternate.contains(&format!("format: {:?},", format)));
+    assert!(alternate.contains(&format!("bits: {},", bits)));
+    assert!(alternate.contains("len: 100,"));
+    assert!(alternate.contains(&format!("capacity: {},", s.capacity())));
+    assert!(alternate.contains("elements: {0, 1000, 2000,"));
+}
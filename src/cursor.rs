@@ -0,0 +1,284 @@
+//! The seekable sorted [`setu64::Cursor`](crate::setu64::Cursor) and
+//! [`setu32::Cursor`](crate::setu32::Cursor), which differ only in the
+//! type of their elements.
+
+/// The index of the first nonzero word of `a` at or after `from`, or
+/// `a.len()` if there is none.
+pub(crate) fn next_nonzero_u32(a: &[u32], from: usize) -> usize {
+    a.get(from..)
+        .and_then(|rest| rest.iter().position(|&w| w != 0))
+        .map_or(a.len(), |i| from + i)
+}
+
+/// Defines `cursor`, `iter_from` and the `Cursor` type for `$set`, whose
+/// elements are `$t`.  The module using it must provide `Internal`,
+/// `Tiny`, `mask` and the `$unsplit` function that rebuilds an element
+/// from a key, an offset and the bits of the hash table.
+macro_rules! impl_cursor {
+    ($set: ident, $t: ident, $unsplit: ident, $next_nonzero: path) => {
+impl $set {
+    /// A [`Cursor`] at the smallest element of the set.
+    ///
+    /// If the set is stored as a hash table, this copies and sorts its
+    /// entries, taking O(n log n) time and O(n) memory, so seek an
+    /// existing cursor rather than creating a new one for each lookup.
+    #[inline]
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor::new(self)
+    }
+    #[doc = concat!(
+        "A [`Cursor`] at the smallest element that is at least `x`.
+
+This costs as much as [`", stringify!($set), "::cursor`].
+
+```
+use tinyset::", stringify!($set), ";
+
+let a: ", stringify!($set), " = (0..100).map(|x| x * 10).collect();
+assert_eq!(a.iter_from(55).take(3).collect::<Vec<_>>(), vec![60, 70, 80]);
+```
+"
+    )]
+    #[inline]
+    pub fn iter_from(&self, x: $t) -> Cursor<'_> {
+        let mut c = self.cursor();
+        c.seek(x);
+        c
+    }
+}
+
+#[doc = concat!(
+    "An iterator over a [`", stringify!($set), "`] in ascending order, which can skip
+ahead to a given value.
+
+Seeking skips whole words of a dense bitmap and whole buckets of a
+hash table, which makes a cursor suitable for galloping
+intersections and merge joins.
+
+Creating a cursor on a set stored as a hash table copies and sorts
+its entries, taking O(n log n) time and O(n) memory.  Only seeking
+and stepping are cheap afterwards, so a cursor pays off when it is
+reused for many lookups.
+
+```
+use tinyset::", stringify!($set), ";
+
+let a: ", stringify!($set), " = (0..1000).map(|x| x * 3).collect();
+let b: ", stringify!($set), " = (0..1000).map(|x| x * 7).collect();
+// Intersect by leapfrogging each cursor to the other's position.
+let (mut ca, mut cb) = (a.cursor(), b.cursor());
+let mut both = Vec::new();
+while let (Some(x), Some(y)) = (ca.peek(), cb.peek()) {
+    if x == y {
+        both.push(x);
+        ca.next();
+    } else if x < y {
+        ca.seek(y);
+    } else {
+        cb.seek(x);
+    }
+}
+assert_eq!(both, (0..143).map(|x| x * 21).collect::<Vec<_>>());
+```
+"
+)]
+#[derive(Debug, Clone)]
+pub struct Cursor<'a>(Kind<'a>);
+
+#[derive(Debug, Clone)]
+enum Kind<'a> {
+    Stack(Tiny),
+    /// Nothing below `pos` remains.
+    Dense {
+        a: &'a [$t],
+        pos: u64,
+    },
+    /// The sorted nonzero words of a hash table, the remaining bitmap of
+    /// bucket `key`, and the index of the next word.
+    Heap {
+        bits: $t,
+        words: Vec<$t>,
+        next: usize,
+        key: $t,
+        bitmap: $t,
+    },
+    /// Sorted elements, and the index of the next one.
+    Big {
+        v: Vec<$t>,
+        next: usize,
+    },
+}
+
+impl<'a> Cursor<'a> {
+    fn new(set: &'a $set) -> Self {
+        Cursor(match set.internal() {
+            Internal::Empty => Kind::Big {
+                v: Vec::new(),
+                next: 0,
+            },
+            Internal::Stack(t) => Kind::Stack(t),
+            Internal::Dense { a, .. } => Kind::Dense { a, pos: 0 },
+            Internal::Heap { s, a } => {
+                // The key is in the high bits, so sorting the words sorts
+                // the buckets.
+                let mut words: Vec<$t> = a.iter().cloned().filter(|&x| x != 0).collect();
+                words.sort_unstable();
+                Kind::Heap {
+                    bits: s.bits,
+                    words,
+                    next: 0,
+                    key: 0,
+                    bitmap: 0,
+                }
+            }
+            Internal::Big { s, a } => {
+                let mut v: Vec<$t> = a
+                    .iter()
+                    .cloned()
+                    .filter(|&x| x != 0)
+                    .map(|x| if x == s.bits { 0 } else { x })
+                    .collect();
+                v.sort_unstable();
+                Kind::Big { v, next: 0 }
+            }
+        })
+    }
+
+    /// The next element, without advancing.
+    pub fn peek(&mut self) -> Option<$t> {
+        const WORD_BITS: u64 = $t::BITS as u64;
+        match &mut self.0 {
+            Kind::Stack(t) => {
+                let mut t = *t;
+                t.next()
+            }
+            Kind::Dense { a, pos } => {
+                let mut i = (*pos / WORD_BITS) as usize;
+                let mut word = a.get(i)? & (!0 << (*pos % WORD_BITS));
+                if word == 0 {
+                    i = $next_nonzero(a, i + 1);
+                    word = *a.get(i)?;
+                }
+                *pos = i as u64 * WORD_BITS + word.trailing_zeros() as u64;
+                Some(*pos as $t)
+            }
+            Kind::Heap {
+                bits,
+                words,
+                next,
+                key,
+                bitmap,
+            } => {
+                while *bitmap == 0 {
+                    let w = *words.get(*next)?;
+                    *next += 1;
+                    *key = w >> *bits;
+                    *bitmap = w & mask(*bits as usize);
+                }
+                Some($unsplit(*key, bitmap.trailing_zeros() as $t, *bits))
+            }
+            Kind::Big { v, next } => v.get(*next).cloned(),
+        }
+    }
+
+    /// Advances past every element less than `x`, returning the first
+    /// element that remains.
+    pub fn seek(&mut self, x: $t) -> Option<$t> {
+        match &mut self.0 {
+            Kind::Stack(t) => {
+                let mut rest = *t;
+                while rest.next().map(|e| e < x).unwrap_or(false) {
+                    *t = rest;
+                }
+            }
+            Kind::Dense { pos, .. } => *pos = (*pos).max(x as u64),
+            Kind::Heap {
+                bits,
+                words,
+                next,
+                key,
+                bitmap,
+            } => {
+                let (target, offset) = (x / *bits, x % *bits);
+                if *bitmap != 0 && *key < target {
+                    *bitmap = 0;
+                }
+                if *bitmap == 0 {
+                    let b = *bits;
+                    *next += words[*next..].partition_point(|&w| w >> b < target);
+                    if let Some(&w) = words.get(*next) {
+                        *next += 1;
+                        *key = w >> b;
+                        *bitmap = w & mask(b as usize);
+                    }
+                }
+                if *key == target {
+                    *bitmap &= !0 << offset;
+                }
+            }
+            Kind::Big { v, next } => *next += v[*next..].partition_point(|&e| e < x),
+        }
+        self.peek()
+    }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = $t;
+    #[inline]
+    fn next(&mut self) -> Option<$t> {
+        let e = self.peek()?;
+        match &mut self.0 {
+            Kind::Stack(t) => {
+                t.next();
+            }
+            Kind::Dense { pos, .. } => *pos = e as u64 + 1,
+            Kind::Heap { bitmap, .. } => *bitmap &= *bitmap - 1,
+            Kind::Big { next, .. } => *next += 1,
+        }
+        Some(e)
+    }
+}
+
+#[cfg(test)]
+fn check_cursor(elems: &[$t], seeks: &[$t]) {
+    use std::collections::BTreeSet;
+    let mut rest: BTreeSet<$t> = elems.iter().cloned().collect();
+    let set: $set = elems.iter().cloned().collect();
+    assert_eq!(
+        set.cursor().collect::<Vec<_>>(),
+        rest.iter().cloned().collect::<Vec<_>>()
+    );
+    let mut c = set.cursor();
+    for &x in seeks {
+        assert_eq!(
+            set.iter_from(x).next(),
+            set.iter().filter(|&e| e >= x).min()
+        );
+        rest = rest.split_off(&x);
+        let want = rest.iter().next().cloned();
+        assert_eq!(c.seek(x), want);
+        assert_eq!(c.peek(), want);
+        assert_eq!(c.next(), want);
+        if let Some(w) = want {
+            rest.remove(&w);
+        }
+    }
+    assert_eq!(c.collect::<Vec<_>>(), rest.into_iter().collect::<Vec<_>>());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn cursor_matches_btreeset(elems: Vec<$t>, seeks: Vec<$t>) {
+        check_cursor(&elems, &seeks);
+    }
+    #[test]
+    fn cursor_matches_btreeset_small(elems in proptest::collection::vec(0..5000 as $t, 0usize..500),
+                                     seeks in proptest::collection::vec(0..5000 as $t, 0usize..50)) {
+        check_cursor(&elems, &seeks);
+    }
+}
+    };
+}
+
+pub(crate) use impl_cursor;
@@ -0,0 +1,342 @@
+//! Kernels for whole dense bitmaps.
+//!
+//! Each kernel has a portable scalar version, plus versions using AVX2 on
+//! x86_64 and NEON on aarch64 that are chosen at runtime when the CPU
+//! supports them.  AVX-512 is not used, since its intrinsics need a newer
+//! compiler than our minimum supported Rust version.
+//!
+//! The combining kernels overwrite `dst` with the result, and return the
+//! number of bits set in it, which saves a second pass to recompute the
+//! length of the set.  They require `dst` and `src` to have the same
+//! length.
+
+/// Calls the fastest available version of a kernel.
+macro_rules! dispatch {
+    ($name:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        {
+            if std::is_x86_feature_detected!("avx2") {
+                return unsafe { avx2::$name($($arg),*) };
+            }
+            if std::is_x86_feature_detected!("popcnt") {
+                return unsafe { popcnt::$name($($arg),*) };
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                return unsafe { neon::$name($($arg),*) };
+            }
+        }
+        scalar::$name($($arg),*)
+    }};
+}
+
+/// The number of bits set.
+pub(crate) fn popcount(a: &[u64]) -> usize {
+    dispatch!(popcount(a))
+}
+
+/// `dst |= src`, returning the number of bits set in `dst`.
+pub(crate) fn or_into(dst: &mut [u64], src: &[u64]) -> usize {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(or_into(dst, src))
+}
+
+/// `dst &= src`, returning the number of bits set in `dst`.
+pub(crate) fn and_into(dst: &mut [u64], src: &[u64]) -> usize {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(and_into(dst, src))
+}
+
+/// `dst &= !src`, returning the number of bits set in `dst`.
+pub(crate) fn andnot_into(dst: &mut [u64], src: &[u64]) -> usize {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(andnot_into(dst, src))
+}
+
+/// The index of the first non-zero word at or after `from`, or `a.len()`
+/// if there is none.  This lets iteration skip empty stretches of a
+/// bitmap quickly.
+pub(crate) fn next_nonzero(a: &[u64], from: usize) -> usize {
+    if from >= a.len() {
+        return a.len();
+    }
+    dispatch!(next_nonzero(a, from))
+}
+
+mod scalar {
+    #[inline(always)]
+    pub(super) fn popcount(a: &[u64]) -> usize {
+        a.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    #[inline(always)]
+    pub(super) fn or_into(dst: &mut [u64], src: &[u64]) -> usize {
+        let mut n = 0;
+        for (d, s) in dst.iter_mut().zip(src.iter()) {
+            *d |= *s;
+            n += d.count_ones() as usize;
+        }
+        n
+    }
+    #[inline(always)]
+    pub(super) fn and_into(dst: &mut [u64], src: &[u64]) -> usize {
+        let mut n = 0;
+        for (d, s) in dst.iter_mut().zip(src.iter()) {
+            *d &= *s;
+            n += d.count_ones() as usize;
+        }
+        n
+    }
+    #[inline(always)]
+    pub(super) fn andnot_into(dst: &mut [u64], src: &[u64]) -> usize {
+        let mut n = 0;
+        for (d, s) in dst.iter_mut().zip(src.iter()) {
+            *d &= !*s;
+            n += d.count_ones() as usize;
+        }
+        n
+    }
+    #[inline(always)]
+    pub(super) fn next_nonzero(a: &[u64], from: usize) -> usize {
+        a[from..]
+            .iter()
+            .position(|&w| w != 0)
+            .map_or(a.len(), |i| from + i)
+    }
+}
+
+/// The scalar kernels, compiled to use the `popcnt` instruction.
+#[cfg(target_arch = "x86_64")]
+mod popcnt {
+    use super::scalar;
+
+    #[target_feature(enable = "popcnt")]
+    pub(super) unsafe fn popcount(a: &[u64]) -> usize {
+        scalar::popcount(a)
+    }
+    #[target_feature(enable = "popcnt")]
+    pub(super) unsafe fn or_into(dst: &mut [u64], src: &[u64]) -> usize {
+        scalar::or_into(dst, src)
+    }
+    #[target_feature(enable = "popcnt")]
+    pub(super) unsafe fn and_into(dst: &mut [u64], src: &[u64]) -> usize {
+        scalar::and_into(dst, src)
+    }
+    #[target_feature(enable = "popcnt")]
+    pub(super) unsafe fn andnot_into(dst: &mut [u64], src: &[u64]) -> usize {
+        scalar::andnot_into(dst, src)
+    }
+    #[target_feature(enable = "popcnt")]
+    pub(super) unsafe fn next_nonzero(a: &[u64], from: usize) -> usize {
+        scalar::next_nonzero(a, from)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::scalar;
+    use std::arch::x86_64::*;
+
+    /// Counts the bits in each 64-bit lane, by looking up each nibble in a
+    /// table of bit counts.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn lane_popcount(v: __m256i) -> __m256i {
+        let table = _mm256_setr_epi8(
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2,
+            3, 3, 4,
+        );
+        let nibble = _mm256_set1_epi8(0x0f);
+        let lo = _mm256_and_si256(v, nibble);
+        let hi = _mm256_and_si256(_mm256_srli_epi16(v, 4), nibble);
+        let bytes = _mm256_add_epi8(
+            _mm256_shuffle_epi8(table, lo),
+            _mm256_shuffle_epi8(table, hi),
+        );
+        _mm256_sad_epu8(bytes, _mm256_setzero_si256())
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn sum_lanes(v: __m256i) -> usize {
+        let mut lanes = [0u64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, v);
+        lanes.iter().sum::<u64>() as usize
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn popcount(a: &[u64]) -> usize {
+        let chunks = a.chunks_exact(4);
+        let rest = chunks.remainder();
+        let mut acc = _mm256_setzero_si256();
+        for c in chunks {
+            let v = _mm256_loadu_si256(c.as_ptr() as *const __m256i);
+            acc = _mm256_add_epi64(acc, lane_popcount(v));
+        }
+        sum_lanes(acc) + scalar::popcount(rest)
+    }
+
+    macro_rules! combine {
+        ($name:ident, $op:ident) => {
+            #[target_feature(enable = "avx2")]
+            pub(super) unsafe fn $name(dst: &mut [u64], src: &[u64]) -> usize {
+                let n = dst.len() / 4 * 4;
+                let mut acc = _mm256_setzero_si256();
+                for i in (0..n).step_by(4) {
+                    let d = dst.as_mut_ptr().add(i) as *mut __m256i;
+                    let s = src.as_ptr().add(i) as *const __m256i;
+                    let v = $op(_mm256_loadu_si256(d), _mm256_loadu_si256(s));
+                    _mm256_storeu_si256(d, v);
+                    acc = _mm256_add_epi64(acc, lane_popcount(v));
+                }
+                sum_lanes(acc) + scalar::$name(&mut dst[n..], &src[n..])
+            }
+        };
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn andnot(d: __m256i, s: __m256i) -> __m256i {
+        // Note that _mm256_andnot_si256 negates its first argument.
+        _mm256_andnot_si256(s, d)
+    }
+
+    combine!(or_into, _mm256_or_si256);
+    combine!(and_into, _mm256_and_si256);
+    combine!(andnot_into, andnot);
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn next_nonzero(a: &[u64], from: usize) -> usize {
+        let mut i = from;
+        while i + 4 <= a.len() {
+            let v = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+            if _mm256_testz_si256(v, v) == 0 {
+                break;
+            }
+            i += 4;
+        }
+        scalar::next_nonzero(a, i)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::scalar;
+    use std::arch::aarch64::*;
+
+    /// Counts the bits in each 64-bit lane.
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn lane_popcount(v: uint64x2_t) -> uint64x2_t {
+        vpaddlq_u32(vpaddlq_u16(vpaddlq_u8(vcntq_u8(vreinterpretq_u8_u64(v)))))
+    }
+
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn sum_lanes(v: uint64x2_t) -> usize {
+        (vgetq_lane_u64(v, 0) + vgetq_lane_u64(v, 1)) as usize
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn popcount(a: &[u64]) -> usize {
+        let chunks = a.chunks_exact(2);
+        let rest = chunks.remainder();
+        let mut acc = vdupq_n_u64(0);
+        for c in chunks {
+            acc = vaddq_u64(acc, lane_popcount(vld1q_u64(c.as_ptr())));
+        }
+        sum_lanes(acc) + scalar::popcount(rest)
+    }
+
+    macro_rules! combine {
+        ($name:ident, $op:ident) => {
+            #[target_feature(enable = "neon")]
+            pub(super) unsafe fn $name(dst: &mut [u64], src: &[u64]) -> usize {
+                let n = dst.len() / 2 * 2;
+                let mut acc = vdupq_n_u64(0);
+                for i in (0..n).step_by(2) {
+                    let d = dst.as_mut_ptr().add(i);
+                    let v = $op(vld1q_u64(d), vld1q_u64(src.as_ptr().add(i)));
+                    vst1q_u64(d, v);
+                    acc = vaddq_u64(acc, lane_popcount(v));
+                }
+                sum_lanes(acc) + scalar::$name(&mut dst[n..], &src[n..])
+            }
+        };
+    }
+
+    // vbicq_u64(a, b) is a & !b.
+    combine!(or_into, vorrq_u64);
+    combine!(and_into, vandq_u64);
+    combine!(andnot_into, vbicq_u64);
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn next_nonzero(a: &[u64], from: usize) -> usize {
+        let mut i = from;
+        while i + 2 <= a.len() {
+            let v = vld1q_u64(a.as_ptr().add(i));
+            if vmaxvq_u32(vreinterpretq_u32_u64(v)) != 0 {
+                break;
+            }
+            i += 2;
+        }
+        scalar::next_nonzero(a, i)
+    }
+}
+
+#[cfg(test)]
+fn check_kernels(a: &[u64], b: &[u64]) {
+    let n = a.len().min(b.len());
+    let (a, b) = (&a[..n], &b[..n]);
+    let count = |v: &[u64]| v.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+    assert_eq!(popcount(a), count(a));
+    assert_eq!(scalar::popcount(a), count(a));
+    type Kernel = fn(&mut [u64], &[u64]) -> usize;
+    /// A kernel and the operation it applies to each pair of words.
+    type KernelCase = (Kernel, fn(u64, u64) -> u64);
+    let kernels: [KernelCase; 6] = [
+        (or_into, |x, y| x | y),
+        (and_into, |x, y| x & y),
+        (andnot_into, |x, y| x & !y),
+        (scalar::or_into, |x, y| x | y),
+        (scalar::and_into, |x, y| x & y),
+        (scalar::andnot_into, |x, y| x & !y),
+    ];
+    for (kernel, op) in kernels.iter() {
+        let expected: Vec<u64> = a.iter().zip(b).map(|(&x, &y)| op(x, y)).collect();
+        let mut dst = a.to_vec();
+        assert_eq!(kernel(&mut dst, b), count(&expected));
+        assert_eq!(dst, expected);
+    }
+    for from in 0..=n + 1 {
+        let expected = (from..n).find(|&i| a[i] != 0).unwrap_or(n);
+        assert_eq!(next_nonzero(a, from), expected);
+        if from < n {
+            assert_eq!(scalar::next_nonzero(a, from), expected);
+        }
+    }
+}
+
+#[test]
+fn kernels() {
+    check_kernels(&[], &[]);
+    check_kernels(&[u64::MAX; 9], &[0x5555_5555_5555_5555; 9]);
+    let mut sparse = vec![0; 37];
+    sparse[5] = 1;
+    sparse[36] = 1 << 63;
+    check_kernels(&sparse, &[u64::MAX; 37]);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn kernels_match_scalar(a: Vec<u64>, b: Vec<u64>) {
+        check_kernels(&a, &b);
+    }
+    #[test]
+    fn kernels_on_sparse_words(a in proptest::collection::vec(proptest::sample::select(vec![0, 1, u64::MAX]), 0..50),
+                               b in proptest::collection::vec(proptest::sample::select(vec![0, 1, u64::MAX]), 0..50)) {
+        check_kernels(&a, &b);
+    }
+}
@@ -24,6 +24,21 @@
 //! 4. [`SetUsize`] holds `usize` items, and uses either [SetU64] or
 //! [SetU32] internally.
 //!
+//! [`SetU32b`] is an alternative implementation of [`SetU32`] with a
+//! different hash table layout, which may be faster for some uses.
+//!
+//! [`ArcSetU64`] and [`ArcSet64`] are variants of [`SetU64`] and
+//! [`Set64`] that share their storage between clones, copying it only
+//! when a shared set is modified.
+//!
+//! [`AtomicSetU64`] can be inserted into from several threads at once.
+//!
+//! The [`TinySet`] trait is implemented by each of these sets (and by
+//! `HashSet` and `BTreeSet`), for code that is generic over the set type.
+//! With the `proptest` or `quickcheck` feature, the sets implement the
+//! `Arbitrary` trait of that crate, generating sets in each of their
+//! internal representations.
+//!
 //! All of these set types will do no heap allocation for small sets of
 //! small elements.  Small sets occupy the same space as a single
 //! pointer, typically 64 bits.  In these 64 bits (or 32 bits), you can
@@ -52,19 +67,43 @@
 
 #![deny(missing_docs)]
 
+#[cfg(any(feature = "proptest", feature = "quickcheck"))]
+mod arbitrary;
+mod convert;
+mod kernels;
+mod memory;
+pub use memory::HeapSize;
 mod rand;
+#[cfg(feature = "keyed_hashing")]
+pub use crate::rand::set_keyed_hashing;
+#[cfg(feature = "rand")]
+pub use crate::rand::RandSource;
+pub use crate::rand::{set_global_seed, set_random_source, set_seed, OsSeeded, RandomSource};
+mod roaring;
+#[cfg(feature = "serde")]
+pub mod serde;
 mod sets;
+pub use sets::TinySet;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub mod setusize;
 pub use setusize::SetUsize;
 
 pub mod setu32;
-pub use setu32::SetU32;
+pub use setu32::{SetU32, SetU32Ref};
+
+pub mod setu32b;
+pub use setu32b::SetU32b;
 
 pub mod setu64;
-pub use setu64::SetU64;
+pub use setu64::{AtomicSetU64, SetU64, SetU64Ref};
 
 pub mod set64;
 pub use crate::set64::{Fits64, Set64};
 
+pub mod arcset;
+pub use arcset::{ArcSet64, ArcSetU64};
+
 mod copyset;
+mod cursor;
@@ -0,0 +1,122 @@
+//! Memory accounting for the set types.
+
+use crate::{ArcSet64, ArcSetU64, AtomicSetU64, Fits64, Set64, SetU32, SetU32b, SetU64, SetUsize};
+
+/// A value that can report how much memory it owns on the heap.
+///
+/// This is implemented by each of the set types in this crate, so that
+/// memory reports can include structures built on them.  With the
+/// `heapsize` feature, the sets also implement `heapsize::HeapSizeOf`.
+///
+/// ```
+/// use tinyset::{HeapSize, SetU64};
+///
+/// struct Index {
+///     name: String,
+///     ids: SetU64,
+/// }
+///
+/// impl HeapSize for Index {
+///     fn heap_bytes(&self) -> usize {
+///         self.name.capacity() + self.ids.heap_bytes()
+///     }
+/// }
+///
+/// let small = Index { name: String::new(), ids: [1, 2, 3].into() };
+/// assert_eq!(small.heap_bytes(), 0);
+/// let big = Index { name: String::new(), ids: (0..1000).map(|x| x * 100).collect() };
+/// assert!(big.heap_bytes() > 0);
+/// ```
+pub trait HeapSize {
+    /// The number of bytes allocated on the heap by this value, not
+    /// counting `size_of_val(self)`.
+    fn heap_bytes(&self) -> usize;
+}
+
+macro_rules! heap_size {
+    ($($ty: ty),* $(; $($gen: ty),*)?) => {
+        $(
+            impl HeapSize for $ty {
+                #[inline]
+                fn heap_bytes(&self) -> usize {
+                    <$ty>::heap_bytes(self)
+                }
+            }
+            #[cfg(any(test, feature = "heapsize"))]
+            impl heapsize::HeapSizeOf for $ty {
+                #[inline]
+                fn heap_size_of_children(&self) -> usize {
+                    <$ty>::heap_bytes(self)
+                }
+            }
+        )*
+        $($(
+            impl<T: Fits64> HeapSize for $gen {
+                #[inline]
+                fn heap_bytes(&self) -> usize {
+                    <$gen>::heap_bytes(self)
+                }
+            }
+            #[cfg(any(test, feature = "heapsize"))]
+            impl<T: Fits64> heapsize::HeapSizeOf for $gen {
+                #[inline]
+                fn heap_size_of_children(&self) -> usize {
+                    <$gen>::heap_bytes(self)
+                }
+            }
+        )*)?
+    };
+}
+
+heap_size!(SetU64, SetU32, SetU32b, SetUsize, ArcSetU64, AtomicSetU64; Set64<T>, ArcSet64<T>);
+
+#[cfg(test)]
+fn check_sizes<S: HeapSize + heapsize::HeapSizeOf>(s: &S, mem_used: usize) {
+    assert_eq!(s.heap_bytes() + std::mem::size_of::<S>(), mem_used);
+    assert_eq!(
+        heapsize::HeapSizeOf::heap_size_of_children(s),
+        s.heap_bytes()
+    );
+}
+
+#[test]
+fn heap_bytes() {
+    for v in [
+        vec![],
+        vec![1, 2, 3],
+        (0..1000).collect(),
+        (0..1000).map(|x| x << 40).collect(),
+    ] {
+        let s: SetU64 = v.iter().cloned().collect();
+        check_sizes(&s, s.mem_used());
+        assert_eq!(s.heap_bytes() == 0, s.capacity() == 0);
+        let t: SetU32 = v.iter().map(|&x| x as u32).collect();
+        check_sizes(&t, t.mem_used());
+        let t: SetU32b = v.iter().map(|&x| x as u32).collect();
+        check_sizes(&t, t.mem_used());
+        let t: SetUsize = v.iter().map(|&x| x as usize).collect();
+        check_sizes(&t, t.mem_used());
+        let t: Set64<u64> = v.iter().cloned().collect();
+        check_sizes(&t, t.mem_used());
+        assert_eq!(t.heap_bytes(), s.heap_bytes());
+
+        let a = ArcSetU64::from(s.clone());
+        check_sizes(&a, a.mem_used());
+        assert!(a.heap_bytes() >= s.heap_bytes());
+        let b = a.clone();
+        assert_eq!(b.heap_bytes(), a.heap_bytes());
+        let a: ArcSet64<u64> = t.into();
+        check_sizes(&a, a.mem_used());
+
+        let at = AtomicSetU64::with_max(100);
+        let empty = at.heap_bytes();
+        for &x in v.iter() {
+            at.insert(x);
+        }
+        check_sizes(&at, at.mem_used());
+        if v.len() > 3 {
+            // Most elements are past the bitmap, in shards that are counted.
+            assert!(at.heap_bytes() > empty);
+        }
+    }
+}
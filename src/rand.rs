@@ -1,61 +1,268 @@
-#[cfg(not(feature = "rand"))]
-use std::sync::atomic::{AtomicU64, Ordering};
+//! The random numbers that choose the capacities of growing sets.
+//!
+//! These are random by default, so that an adversary cannot predict which
+//! elements will collide.  A seed instead makes each number depend only on
+//! the seed and the size of the set that asks for it, so the layout (and
+//! iteration order) of a set depends only on the operations performed on
+//! it.
+//!
+//! Without a seed, the numbers come from a [`RandomSource`], which is
+//! [`RandSource`] with the `rand` feature and [`OsSeeded`] otherwise, and
+//! can be replaced with [`set_random_source`].
 
-#[cfg(not(feature = "rand"))]
-static SEED: AtomicU64 = AtomicU64::new(0);
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::RwLock;
 
-#[cfg(not(feature = "rand"))]
-pub fn rand32(cap: u32, bits: u32) -> u32 {
-    rand64(cap as usize, bits as u64) as u32
+const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+thread_local! {
+    static THREAD_SEED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Whether [`GLOBAL_SEED`] is in use.
+static GLOBAL_SEEDED: AtomicBool = AtomicBool::new(cfg!(feature = "deterministic_iteration"));
+static GLOBAL_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Make the sets that grow on this thread lay out their elements
+/// according to `seed`, or at random if it is `None`, returning the
+/// previous seed of this thread.
+///
+/// With a seed, the order of iteration through a set depends only on the
+/// seed and on the sequence of insertions and removals from that set.  A
+/// seed for the thread takes precedence over [`set_global_seed`].
+///
+/// ```
+/// let order = || {
+///     let s: tinyset::SetU64 = (0..100).map(|x| x * 12345).collect();
+///     s.iter().collect::<Vec<_>>()
+/// };
+/// tinyset::set_seed(Some(1));
+/// let a = order();
+/// assert_eq!(order(), a);
+/// tinyset::set_seed(None);
+/// ```
+///
+/// Since a seed makes collisions predictable, it should not be used for
+/// sets that hold untrusted input.
+pub fn set_seed(seed: Option<u64>) -> Option<u64> {
+    THREAD_SEED.with(|s| s.replace(seed))
+}
+
+/// The seed of the current thread, as set by [`set_seed`].
+pub(crate) fn thread_seed() -> Option<u64> {
+    THREAD_SEED.with(|s| s.get())
+}
+
+/// Set the seed for threads that have not called [`set_seed`], as
+/// described there.
+///
+/// This defaults to `Some(0)` with the `deterministic_iteration` feature,
+/// and to `None` otherwise.
+pub fn set_global_seed(seed: Option<u64>) {
+    GLOBAL_SEED.store(seed.unwrap_or(0), Ordering::Relaxed);
+    GLOBAL_SEEDED.store(seed.is_some(), Ordering::Relaxed);
+}
+
+fn seed() -> Option<u64> {
+    thread_seed().or_else(|| {
+        if GLOBAL_SEEDED.load(Ordering::Relaxed) {
+            Some(GLOBAL_SEED.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(feature = "keyed_hashing")]
+static KEYED_HASHING: AtomicBool = AtomicBool::new(true);
+
+/// Choose whether the hash tables of [`SetU64`](crate::SetU64)s created
+/// from now on mix a random key into the hash of each element.  This is
+/// the default with the `keyed_hashing` feature.
+///
+/// Without a key, an element is placed in a table by its own value, so
+/// that an adversary who can guess the capacity of a table can choose
+/// elements that collide.  The key costs a few multiplications per
+/// lookup, and is dropped by [`SetU64::to_compact`](crate::SetU64::to_compact),
+/// which is independent of it.
+#[cfg(feature = "keyed_hashing")]
+pub fn set_keyed_hashing(enabled: bool) {
+    KEYED_HASHING.store(enabled, Ordering::Relaxed);
+}
+
+/// A key for a new hash table, or zero for none.
+#[cfg(feature = "keyed_hashing")]
+pub(crate) fn hashkey(cap: usize, bits: u64) -> u64 {
+    if KEYED_HASHING.load(Ordering::Relaxed) {
+        // The capacity was chosen with `rand64(cap, bits)`, so ask for
+        // something else.
+        rand64(cap, !bits) | 1
+    } else {
+        0
+    }
+}
+
+/// The output function of SplitMix64, which is pretty crude, but should
+/// actually be good enough in most cases.
+pub(crate) fn splitmix(z: u64) -> u64 {
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// A source of the random numbers that choose the capacities of sets
+/// (and their hash keys, with the `keyed_hashing` feature) when no seed
+/// is set.
+///
+/// Any `Fn() -> u64` that is `Send + Sync` is a `RandomSource`, so a
+/// source of your own can be installed with [`set_random_source`]:
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// fn xorshift() -> u64 {
+///     static STATE: AtomicU64 = AtomicU64::new(0x2545_f491_4f6c_dd1d);
+///     let mut x = STATE.load(Ordering::Relaxed);
+///     x ^= x << 13;
+///     x ^= x >> 7;
+///     x ^= x << 17;
+///     STATE.store(x, Ordering::Relaxed);
+///     x
+/// }
+///
+/// tinyset::set_random_source(Some(&xorshift));
+/// let s: tinyset::SetU64 = (0..1000).map(|x| x * 12345).collect();
+/// assert_eq!(s.len(), 1000);
+/// tinyset::set_random_source(None);
+/// ```
+///
+/// The numbers must actually vary, since a set that fails to fit its
+/// elements into a table asks for another capacity until one works.
+pub trait RandomSource: Send + Sync {
+    /// The next random number.
+    fn next_u64(&self) -> u64;
 }
 
+impl<F: Fn() -> u64 + Send + Sync> RandomSource for F {
+    fn next_u64(&self) -> u64 {
+        self()
+    }
+}
+
+/// The [`RandomSource`] that calls `rand::random`, which is the default
+/// with the `rand` feature.
 #[cfg(feature = "rand")]
-pub fn rand32(_cap: u32, _bits: u32) -> u32 {
-    #[cfg(feature = "deterministic_iteration")]
-    compile_error!("Feature rand and deterministic_iteration are mutually exclusive and cannot be enabled together");
-    rand::random::<u32>()
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandSource;
+
+#[cfg(feature = "rand")]
+impl RandomSource for RandSource {
+    fn next_u64(&self) -> u64 {
+        rand::random::<u64>()
+    }
 }
 
-#[cfg(not(feature = "rand"))]
-pub fn rand64(cap: usize, bits: u64) -> u64 {
-    #[cfg(feature = "deterministic_iteration")]
-    {
-        // Just multiply each by a large prime to very crudely hash
-        let x = (cap as u64).wrapping_mul(9838956529666160483);
-        let y = (bits).wrapping_mul(17253312864001072049);
-        x ^ y
+/// A [`RandomSource`] that runs a SplitMix64 generator for each thread,
+/// seeded from the operating system's entropy the first time the thread
+/// uses it.  This is the default without the `rand` feature.
+///
+/// The entropy comes from the standard library, which seeds the keys of
+/// [`RandomState`](std::collections::hash_map::RandomState) from the
+/// operating system, so it needs no further dependencies.  Each thread
+/// has its own state, so threads that create many sets do not contend
+/// for it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsSeeded;
+
+thread_local! {
+    static OS_SEEDED: Cell<u64> = const { Cell::new(0) };
+}
+
+impl RandomSource for OsSeeded {
+    fn next_u64(&self) -> u64 {
+        OS_SEEDED.with(|state| {
+            let mut z = state.get();
+            if z == 0 {
+                use std::hash::{BuildHasher, Hasher};
+                z = std::collections::hash_map::RandomState::new()
+                    .build_hasher()
+                    .finish();
+            }
+            z = z.wrapping_add(GOLDEN_GAMMA);
+            state.set(z);
+            splitmix(z)
+        })
     }
-    #[cfg(not(feature = "deterministic_iteration"))]
-    {
-        use std::num::Wrapping;
-        // This is the SplitMix64 algorithm.  It's pretty crude,
-        // but should actually be good enough in most cases.
-        let z = Wrapping(SEED.fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed));
-        if z == Wrapping(0) {
-            let seed = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .subsec_nanos();
-            SEED.store(seed as u64, Ordering::Relaxed);
-            return rand64(cap, bits);
+}
+
+/// The source installed by [`set_random_source`], if any.
+static SOURCE: RwLock<Option<&'static dyn RandomSource>> = RwLock::new(None);
+/// Whether [`SOURCE`] might hold a source, so that the default source
+/// need not take the lock.
+static SOURCE_SET: AtomicBool = AtomicBool::new(false);
+
+/// Use `source` for the random numbers of all sets from now on, or the
+/// default source if it is `None`.
+///
+/// The default is [`RandSource`] with the `rand` feature, and [`OsSeeded`]
+/// otherwise.  A seed set with [`set_seed`] or [`set_global_seed`] takes
+/// precedence over any source.
+pub fn set_random_source(source: Option<&'static dyn RandomSource>) {
+    let mut current = SOURCE.write().unwrap_or_else(|e| e.into_inner());
+    *current = source;
+    SOURCE_SET.store(source.is_some(), Ordering::Release);
+}
+
+fn random() -> u64 {
+    if SOURCE_SET.load(Ordering::Acquire) {
+        let source = *SOURCE.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(source) = source {
+            return source.next_u64();
         }
-        let z = (z ^ (z >> 30)) * Wrapping(0xbf58476d1ce4e5b9);
-        let z = (z ^ (z >> 27)) * Wrapping(0x94d049bb133111eb);
-        (z ^ (z >> 31)).0
     }
+    #[cfg(feature = "rand")]
+    return RandSource.next_u64();
+    #[cfg(not(feature = "rand"))]
+    return OsSeeded.next_u64();
 }
 
-#[cfg(feature = "rand")]
-pub fn rand64(_cap: usize, _bits: u64) -> u64 {
-    rand::random::<u64>()
+pub fn rand32(cap: u32, bits: u32) -> u32 {
+    rand64(cap as usize, bits as u64) as u32
+}
+
+pub fn rand64(cap: usize, bits: u64) -> u64 {
+    match seed() {
+        // Multiply each by a large prime to very crudely hash
+        Some(seed) => splitmix(
+            seed.wrapping_add(GOLDEN_GAMMA)
+                ^ (cap as u64).wrapping_mul(9838956529666160483)
+                ^ bits.wrapping_mul(17253312864001072049),
+        ),
+        None => random(),
+    }
 }
 
-#[cfg(not(feature = "rand"))]
 pub fn rand_usize(cap: usize, bits: u64) -> usize {
     rand64(cap, bits) as usize
 }
 
-#[cfg(feature = "rand")]
-pub fn rand_usize(_cap: usize, _bits: u64) -> usize {
-    rand::random::<usize>()
+#[test]
+fn seeds() {
+    let order = || {
+        let s: crate::SetU64 = (0..1000).map(|x| x * 12345).collect();
+        s.iter().collect::<Vec<_>>()
+    };
+    let old = set_seed(Some(7));
+    let a = order();
+    assert_eq!(order(), a);
+    // Unrelated sets do not change the order.
+    let _other: crate::SetU64 = (0..1000).map(|x| x * 777).collect();
+    assert_eq!(order(), a);
+    let r = rand64(5, 3);
+    assert_eq!(rand64(5, 3), r);
+    assert_ne!(rand64(5, 4), r);
+    set_seed(Some(8));
+    assert_ne!(rand64(5, 3), r);
+    assert_eq!(set_seed(old), Some(8));
 }
@@ -0,0 +1,274 @@
+//! Interoperability with [Roaring bitmaps](https://roaringbitmap.org).
+//!
+//! The portable serialization format is implemented natively, so it is
+//! always available.  Conversions to and from the types of the `roaring`
+//! crate require the `roaring` feature.
+
+use crate::SetU32;
+use std::io::{self, Read, Write};
+
+const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+const SERIAL_COOKIE: u16 = 12347;
+const NO_OFFSET_THRESHOLD: usize = 4;
+/// Containers holding more than this many values are stored as bitmaps.
+const ARRAY_LIMIT: usize = 4096;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut b = [0; 2];
+    r.read_exact(&mut b)?;
+    Ok(u16::from_le_bytes(b))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+impl SetU32 {
+    /// Serialize this set in the [portable Roaring format][format].
+    ///
+    /// The output is readable by the official C/C++, Java and Go
+    /// implementations (and the `roaring` crate).  Run containers are
+    /// never written.
+    ///
+    /// [format]: https://github.com/RoaringBitmap/RoaringFormatSpec
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let a: SetU32 = (1..4).chain(70000..80000).collect();
+    /// let mut bytes = Vec::new();
+    /// a.serialize_roaring(&mut bytes).unwrap();
+    /// assert_eq!(a, SetU32::deserialize_roaring(&bytes[..]).unwrap());
+    /// ```
+    pub fn serialize_roaring<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut v: Vec<u32> = self.iter().collect();
+        v.sort_unstable();
+        let mut containers: Vec<&[u32]> = Vec::new();
+        let mut rest = &v[..];
+        while let Some(&first) = rest.first() {
+            let n = rest.iter().take_while(|&&x| x >> 16 == first >> 16).count();
+            containers.push(&rest[..n]);
+            rest = &rest[n..];
+        }
+
+        w.write_all(&SERIAL_COOKIE_NO_RUNCONTAINER.to_le_bytes())?;
+        w.write_all(&(containers.len() as u32).to_le_bytes())?;
+        for c in containers.iter() {
+            w.write_all(&((c[0] >> 16) as u16).to_le_bytes())?;
+            w.write_all(&((c.len() - 1) as u16).to_le_bytes())?;
+        }
+        let mut offset = 8 + 8 * containers.len() as u32;
+        for c in containers.iter() {
+            w.write_all(&offset.to_le_bytes())?;
+            offset += if c.len() <= ARRAY_LIMIT {
+                2 * c.len() as u32
+            } else {
+                8 * 1024
+            };
+        }
+        for c in containers.iter() {
+            if c.len() <= ARRAY_LIMIT {
+                for &x in c.iter() {
+                    w.write_all(&(x as u16).to_le_bytes())?;
+                }
+            } else {
+                let mut bitmap = [0u64; 1024];
+                for &x in c.iter() {
+                    let low = x as u16 as usize;
+                    bitmap[low >> 6] |= 1 << (low & 63);
+                }
+                for word in bitmap.iter() {
+                    w.write_all(&word.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize a set from the [portable Roaring format][format].
+    ///
+    /// Array, bitmap and run containers are all accepted.
+    ///
+    /// [format]: https://github.com/RoaringBitmap/RoaringFormatSpec
+    pub fn deserialize_roaring<R: Read>(mut r: R) -> io::Result<SetU32> {
+        let cookie = read_u32(&mut r)?;
+        let (size, runs) = if cookie == SERIAL_COOKIE_NO_RUNCONTAINER {
+            (read_u32(&mut r)? as usize, None)
+        } else if cookie as u16 == SERIAL_COOKIE {
+            let size = (cookie >> 16) as usize + 1;
+            let mut runs = vec![0u8; (size + 7) / 8];
+            r.read_exact(&mut runs)?;
+            (size, Some(runs))
+        } else {
+            return Err(invalid("unknown roaring cookie value"));
+        };
+        if size > 1 << 16 {
+            return Err(invalid("too many roaring containers"));
+        }
+
+        let mut description = Vec::with_capacity(size);
+        for _ in 0..size {
+            let key = read_u16(&mut r)?;
+            let card = read_u16(&mut r)? as usize + 1;
+            description.push((key, card));
+        }
+        if runs.is_none() || size >= NO_OFFSET_THRESHOLD {
+            // The offsets are only needed for random access.
+            for _ in 0..size {
+                read_u32(&mut r)?;
+            }
+        }
+
+        let mut v = Vec::new();
+        for (i, (key, card)) in description.into_iter().enumerate() {
+            let high = (key as u32) << 16;
+            let is_run = runs
+                .as_ref()
+                .map(|runs| runs[i / 8] & (1 << (i % 8)) != 0)
+                .unwrap_or(false);
+            if is_run {
+                let num_runs = read_u16(&mut r)?;
+                for _ in 0..num_runs {
+                    let start = read_u16(&mut r)? as u32;
+                    let len = read_u16(&mut r)? as u32;
+                    if start + len > 0xffff {
+                        return Err(invalid("roaring run overflows its container"));
+                    }
+                    v.extend((start..=start + len).map(|x| high | x));
+                }
+            } else if card <= ARRAY_LIMIT {
+                for _ in 0..card {
+                    v.push(high | read_u16(&mut r)? as u32);
+                }
+            } else {
+                for word_index in 0..1024 {
+                    let mut b = [0; 8];
+                    r.read_exact(&mut b)?;
+                    let mut word = u64::from_le_bytes(b);
+                    while word != 0 {
+                        let bit = word.trailing_zeros();
+                        v.push(high | (word_index << 6) | bit);
+                        word &= word - 1;
+                    }
+                }
+            }
+        }
+        Ok(v.into_iter().collect())
+    }
+}
+
+#[cfg(feature = "roaring")]
+mod conversions {
+    use crate::{SetU32, SetU64};
+    use roaring::{RoaringBitmap, RoaringTreemap};
+
+    impl From<&SetU32> for RoaringBitmap {
+        fn from(s: &SetU32) -> Self {
+            s.iter().collect()
+        }
+    }
+    impl From<SetU32> for RoaringBitmap {
+        fn from(s: SetU32) -> Self {
+            s.into_iter().collect()
+        }
+    }
+    impl From<&RoaringBitmap> for SetU32 {
+        fn from(b: &RoaringBitmap) -> Self {
+            b.iter().collect()
+        }
+    }
+    impl From<RoaringBitmap> for SetU32 {
+        fn from(b: RoaringBitmap) -> Self {
+            b.into_iter().collect()
+        }
+    }
+
+    impl From<&SetU64> for RoaringTreemap {
+        fn from(s: &SetU64) -> Self {
+            s.iter().collect()
+        }
+    }
+    impl From<SetU64> for RoaringTreemap {
+        fn from(s: SetU64) -> Self {
+            s.into_iter().collect()
+        }
+    }
+    impl From<&RoaringTreemap> for SetU64 {
+        fn from(b: &RoaringTreemap) -> Self {
+            b.iter().collect()
+        }
+    }
+    impl From<RoaringTreemap> for SetU64 {
+        fn from(b: RoaringTreemap) -> Self {
+            b.into_iter().collect()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let a: SetU32 = (0..10).chain(100000..110000).collect();
+        let b = RoaringBitmap::from(&a);
+        assert_eq!(a.len() as u64, b.len());
+        assert_eq!(a, SetU32::from(b));
+
+        let a: SetU64 = (0..10).chain(1 << 40..(1 << 40) + 10000).collect();
+        let b = RoaringTreemap::from(&a);
+        assert_eq!(a.len() as u64, b.len());
+        assert_eq!(a, SetU64::from(b));
+    }
+}
+
+#[cfg(test)]
+fn check_roaring(set: SetU32) {
+    let mut ours = Vec::new();
+    set.serialize_roaring(&mut ours).unwrap();
+    let theirs: ::roaring::RoaringBitmap = set.iter().collect();
+    let mut bytes = Vec::new();
+    theirs.serialize_into(&mut bytes).unwrap();
+    assert_eq!(ours, bytes);
+    assert_eq!(set, SetU32::deserialize_roaring(&bytes[..]).unwrap());
+}
+
+#[test]
+fn same_bytes_as_roaring() {
+    check_roaring(SetU32::new());
+    check_roaring((0..1).collect());
+    check_roaring((1..4).collect());
+    check_roaring((0..5000).collect());
+    check_roaring((0..100).map(|x| x * 70001).collect());
+    check_roaring([u32::MAX, u32::MAX - 1, 0].iter().cloned().collect());
+    check_roaring((60000..200000).collect());
+}
+
+#[test]
+fn run_containers() {
+    // Two containers, the first holding the run 5..=9 and the second
+    // holding the array [3].
+    let mut bytes = Vec::new();
+    bytes.extend(&(SERIAL_COOKIE as u32 | 1 << 16).to_le_bytes());
+    bytes.push(0b01);
+    for x in [0u16, 4, 1, 0] {
+        bytes.extend(&x.to_le_bytes());
+    }
+    for x in [1u16, 5, 4, 3] {
+        bytes.extend(&x.to_le_bytes());
+    }
+    let set = SetU32::deserialize_roaring(&bytes[..]).unwrap();
+    assert_eq!(set, [5, 6, 7, 8, 9, 65539].iter().cloned().collect());
+}
+
+#[test]
+fn corrupt_input() {
+    assert!(SetU32::deserialize_roaring(&[0u8, 1, 2, 3][..]).is_err());
+    let mut bytes = Vec::new();
+    let set: SetU32 = (0..10).collect();
+    set.serialize_roaring(&mut bytes).unwrap();
+    bytes.pop();
+    assert!(SetU32::deserialize_roaring(&bytes[..]).is_err());
+}
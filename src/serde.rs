@@ -0,0 +1,384 @@
+//! Serde adapters for individual fields.
+//!
+//! The `Serialize` implementations of the set types pick their format
+//! based on crate features, which apply to every set in the program.
+//! The modules here instead choose a format for a single field, using
+//! `#[serde(with = "...")]`, and behave the same regardless of which
+//! features are enabled:
+//!
+//! * [`compact`] writes the internal representation of the set (see
+//!   [`SetU64::to_compact`](crate::SetU64::to_compact)).  This is the
+//!   smallest and fastest format, but it depends on the platform and on
+//!   the version of `tinyset`.
+//! * [`sorted`] writes the elements in ascending order.
+//! * [`ranges`] writes ascending `[first, last]` pairs, each describing a
+//!   run of consecutive elements.
+//!
+//! Each adapter works with [`SetU64`], [`SetU32`] and [`SetUsize`], and
+//! [`sorted`] also works with a [`Set64`] of an ordered type.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use tinyset::{SetU32, SetU64};
+//!
+//! #[derive(Serialize, Deserialize, PartialEq, Debug)]
+//! struct Record {
+//!     #[serde(with = "tinyset::serde::sorted")]
+//!     tags: SetU32,
+//!     #[serde(with = "tinyset::serde::ranges")]
+//!     pages: SetU64,
+//! }
+//!
+//! let r = Record {
+//!     tags: [30, 1, 7].iter().cloned().collect(),
+//!     pages: (1..=5).chain(9..=10).collect(),
+//! };
+//! let json = serde_json::to_string(&r).unwrap();
+//! assert_eq!(json, r#"{"tags":[1,7,30],"pages":[[1,5],[9,10]]}"#);
+//! assert_eq!(r, serde_json::from_str(&json).unwrap());
+//! ```
+
+use crate::{Fits64, Set64, SetU32, SetU32Ref, SetU64, SetU64Ref, SetUsize};
+use ::serde::de::DeserializeOwned;
+use ::serde::Serialize;
+
+/// A set type that can be used with [`sorted`].
+///
+/// This trait is sealed, and is implemented for [`SetU64`], [`SetU32`],
+/// [`SetUsize`] and [`Set64`] of an ordered type.
+pub trait Set: sealed::Set {}
+
+/// A set of integers, which can also be used with [`compact`] and
+/// [`ranges`].
+///
+/// This trait is sealed, and is implemented for [`SetU64`], [`SetU32`]
+/// and [`SetUsize`].  A [`Set64`] is not an `IntegerSet`: its compact
+/// form holds [`Fits64`] keys, which could not be trusted to decode to
+/// valid elements, and consecutive keys need not be consecutive values.
+pub trait IntegerSet: Set + sealed::IntegerSet {}
+
+mod sealed {
+    use super::*;
+
+    pub trait Set: Sized {
+        type Item: Copy + Serialize + DeserializeOwned;
+        fn new() -> Self;
+        /// The elements in ascending order.
+        fn sorted(&self) -> Box<dyn Iterator<Item = Self::Item> + '_>;
+        fn insert(&mut self, item: Self::Item);
+    }
+
+    /// The integer sets work on their elements as `u64` keys, so that
+    /// runs can be found without overflow.
+    pub trait IntegerSet: Set {
+        type Word: Copy + Serialize + DeserializeOwned;
+        fn item(key: u64) -> Self::Item;
+        fn key(item: Self::Item) -> u64;
+        /// Inserts the keys from `first` to `last` inclusive.
+        fn insert_keys(&mut self, first: u64, last: u64);
+        fn to_compact(&self) -> Vec<Self::Word>;
+        fn from_compact(words: &[Self::Word]) -> Option<Self>;
+    }
+}
+
+macro_rules! integer_set {
+    ($ty: ty, $item: ty, $word: ty, |$s: ident| $to_compact: expr, |$w: ident| $from_compact: expr) => {
+        impl sealed::Set for $ty {
+            type Item = $item;
+            fn new() -> Self {
+                <$ty>::new()
+            }
+            fn sorted(&self) -> Box<dyn Iterator<Item = $item> + '_> {
+                Box::new(self.iter_sorted())
+            }
+            fn insert(&mut self, item: $item) {
+                self.insert(item);
+            }
+        }
+        impl Set for $ty {}
+
+        impl sealed::IntegerSet for $ty {
+            type Word = $word;
+            fn item(key: u64) -> $item {
+                key as $item
+            }
+            fn key(item: $item) -> u64 {
+                item as u64
+            }
+            fn insert_keys(&mut self, first: u64, last: u64) {
+                self.insert_range(first as $item..=last as $item);
+            }
+            fn to_compact(&self) -> Vec<$word> {
+                let $s = self;
+                $to_compact
+            }
+            fn from_compact($w: &[$word]) -> Option<Self> {
+                $from_compact
+            }
+        }
+        impl IntegerSet for $ty {}
+    };
+}
+
+integer_set!(SetU64, u64, u64, |s| s.to_compact(), |w| {
+    SetU64Ref::new(w).map(|v| v.to_set())
+});
+integer_set!(SetU32, u32, u32, |s| s.to_compact(), |w| {
+    SetU32Ref::new(w).map(|v| v.to_set())
+});
+integer_set!(
+    SetUsize,
+    usize,
+    <crate::setusize::Internal as sealed::IntegerSet>::Word,
+    |s| s.0.to_compact(),
+    |w| sealed::IntegerSet::from_compact(w).map(SetUsize)
+);
+
+/// The elements of a [`Set64`] are sorted by their own order, not by
+/// [`Fits64::to_u64`], so that the output does not depend on how `T`
+/// is stored.
+impl<T: Fits64 + Ord + Serialize + DeserializeOwned> sealed::Set for Set64<T> {
+    type Item = T;
+    fn new() -> Self {
+        Set64::new()
+    }
+    fn sorted(&self) -> Box<dyn Iterator<Item = T> + '_> {
+        let mut v: Vec<T> = self.iter().collect();
+        v.sort_unstable();
+        Box::new(v.into_iter())
+    }
+    fn insert(&mut self, item: T) {
+        self.insert(item);
+    }
+}
+impl<T: Fits64 + Ord + Serialize + DeserializeOwned> Set for Set64<T> {}
+
+/// Serialize a set in its compact internal representation.
+///
+/// Deserialization validates the representation, and fails if it does
+/// not describe a set.
+pub mod compact {
+    use super::{sealed, IntegerSet};
+    use ::serde::de::{Deserialize, Deserializer, Error};
+    use ::serde::ser::Serializer;
+
+    /// Serialize `set` in its compact representation.
+    pub fn serialize<T: IntegerSet, S: Serializer>(
+        set: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(sealed::IntegerSet::to_compact(set))
+    }
+
+    /// Deserialize a set from its compact representation.
+    pub fn deserialize<'de, T: IntegerSet, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let words: Vec<T::Word> = Vec::deserialize(deserializer)?;
+        T::from_compact(&words).ok_or_else(|| D::Error::custom("invalid compact set"))
+    }
+}
+
+/// Serialize a set as a sequence of its elements in ascending order.
+///
+/// The output is the same as that of a sorted `Vec`, so it does not
+/// depend on the history of the set.  Deserialization accepts the
+/// elements in any order.
+pub mod sorted {
+    use super::{sealed, Set};
+    use ::serde::de::{Deserializer, SeqAccess, Visitor};
+    use ::serde::ser::Serializer;
+    use std::marker::PhantomData;
+
+    /// Serialize the elements of `set` in ascending order.
+    pub fn serialize<T: Set, S: Serializer>(set: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(set.sorted())
+    }
+
+    struct SetVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Set> Visitor<'de> for SetVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a sequence of set elements")
+        }
+
+        fn visit_seq<M: SeqAccess<'de>>(self, mut access: M) -> Result<T, M::Error> {
+            let mut set = <T as sealed::Set>::new();
+            while let Some(elem) = access.next_element()? {
+                sealed::Set::insert(&mut set, elem);
+            }
+            Ok(set)
+        }
+    }
+
+    /// Deserialize a set from a sequence of its elements.
+    pub fn deserialize<'de, T: Set, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        deserializer.deserialize_seq(SetVisitor(PhantomData))
+    }
+}
+
+/// Serialize a set as a sequence of ascending `[first, last]` pairs, each
+/// of which holds a run of consecutive elements.
+///
+/// This is much smaller than [`sorted`] for sets that
+/// consist of a few long runs.  Deserialization accepts overlapping pairs
+/// in any order, but fails if they hold more than [`ranges::MAX_ELEMENTS`]
+/// elements in total, since a few bytes of input could otherwise describe
+/// a set too large to allocate.
+pub mod ranges {
+    use super::{sealed, IntegerSet};
+    use ::serde::de::{Deserialize, Deserializer, Error};
+    use ::serde::ser::Serializer;
+
+    /// The largest total length of the runs that will be deserialized,
+    /// which is enough for every `u32`.
+    pub const MAX_ELEMENTS: u64 = 1 << 32;
+
+    /// Serialize `set` as a sequence of runs.
+    pub fn serialize<T: IntegerSet, S: Serializer>(
+        set: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for x in set.sorted().map(T::key) {
+            match runs.last_mut() {
+                Some(run) if run.1 + 1 == x => run.1 = x,
+                _ => runs.push((x, x)),
+            }
+        }
+        serializer.collect_seq(runs.into_iter().map(|(a, b)| (T::item(a), T::item(b))))
+    }
+
+    /// Deserialize a set from a sequence of runs.
+    pub fn deserialize<'de, T: IntegerSet, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let runs: Vec<(T::Item, T::Item)> = Vec::deserialize(deserializer)?;
+        let mut total = 0u64;
+        for &(first, last) in runs.iter() {
+            let (first, last) = (T::key(first), T::key(last));
+            if first > last {
+                return Err(D::Error::custom("range with first element after last"));
+            }
+            total = total.saturating_add(last - first).saturating_add(1);
+            if total > MAX_ELEMENTS {
+                return Err(D::Error::custom("ranges hold too many elements"));
+            }
+        }
+        let mut set = <T as sealed::Set>::new();
+        for (first, last) in runs {
+            sealed::IntegerSet::insert_keys(&mut set, T::key(first), T::key(last));
+        }
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Fields {
+        #[serde(with = "compact")]
+        compact: SetU64,
+        #[serde(with = "sorted")]
+        sorted: SetU32,
+        #[serde(with = "ranges")]
+        ranges: SetUsize,
+        #[serde(with = "sorted")]
+        chars: Set64<char>,
+        #[serde(with = "sorted")]
+        signed: Set64<i32>,
+    }
+
+    fn check_fields(v: &[u32]) {
+        let f = Fields {
+            compact: v.iter().map(|&x| x as u64 * 1000).collect(),
+            sorted: v.iter().cloned().collect(),
+            ranges: v.iter().map(|&x| x as usize).collect(),
+            chars: v
+                .iter()
+                .filter_map(|&x| std::char::from_u32(x % 1000))
+                .collect(),
+            signed: v.iter().map(|&x| x as i32).collect(),
+        };
+        let json = serde_json::to_string(&f).unwrap();
+        assert_eq!(f, serde_json::from_str(&json).unwrap());
+
+        let mut sorted: Vec<u32> = v.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        let json = serde_json::to_string(&sorted).unwrap();
+        let mut de = serde_json::Deserializer::from_str(&json);
+        assert_eq!(f.sorted, sorted::deserialize::<SetU32, _>(&mut de).unwrap());
+        let mut out = Vec::new();
+        sorted::serialize(&f.sorted, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(json.as_bytes(), &out[..]);
+    }
+
+    #[test]
+    fn fields() {
+        check_fields(&[]);
+        check_fields(&[0]);
+        check_fields(&[1, 2, 3, 5, 8, 13]);
+        check_fields(&(0..1000).collect::<Vec<_>>());
+        check_fields(&(0..1000).map(|x| x * 37 % 1009).collect::<Vec<_>>());
+        check_fields(&[u32::MAX, 0, u32::MAX - 1, 1 << 31]);
+    }
+
+    #[test]
+    fn ranges_format() {
+        let set: SetU32 = (0..10).chain(20..21).chain(100..=102).collect();
+        let mut out = Vec::new();
+        ranges::serialize(&set, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&out).unwrap(),
+            "[[0,9],[20,20],[100,102]]"
+        );
+
+        let mut de = serde_json::Deserializer::from_str("[[5,3]]");
+        assert!(ranges::deserialize::<SetU32, _>(&mut de).is_err());
+
+        let mut de = serde_json::Deserializer::from_str("[[10,100009],[5,20]]");
+        let set: SetU64 = ranges::deserialize(&mut de).unwrap();
+        assert_eq!(set, (5..100010).collect());
+        for huge in [
+            "[[0,18446744073709551615]]",
+            "[[0,2147483648],[0,2147483648]]",
+        ] {
+            let mut de = serde_json::Deserializer::from_str(huge);
+            assert!(ranges::deserialize::<SetU64, _>(&mut de).is_err());
+        }
+    }
+
+    #[test]
+    fn sorted_signed() {
+        let set: Set64<i32> = [3, -1, -5, i32::MIN].iter().cloned().collect();
+        let mut out = Vec::new();
+        sorted::serialize(&set, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(std::str::from_utf8(&out).unwrap(), "[-2147483648,-5,-1,3]");
+    }
+
+    #[test]
+    fn invalid_compact() {
+        let mut de = serde_json::Deserializer::from_str("[3,64,3]");
+        assert!(compact::deserialize::<SetU64, _>(&mut de).is_err());
+        let mut de = serde_json::Deserializer::from_str("[2,64,3]");
+        let set: SetU64 = compact::deserialize(&mut de).unwrap();
+        assert_eq!(set, [0, 1].iter().cloned().collect());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn fields_round_trip(v: Vec<u32>) {
+            check_fields(&v);
+        }
+        #[test]
+        fn small_fields_round_trip(v in proptest::collection::vec(0u32..3000, 0usize..300)) {
+            check_fields(&v);
+        }
+    }
+}
@@ -7,6 +7,7 @@
 
 //! A set that is compact in size.
 
+use crate::copyset::inclusive_bounds;
 #[cfg(test)]
 use proptest::prelude::*;
 use std;
@@ -171,7 +172,7 @@ define_ifits!(isize, usize, fits_isize);
 /// scenario is large integers widely spaced apart, in which case the
 /// storage is similar to a [`std::collections::HashSet`].
 #[derive(Debug, Clone)]
-pub struct Set64<T: Fits64>(crate::setu64::SetU64, PhantomData<T>);
+pub struct Set64<T: Fits64>(pub(crate) crate::setu64::SetU64, PhantomData<T>);
 
 impl<T: Fits64> Default for Set64<T> {
     /// Creates an empty set..
@@ -201,11 +202,62 @@ impl<T: Fits64> Set64<T> {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+    /// Tally up how much memory is in use, including the set itself.
+    pub fn mem_used(&self) -> usize {
+        self.0.mem_used()
+    }
+    /// The number of bytes allocated on the heap for this set.
+    pub fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
     /// Returns true if the set contains a value.
     pub fn contains<R: std::borrow::Borrow<T>>(&self, value: R) -> bool {
         let x = value.borrow().clone().to_u64();
         self.0.contains(x)
     }
+    /// Returns true if the set contains every one of `vals`.
+    ///
+    /// See [`SetU64::contains_all`](crate::SetU64::contains_all).  The
+    /// batch queries are fastest when `vals` is in ascending order of
+    /// [`Fits64::to_u64`].
+    pub fn contains_all(&self, vals: &[T]) -> bool {
+        let mut all = true;
+        self.0.probe(vals.iter().map(|x| x.to_u64()), |_, found| {
+            all = found;
+            found
+        });
+        all
+    }
+    /// Returns true if the set contains at least one of `vals`.
+    pub fn contains_any(&self, vals: &[T]) -> bool {
+        let mut any = false;
+        self.0.probe(vals.iter().map(|x| x.to_u64()), |_, found| {
+            any = found;
+            !found
+        });
+        any
+    }
+    /// Counts how many of `vals` are in the set, including repeats.
+    pub fn count_contained(&self, vals: &[T]) -> usize {
+        let mut count = 0;
+        self.0.probe(vals.iter().map(|x| x.to_u64()), |_, found| {
+            count += found as usize;
+            true
+        });
+        count
+    }
+    /// Returns those of `vals` that are in the set, in their original
+    /// order.
+    pub fn filter_contained(&self, vals: &[T]) -> Vec<T> {
+        let mut out = Vec::new();
+        self.0.probe(vals.iter().map(|x| x.to_u64()), |e, found| {
+            if found {
+                out.push(unsafe { T::from_u64(e) });
+            }
+            true
+        });
+        out
+    }
     /// Removes an element, and returns true if that element was present.
     pub fn remove(&mut self, value: &T) -> bool {
         let x = value.clone().to_u64();
@@ -219,42 +271,305 @@ impl<T: Fits64> Set64<T> {
     pub fn drain<'a>(&'a mut self) -> impl Iterator<Item = T> + 'a {
         self.0.drain().map(|x| unsafe { T::from_u64(x) })
     }
+    /// Create a set from elements in ascending order of
+    /// [`Fits64::to_u64`], which is numerical order for unsigned integers
+    /// and `char`.
+    ///
+    /// See [`SetU64::from_sorted_iter`](crate::SetU64::from_sorted_iter).
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Clone,
+    {
+        Set64(
+            crate::setu64::SetU64::from_sorted_iter(iter.into_iter().map(T::to_u64)),
+            PhantomData,
+        )
+    }
+    /// Create a set from a slice in ascending order of [`Fits64::to_u64`].
+    pub fn from_sorted_slice(v: &[T]) -> Self {
+        Self::from_sorted_iter(v.iter().cloned())
+    }
+    /// Insert elements given in ascending order of [`Fits64::to_u64`].
+    ///
+    /// See [`SetU64::extend_sorted`](crate::SetU64::extend_sorted).
+    pub fn extend_sorted<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Clone,
+    {
+        self.0.extend_sorted(iter.into_iter().map(T::to_u64))
+    }
+    /// Creates a set from the [`Fits64::to_u64`] encodings of its
+    /// elements, as given by converting a `Set64<T>` into a
+    /// [`SetU64`](crate::SetU64).
+    ///
+    /// # Safety
+    ///
+    /// Every element of `set` must be the encoding of some `T`, since it
+    /// will be decoded with [`Fits64::from_u64`].
+    ///
+    /// ```
+    /// use tinyset::{Set64, SetU64};
+    ///
+    /// let a: Set64<i8> = [-1, 0, 1].iter().cloned().collect();
+    /// let encoded = SetU64::from(a.clone());
+    /// assert_eq!(unsafe { Set64::<i8>::from_encoded(encoded) }, a);
+    /// ```
+    pub unsafe fn from_encoded(set: crate::SetU64) -> Self {
+        Set64(set, PhantomData)
+    }
+    /// A [`Cursor`] at the first element in order of [`Fits64::to_u64`].
+    ///
+    /// Like [`SetU64::cursor`](crate::SetU64::cursor), this copies and
+    /// sorts the set when it is stored as a hash table, in O(n log n) time.
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor(self.0.cursor(), PhantomData)
+    }
+    /// A [`Cursor`] at the first element that is not before `x` in order
+    /// of [`Fits64::to_u64`].
+    ///
+    /// ```
+    /// use tinyset::Set64;
+    ///
+    /// let a: Set64<char> = "hello world".chars().collect();
+    /// assert_eq!(a.iter_from('m').collect::<String>(), "orw");
+    /// ```
+    pub fn iter_from(&self, x: T) -> Cursor<'_, T> {
+        Cursor(self.0.iter_from(x.to_u64()), PhantomData)
+    }
+    /// Removes every value in `range`, returning how many were present.
+    pub fn remove_range<R: std::ops::RangeBounds<T>>(&mut self, range: R) -> usize
+    where
+        T: PartialOrd,
+    {
+        self.drain_range(range).count()
+    }
+    /// Removes every value in `range`, returning them in an iterator.
+    pub fn drain_range<R: std::ops::RangeBounds<T>>(&mut self, range: R) -> IntoIter<T>
+    where
+        T: PartialOrd,
+    {
+        let drained: crate::SetU64 = self
+            .iter()
+            .filter(|x| range.contains(x))
+            .map(T::to_u64)
+            .collect();
+        for x in drained.iter() {
+            self.0.remove(x);
+        }
+        IntoIter(drained.into_iter(), PhantomData)
+    }
 }
 
-impl<T: Fits64> PartialEq for Set64<T> {
-    fn eq(&self, other: &Set64<T>) -> bool {
-        if self.len() != other.len() {
-            return false;
+macro_rules! impl_complement {
+    ($($ty: ty),*) => {$(
+impl Set64<$ty> {
+    /// The values in `universe` that are not in the set.
+    ///
+    /// See [`SetU64::complement`](crate::SetU64::complement).
+    pub fn complement(&self, universe: std::ops::Range<$ty>) -> Self {
+        Set64(self.0.complement(universe.start as u64..universe.end as u64), PhantomData)
+    }
+    /// Replaces the set with the values in `0..n` that are not in it.
+    pub fn complement_in_place(&mut self, n: $ty) {
+        self.0.complement_in_place(n as u64)
+    }
+}
+    )*};
+}
+impl_complement!(u8, u16, u32, u64, usize);
+
+macro_rules! impl_insert_range {
+    ($($ty: ty),* ; $($ity: ty),*) => {$(
+impl Set64<$ty> {
+    #[doc = concat!(
+        "Adds every value in `range`, returning how many were not already
+present.
+
+The values are inserted together as by
+[`SetU64::insert_range`](crate::SetU64::insert_range).
+
+```
+use tinyset::Set64;
+
+let mut s: Set64<", stringify!($ty), "> = Set64::new();
+assert_eq!(s.insert_range(..10), 10);
+assert_eq!(s.insert_range(5..=20), 11);
+assert_eq!(s.insert_range(", stringify!($ty), "::MAX - 5..), 6);
+assert_eq!(s.len(), 27);
+```
+"
+    )]
+    pub fn insert_range<R: std::ops::RangeBounds<$ty>>(&mut self, range: R) -> usize {
+        Self::insert_codes(range, |lo, hi| self.0.insert_range(lo..=hi))
+    }
+    /// Passes the encodings of the values in `range` to `insert` as
+    /// inclusive runs, returning the total it reports.
+    pub(crate) fn insert_codes<R: std::ops::RangeBounds<$ty>>(
+        range: R,
+        mut insert: impl FnMut(u64, u64) -> usize,
+    ) -> usize {
+        match inclusive_bounds(&range, <$ty>::MAX as u64, |&x| x as u64) {
+            Some((lo, hi)) => insert(lo, hi),
+            None => 0,
         }
-        for k in other.0.iter() {
-            if !self.0.contains(k) {
-                return false;
+    }
+}
+    )*$(
+impl Set64<$ity> {
+    #[doc = concat!(
+        "Adds every value in `range`, returning how many were not already
+present.
+
+Where a range holds both negative and non-negative values their
+encodings are consecutive and are inserted together as by
+[`SetU64::insert_range`](crate::SetU64::insert_range).  The rest are
+inserted one at a time.
+
+```
+use tinyset::Set64;
+
+let mut s: Set64<", stringify!($ity), "> = Set64::new();
+assert_eq!(s.insert_range(-100..100), 200);
+assert_eq!(s.remove_range(0..), 100);
+assert_eq!(s.drain_range(..-50).count(), 50);
+assert_eq!(s, (-50..0).collect());
+```
+"
+    )]
+    pub fn insert_range<R: std::ops::RangeBounds<$ity>>(&mut self, range: R) -> usize {
+        Self::insert_codes(range, |lo, hi| self.0.insert_range(lo..=hi))
+    }
+    /// Passes the encodings of the values in `range` to `insert` as
+    /// inclusive runs, returning the total it reports.
+    pub(crate) fn insert_codes<R: std::ops::RangeBounds<$ity>>(
+        range: R,
+        insert: impl FnMut(u64, u64) -> usize,
+    ) -> usize {
+        // Flipping the sign bit maps the values to u64 in order.
+        let flip = |&x: &$ity| (x as i64 as u64) ^ (1 << 63);
+        match inclusive_bounds(&range, flip(&<$ity>::MAX), flip) {
+            Some((lo, hi)) if hi >= flip(&<$ity>::MIN) => {
+                let lo = lo.max(flip(&<$ity>::MIN));
+                insert_signed((lo ^ (1 << 63)) as i64, (hi ^ (1 << 63)) as i64, insert)
+            }
+            _ => 0,
+        }
+    }
+}
+    )*};
+}
+impl_insert_range!(u8, u16, u32, u64, usize; i8, i16, i32, i64, isize);
+
+impl Set64<char> {
+    /// Adds every value in `range`, returning how many were not already
+    /// present.
+    ///
+    /// ```
+    /// use tinyset::Set64;
+    ///
+    /// let mut s: Set64<char> = Set64::new();
+    /// assert_eq!(s.insert_range('a'..='z'), 26);
+    /// assert_eq!(s.insert_range('\u{D000}'..'\u{F000}'), 0x1800);
+    /// ```
+    pub fn insert_range<R: std::ops::RangeBounds<char>>(&mut self, range: R) -> usize {
+        Self::insert_codes(range, |lo, hi| self.0.insert_range(lo..=hi))
+    }
+    /// Passes the encodings of the values in `range` to `insert` as
+    /// inclusive runs, returning the total it reports.
+    pub(crate) fn insert_codes<R: std::ops::RangeBounds<char>>(
+        range: R,
+        mut insert: impl FnMut(u64, u64) -> usize,
+    ) -> usize {
+        // The encodings skip the surrogates, which are not chars.
+        let (gap_lo, gap_hi) = (0xD800, 0xDFFF);
+        match inclusive_bounds(&range, char::MAX as u64, |&c| c as u64) {
+            Some((lo, hi)) => {
+                let mut added = 0;
+                if lo < gap_lo {
+                    added += insert(lo, hi.min(gap_lo - 1));
+                }
+                if hi > gap_hi {
+                    added += insert(lo.max(gap_hi + 1), hi);
+                }
+                added
             }
+            None => 0,
         }
-        true
+    }
+}
+
+/// Passes the encodings of the signed values from `lo` to `hi` inclusive
+/// to `insert` as inclusive runs, returning the total it reports.
+///
+/// [`Fits64::to_u64`] encodes the values `-n..0` as the odd numbers below
+/// `2n`, and `0..p` as the even numbers below `2p`, so a range that spans
+/// zero has consecutive encodings up to twice its shorter side, and every
+/// other encoding beyond.
+fn insert_signed(lo: i64, hi: i64, mut insert: impl FnMut(u64, u64) -> usize) -> usize {
+    fn every_other(insert: &mut impl FnMut(u64, u64) -> usize, from: u64, to: u64) -> usize {
+        (from..=to).step_by(2).map(|x| insert(x, x)).sum()
+    }
+    if hi < 0 {
+        return every_other(&mut insert, hi.to_u64(), lo.to_u64());
+    }
+    if lo >= 0 {
+        return every_other(&mut insert, lo.to_u64(), hi.to_u64());
+    }
+    let (n, p) = ((!lo) as u64 + 1, hi as u64 + 1);
+    let both = n.min(p);
+    let mut added = insert(0, 2 * (both - 1) + 1);
+    if n > both {
+        added += every_other(&mut insert, 2 * both + 1, 2 * (n - 1) + 1);
+    } else if p > both {
+        added += every_other(&mut insert, 2 * both, 2 * (p - 1));
+    }
+    added
+}
+
+impl<T: Fits64> PartialEq for Set64<T> {
+    fn eq(&self, other: &Set64<T>) -> bool {
+        self.0 == other.0
     }
 }
 impl<T: Fits64> Eq for Set64<T> {}
 
+impl<T: Fits64 + Ord> PartialOrd for Set64<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Fits64 + Ord> Ord for Set64<T> {
+    /// Compares the elements in ascending order of `T`, just like a
+    /// `BTreeSet`.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        cmp_as::<T>(&self.0, &other.0)
+    }
+}
+
+/// Compares two sets as their elements would compare when sorted as `T`,
+/// which need not be the order of [`Fits64::to_u64`].
+pub(crate) fn cmp_as<T: Fits64 + Ord>(a: &crate::SetU64, b: &crate::SetU64) -> std::cmp::Ordering {
+    let decode = |e| unsafe { T::from_u64(e) };
+    crate::copyset::cmp_unsorted(
+        || a.iter().map(decode),
+        || b.iter().map(decode),
+        |x: T| a.contains(x.to_u64()),
+        |x: T| b.contains(x.to_u64()),
+    )
+}
+
 impl<T: Fits64> std::hash::Hash for Set64<T> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let mut membs: Vec<u64> = self.iter().map(|i| i.to_u64()).collect();
-        membs.sort();
-        for memb in membs {
-            memb.hash(state);
-        }
+        self.0.hash(state)
     }
 }
 
 impl<T: Fits64> std::iter::FromIterator<T> for Set64<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let iter = iter.into_iter();
-        let (sz, _) = iter.size_hint();
-        let mut c = Set64::with_capacity(sz);
-        for i in iter {
-            c.insert(i);
-        }
-        c
+        Set64(iter.into_iter().map(|x| x.to_u64()).collect(), PhantomData)
     }
 }
 
@@ -282,6 +597,34 @@ impl<T: Fits64> Iterator for IntoIter<T> {
     }
 }
 
+/// An iterator in ascending order of [`Fits64::to_u64`], which can skip
+/// ahead to a given value.
+///
+/// This is numerical order for unsigned integers and `char`.  See
+/// [`setu64::Cursor`](crate::setu64::Cursor).
+#[derive(Debug, Clone)]
+pub struct Cursor<'a, T: Fits64>(crate::setu64::Cursor<'a>, PhantomData<T>);
+
+impl<'a, T: Fits64> Cursor<'a, T> {
+    /// The next element, without advancing.
+    pub fn peek(&mut self) -> Option<T> {
+        self.0.peek().map(|x| unsafe { T::from_u64(x) })
+    }
+    /// Advances past every element before `x`, returning the first
+    /// element that remains.
+    pub fn seek(&mut self, x: T) -> Option<T> {
+        self.0.seek(x.to_u64()).map(|x| unsafe { T::from_u64(x) })
+    }
+}
+
+impl<'a, T: Fits64> Iterator for Cursor<'a, T> {
+    type Item = T;
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.0.next().map(|x| unsafe { T::from_u64(x) })
+    }
+}
+
 impl<T: Fits64> IntoIterator for Set64<T> {
     type Item = T;
     type IntoIter = IntoIter<T>;
@@ -315,13 +658,7 @@ impl<'a, 'b, T: Fits64> std::ops::Sub<&'b Set64<T>> for &'a Set64<T> {
     /// assert_eq!(i, expected.len());
     /// ```
     fn sub(self, rhs: &Set64<T>) -> Set64<T> {
-        let mut s = Set64::with_capacity(self.len());
-        for v in self.iter() {
-            if !rhs.contains(&v) {
-                s.insert(v);
-            }
-        }
-        s
+        Set64(&self.0 - &rhs.0, PhantomData)
     }
 }
 
@@ -345,10 +682,7 @@ impl<T: Fits64> Extend<T> for Set64<T> {
     /// assert_eq!(i, expected.len());
     /// ```
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
-        let iter = iter.into_iter();
-        for i in iter {
-            self.insert(i);
-        }
+        self.0.extend(iter.into_iter().map(|x| x.to_u64()))
     }
 }
 
@@ -359,14 +693,30 @@ mod serde {
     use serde::ser::{Serialize, SerializeSeq, Serializer};
     use std::marker::PhantomData;
 
-    impl<T: Fits64 + Serialize> Serialize for Set64<T> {
+    impl<T: Fits64 + Ord + Serialize> Serialize for Set64<T> {
+        /// The elements are written in ascending order of `T`, as for a
+        /// `BTreeSet`.
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: Serializer,
         {
             let mut seq = serializer.serialize_seq(Some(self.len()))?;
-            for e in self.iter() {
-                seq.serialize_element(&e)?;
+            let mut rest = self.iter();
+            let ascending = rest.next().map_or(true, |mut prev| {
+                rest.all(|e| std::mem::replace(&mut prev, e) < e)
+            });
+            if ascending {
+                // Small and dense sets of unsigned integers or chars are
+                // already in order.
+                for e in self.iter() {
+                    seq.serialize_element(&e)?;
+                }
+            } else {
+                let mut v: Vec<T> = self.iter().collect();
+                v.sort_unstable();
+                for e in v {
+                    seq.serialize_element(&e)?;
+                }
             }
             seq.end()
         }
@@ -455,6 +805,38 @@ mod serde {
         let s = serde_json::to_string(&set).unwrap();
         assert_eq!(set, serde_json::from_str(&s).unwrap());
     }
+
+    #[test]
+    fn serialize_sorted() {
+        for v in [
+            vec![3, 1, 2],
+            (0..1000).rev().map(|x| x * 7).collect::<Vec<_>>(),
+            (0..1000).map(|x| x * 7919 % 100003).collect(),
+            vec![u64::MAX, 0, 5, 1 << 20],
+        ] {
+            let set: Set64<u64> = v.iter().cloned().collect();
+            let mut sorted = v.clone();
+            sorted.sort();
+            sorted.dedup();
+            let s = serde_json::to_string(&set).unwrap();
+            assert_eq!(s, serde_json::to_string(&sorted).unwrap());
+            assert_eq!(set, serde_json::from_str(&s).unwrap());
+        }
+    }
+
+    #[test]
+    fn serialize_in_order_of_t() {
+        let set: Set64<i32> = [-3, -1, 0, 2].iter().cloned().collect();
+        assert_eq!(serde_json::to_string(&set).unwrap(), "[-3,-1,0,2]");
+
+        let v: Vec<i64> = (-1000..1000).map(|x| x * 7919 % 100003).collect();
+        let set: Set64<i64> = v.iter().cloned().collect();
+        let mut sorted = v.clone();
+        sorted.sort();
+        let s = serde_json::to_string(&set).unwrap();
+        assert_eq!(s, serde_json::to_string(&sorted).unwrap());
+        assert_eq!(set, serde_json::from_str(&s).unwrap());
+    }
 }
 
 impl<'a, 'b, T: Fits64> std::ops::BitOr<&'b Set64<T>> for &'a Set64<T> {
@@ -481,14 +863,27 @@ impl<'a, 'b, T: Fits64> std::ops::BitOr<&'b Set64<T>> for &'a Set64<T> {
     /// assert_eq!(i, expected.len());
     /// ```
     fn bitor(self, rhs: &Set64<T>) -> Set64<T> {
-        let mut s: Set64<T> = Set64::with_capacity(self.len() + rhs.len());
-        for x in self.iter() {
-            s.insert(x);
-        }
-        for x in rhs.iter() {
-            s.insert(x);
-        }
-        s
+        Set64(&self.0 | &rhs.0, PhantomData)
+    }
+}
+
+impl<T: Fits64> std::ops::BitAnd<&Set64<T>> for &Set64<T> {
+    type Output = Set64<T>;
+
+    /// Returns the intersection of `self` and `rhs` as a new `Set64<T>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinyset::Set64;
+    ///
+    /// let a: Set64<u32> = vec![1, 2, 3].into_iter().collect();
+    /// let b: Set64<u32> = vec![3, 4, 5].into_iter().collect();
+    ///
+    /// assert_eq!(&a & &b, vec![3].into_iter().collect());
+    /// ```
+    fn bitand(self, rhs: &Set64<T>) -> Set64<T> {
+        Set64(&self.0 & &rhs.0, PhantomData)
     }
 }
 
@@ -537,3 +932,211 @@ proptest! {
         crate::copyset::check_set::<Set64<u8>>(&slice);
     }
 }
+
+#[test]
+fn from_sorted() {
+    let v: Vec<char> = ('a'..='z').chain('α'..='ω').collect();
+    let mut a = Set64::from_sorted_slice(&v[..10]);
+    a.extend_sorted(v[5..].iter().cloned());
+    assert_eq!(a, v.iter().cloned().collect());
+    assert_eq!(Set64::from_sorted_iter(0u8..=255).len(), 256);
+}
+
+#[test]
+fn batch_queries() {
+    let s: Set64<i8> = (-50..50).collect();
+    assert!(s.contains_all(&[-50, 0, 49]));
+    assert!(!s.contains_all(&[-51, 0]));
+    assert!(s.contains_any(&[-128, 127, -1]));
+    assert_eq!(s.count_contained(&[-100, -50, 50, 49]), 2);
+    assert_eq!(s.filter_contained(&[-100, -50, 50, 49]), vec![-50, 49]);
+}
+
+#[test]
+fn ranges() {
+    use std::collections::BTreeSet;
+    let mut s: Set64<u16> = Set64::new();
+    assert_eq!(s.insert_range(0..=u16::MAX), 1 << 16);
+    assert_eq!(s.remove_range(1000..), (1 << 16) - 1000);
+    assert_eq!(s, (0..1000).collect());
+
+    let mut s: Set64<char> = ('a'..='z').collect();
+    assert_eq!(s.insert_range('x'..='ä'), ('{'..='ä').count());
+    let mut gone: Vec<char> = s.drain_range('b'..'e').collect();
+    gone.sort();
+    assert_eq!(gone, vec!['b', 'c', 'd']);
+
+    let v: Vec<i64> = (-1000..1000).map(|x| x * 7).collect();
+    let mut expected: BTreeSet<i64> = v.iter().cloned().collect();
+    let mut s: Set64<i64> = v.iter().cloned().collect();
+    let before = expected.len();
+    expected.extend(-5000..-4000);
+    assert_eq!(s.insert_range(-5000..-4000), expected.len() - before);
+    let before = expected.len();
+    expected.retain(|x| !(-4500..=100).contains(x));
+    assert_eq!(s.remove_range(-4500..=100), before - expected.len());
+    assert_eq!(s, expected.iter().cloned().collect());
+}
+
+#[test]
+fn ranges_reach_the_bounds() {
+    let mut s: Set64<u8> = Set64::new();
+    assert_eq!(s.insert_range(250u8..), 6);
+    assert_eq!(s.insert_range(..10), 10);
+    assert_eq!(s.insert_range(..), 256 - 16);
+    assert_eq!(s.insert_range(3..3), 0);
+
+    let mut s: Set64<i8> = Set64::new();
+    assert_eq!(s.insert_range(120i8..), 8);
+    assert_eq!(s.insert_range(..-120), 8);
+    assert_eq!(s.insert_range(..), 256 - 16);
+    assert_eq!(s.len(), 256);
+
+    let mut s: Set64<i64> = Set64::new();
+    assert_eq!(s.insert_range(i64::MAX - 2..), 3);
+    assert_eq!(s.insert_range(..=i64::MIN + 2), 3);
+    assert_eq!(s.insert_range(-1000..1000), 2000);
+    assert_eq!(s.len(), 2006);
+
+    let mut s: Set64<char> = Set64::new();
+    assert_eq!(s.insert_range('\u{D7FE}'..='\u{E001}'), 4);
+    assert_eq!(s.insert_range(char::MAX..), 1);
+    assert_eq!(s.len(), 5);
+}
+
+/// Checks `insert_range` against inserting `values`, which are those in
+/// `range`.
+#[cfg(test)]
+fn check_insert_range<T, R>(v: &[T], range: R, values: impl Iterator<Item = T>)
+where
+    T: Fits64 + Ord + std::fmt::Debug,
+    Set64<T>: InsertRange<T, R>,
+{
+    use std::collections::BTreeSet;
+    let mut expected: BTreeSet<T> = v.iter().cloned().collect();
+    let mut s: Set64<T> = v.iter().cloned().collect();
+    let before = expected.len();
+    expected.extend(values);
+    assert_eq!(s.ins_range(range), expected.len() - before);
+    assert_eq!(s, expected.into_iter().collect());
+}
+
+/// Lets the range tests run over the types that have `insert_range`.
+#[cfg(test)]
+trait InsertRange<T, R> {
+    fn ins_range(&mut self, range: R) -> usize;
+}
+#[cfg(test)]
+macro_rules! impl_test_insert_range {
+    ($($ty: ty),*) => {$(
+impl<R: std::ops::RangeBounds<$ty>> InsertRange<$ty, R> for Set64<$ty> {
+    fn ins_range(&mut self, range: R) -> usize {
+        self.insert_range(range)
+    }
+}
+    )*};
+}
+#[cfg(test)]
+impl_test_insert_range!(u8, u32, i8, i32, char);
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn insert_range_matches_btreeset(v: Vec<i8>, a: i8, b: i8) {
+        check_insert_range(&v, a..b, a..b);
+        check_insert_range(&v, a..=b, a..=b);
+        check_insert_range(&v, a.., a..=i8::MAX);
+    }
+    #[test]
+    fn insert_range_matches_btreeset_wide(v: Vec<i32>, a in -3000..3000i32, b in -3000..3000i32) {
+        check_insert_range(&v, a..b, a..b);
+        check_insert_range(&v, a..=b, a..=b);
+    }
+    #[test]
+    fn insert_range_matches_btreeset_unsigned(v: Vec<u32>, a in 0..3000u32, b in 0..3000u32,
+                                              c: Vec<u8>, d: u8) {
+        check_insert_range(&v, a..b, a..b);
+        check_insert_range(&c, d.., d..=u8::MAX);
+    }
+    #[test]
+    fn insert_range_matches_btreeset_char(v: Vec<char>, a in 0xD000..0xF000u32, n in 0..0x2000u32) {
+        let a = char::from_u32(a).unwrap_or('\u{E000}');
+        let b = char::from_u32(a as u32 + n).unwrap_or('\u{E000}');
+        check_insert_range(&v, a..b, a..b);
+        check_insert_range(&v, a..=b, a..=b);
+    }
+}
+
+#[test]
+fn cursor() {
+    let a: Set64<u32> = (0..1000).map(|x| x * 5).collect();
+    let mut c = a.cursor();
+    assert_eq!(c.peek(), Some(0));
+    assert_eq!(c.seek(12), Some(15));
+    assert_eq!(c.next(), Some(15));
+    assert_eq!(c.seek(4990), Some(4990));
+    assert_eq!(c.collect::<Vec<_>>(), vec![4990, 4995]);
+    let a: Set64<char> = ('a'..='z').chain('α'..='ω').collect();
+    assert_eq!(a.iter_from('y').take(3).collect::<String>(), "yzα");
+}
+
+#[test]
+fn complement() {
+    let live: Set64<u8> = [1, 3, 4].iter().cloned().collect();
+    assert_eq!(live.complement(0..6), [0, 2, 5].iter().cloned().collect());
+    let mut s: Set64<u8> = (0..=255).filter(|x| x % 5 != 0).collect();
+    s.complement_in_place(255);
+    assert_eq!(s, (0..255).filter(|x| x % 5 == 0).collect());
+    let s: Set64<usize> = (0..1000).collect();
+    assert_eq!(s.complement(0..1001), Some(1000).into_iter().collect());
+}
+
+#[test]
+fn hash_matches_eq() {
+    use std::hash::{Hash, Hasher};
+    let hash_of = |s: &Set64<char>| {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        s.hash(&mut h);
+        h.finish()
+    };
+    let a: Set64<char> = ('a'..='z').collect();
+    let mut b: Set64<char> = ('a'..='z').rev().chain('α'..='ω').collect();
+    for c in 'α'..='ω' {
+        b.remove(&c);
+    }
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+    b.remove(&'q');
+    assert_ne!(hash_of(&a), hash_of(&b));
+}
+
+#[cfg(test)]
+fn check_cmp<T: Fits64 + Ord + std::fmt::Debug>(a: &[T], b: &[T]) {
+    use std::collections::BTreeSet;
+    let (sa, sb): (Set64<T>, Set64<T>) = (a.iter().cloned().collect(), b.iter().cloned().collect());
+    let (ba, bb): (BTreeSet<T>, BTreeSet<T>) =
+        (a.iter().cloned().collect(), b.iter().cloned().collect());
+    assert_eq!(sa.cmp(&sb), ba.cmp(&bb), "{:?} vs {:?}", ba, bb);
+}
+
+#[test]
+fn ordering() {
+    check_cmp::<i8>(&[-1, 1], &[-2, 1]);
+    check_cmp::<i8>(&[-1, 1], &[-1, 1, 2]);
+    check_cmp::<i8>(&[-1, 1, 2], &[-1, 1]);
+    check_cmp::<i32>(&[5, -3], &[-3, 5]);
+    check_cmp::<char>(&['b'], &['a', 'c']);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn ordering_matches_btreeset(a in prop::collection::vec(-20i8..20, 0usize..20),
+                                 b in prop::collection::vec(-20i8..20, 0usize..20)) {
+        check_cmp(&a, &b);
+    }
+    #[test]
+    fn ordering_matches_btreeset_i64(a: Vec<i64>, b: Vec<i64>) {
+        check_cmp(&a, &b);
+    }
+}
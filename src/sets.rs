@@ -1,3 +1,6 @@
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{BuildHasher, Hash};
+
 macro_rules! generic_set {
     () => {
         /// Returns true if the set is empty
@@ -14,9 +17,352 @@ impl crate::SetU64 {
 impl crate::SetU32 {
     generic_set!();
 }
+impl crate::SetU32b {
+    generic_set!();
+}
 impl crate::SetUsize {
     generic_set!();
 }
 impl<T: crate::Fits64> crate::Set64<T> {
     generic_set!();
 }
+impl<T: crate::Fits64> crate::ArcSet64<T> {
+    generic_set!();
+}
+
+/// The operations shared by all of the set types, so that an algorithm
+/// can be written once and run on whichever set suits the data.
+///
+/// It is also implemented for `HashSet` and `BTreeSet` of `Copy` items,
+/// which makes it easy to compare against the standard library.
+///
+/// ```
+/// use tinyset::{Set64, SetU32, TinySet};
+/// use std::collections::HashSet;
+///
+/// fn evens<S: TinySet<Item = u32>>(s: &S) -> usize {
+///     s.iter().filter(|x| x % 2 == 0).count()
+/// }
+///
+/// let mut a = SetU32::new();
+/// let mut b: HashSet<u32> = HashSet::new();
+/// for x in 0..10 {
+///     TinySet::insert(&mut a, x);
+///     TinySet::insert(&mut b, x);
+/// }
+/// assert_eq!(evens(&a), 5);
+/// assert_eq!(evens(&b), 5);
+/// assert_eq!(Set64::<u32>::default().union(&(0..3).collect()).len(), 3);
+/// ```
+///
+/// `iter` and `drain` return boxed iterators, since this crate supports
+/// compilers that predate generic associated types.  The inherent
+/// methods of each set avoid that allocation.
+pub trait TinySet: Default + Clone {
+    /// The type of the elements.
+    type Item: Copy;
+
+    /// Insert an element, returning true if it was not already present.
+    fn insert(&mut self, e: Self::Item) -> bool;
+    /// Remove an element, returning true if it was present.
+    fn remove(&mut self, e: Self::Item) -> bool;
+    /// Whether the set contains `e`.
+    fn contains(&self, e: Self::Item) -> bool;
+    /// The number of elements in the set.
+    fn len(&self) -> usize;
+    /// Returns true if the set is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Iterate over the elements, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = Self::Item> + '_>;
+    /// Remove and iterate over all of the elements.
+    fn drain(&mut self) -> Box<dyn Iterator<Item = Self::Item> + '_>;
+    /// An empty set with room for the elements of `other`.
+    fn with_capacity_of(other: &Self) -> Self;
+    /// The total memory used by the set, including its own size.  For
+    /// the standard collections this is an estimate.
+    fn mem_used(&self) -> usize;
+
+    /// The elements in either set.
+    fn union(&self, other: &Self) -> Self {
+        let (mut s, small) = if self.len() < other.len() {
+            (other.clone(), self)
+        } else {
+            (self.clone(), other)
+        };
+        for x in small.iter() {
+            s.insert(x);
+        }
+        s
+    }
+    /// The elements in both sets.
+    fn intersection(&self, other: &Self) -> Self {
+        let (small, big) = if self.len() < other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut s = Self::with_capacity_of(small);
+        for x in small.iter().filter(|&x| big.contains(x)) {
+            s.insert(x);
+        }
+        s
+    }
+    /// The elements in `self` but not in `other`.
+    fn difference(&self, other: &Self) -> Self {
+        let mut s = Self::with_capacity_of(self);
+        for x in self.iter().filter(|&x| !other.contains(x)) {
+            s.insert(x);
+        }
+        s
+    }
+    /// Whether every element of `self` is in `other`.
+    fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.iter().all(|x| other.contains(x))
+    }
+    /// Whether every element of `other` is in `self`.
+    fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+    /// Whether the sets have no elements in common.
+    fn is_disjoint(&self, other: &Self) -> bool {
+        let (small, big) = if self.len() < other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        !small.iter().any(|x| big.contains(x))
+    }
+}
+
+/// Implements [`TinySet`] with the inherent methods of a set type, using
+/// its operators for the set algebra.
+macro_rules! impl_tinyset {
+    ($ty: ty, $item: ty) => {
+        impl TinySet for $ty {
+            type Item = $item;
+            #[inline]
+            fn insert(&mut self, e: $item) -> bool {
+                <$ty>::insert(self, e)
+            }
+            #[inline]
+            fn remove(&mut self, e: $item) -> bool {
+                <$ty>::remove(self, e)
+            }
+            #[inline]
+            fn contains(&self, e: $item) -> bool {
+                <$ty>::contains(self, e)
+            }
+            #[inline]
+            fn len(&self) -> usize {
+                <$ty>::len(self)
+            }
+            fn iter(&self) -> Box<dyn Iterator<Item = $item> + '_> {
+                Box::new(<$ty>::iter(self))
+            }
+            fn drain(&mut self) -> Box<dyn Iterator<Item = $item> + '_> {
+                Box::new(<$ty>::drain(self))
+            }
+            fn with_capacity_of(other: &Self) -> Self {
+                <$ty>::with_capacity_of(other)
+            }
+            fn mem_used(&self) -> usize {
+                <$ty>::mem_used(self)
+            }
+            fn union(&self, other: &Self) -> Self {
+                self | other
+            }
+            fn intersection(&self, other: &Self) -> Self {
+                self & other
+            }
+            fn difference(&self, other: &Self) -> Self {
+                self - other
+            }
+        }
+    };
+}
+
+impl_tinyset!(crate::SetU64, u64);
+impl_tinyset!(crate::SetU32, u32);
+impl_tinyset!(crate::SetU32b, u32);
+impl_tinyset!(crate::SetUsize, usize);
+
+impl<T: crate::Fits64> TinySet for crate::Set64<T> {
+    type Item = T;
+    #[inline]
+    fn insert(&mut self, e: T) -> bool {
+        crate::Set64::insert(self, e)
+    }
+    #[inline]
+    fn remove(&mut self, e: T) -> bool {
+        crate::Set64::remove(self, &e)
+    }
+    #[inline]
+    fn contains(&self, e: T) -> bool {
+        crate::Set64::contains(self, e)
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        crate::Set64::len(self)
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = T> + '_> {
+        Box::new(crate::Set64::iter(self))
+    }
+    fn drain(&mut self) -> Box<dyn Iterator<Item = T> + '_> {
+        Box::new(crate::Set64::drain(self))
+    }
+    fn with_capacity_of(other: &Self) -> Self {
+        let mut s = crate::Set64::new();
+        s.0 = crate::SetU64::with_capacity_of(&other.0);
+        s
+    }
+    fn mem_used(&self) -> usize {
+        self.0.mem_used()
+    }
+    fn union(&self, other: &Self) -> Self {
+        self | other
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        self & other
+    }
+    fn difference(&self, other: &Self) -> Self {
+        self - other
+    }
+}
+
+impl<T, S> TinySet for HashSet<T, S>
+where
+    T: Copy + Eq + Hash,
+    S: BuildHasher + Default + Clone,
+{
+    type Item = T;
+    #[inline]
+    fn insert(&mut self, e: T) -> bool {
+        HashSet::insert(self, e)
+    }
+    #[inline]
+    fn remove(&mut self, e: T) -> bool {
+        HashSet::remove(self, &e)
+    }
+    #[inline]
+    fn contains(&self, e: T) -> bool {
+        HashSet::contains(self, &e)
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        HashSet::len(self)
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = T> + '_> {
+        Box::new(HashSet::iter(self).cloned())
+    }
+    fn drain(&mut self) -> Box<dyn Iterator<Item = T> + '_> {
+        Box::new(HashSet::drain(self))
+    }
+    fn with_capacity_of(other: &Self) -> Self {
+        HashSet::with_capacity_and_hasher(other.capacity(), S::default())
+    }
+    /// Counts one control byte per bucket, as in the current standard
+    /// library.
+    fn mem_used(&self) -> usize {
+        let buckets = if self.capacity() == 0 {
+            0
+        } else {
+            (self.capacity() * 8 / 7).next_power_of_two()
+        };
+        std::mem::size_of::<Self>() + buckets * (std::mem::size_of::<T>() + 1)
+    }
+}
+
+impl<T: Copy + Ord> TinySet for BTreeSet<T> {
+    type Item = T;
+    #[inline]
+    fn insert(&mut self, e: T) -> bool {
+        BTreeSet::insert(self, e)
+    }
+    #[inline]
+    fn remove(&mut self, e: T) -> bool {
+        BTreeSet::remove(self, &e)
+    }
+    #[inline]
+    fn contains(&self, e: T) -> bool {
+        BTreeSet::contains(self, &e)
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        BTreeSet::len(self)
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = T> + '_> {
+        Box::new(BTreeSet::iter(self).cloned())
+    }
+    fn drain(&mut self) -> Box<dyn Iterator<Item = T> + '_> {
+        Box::new(std::mem::take(self).into_iter())
+    }
+    fn with_capacity_of(_other: &Self) -> Self {
+        BTreeSet::new()
+    }
+    /// Assumes full nodes of eleven elements, so this is a lower bound.
+    fn mem_used(&self) -> usize {
+        let nodes = (self.len() + 10) / 11;
+        std::mem::size_of::<Self>() + nodes * (11 * std::mem::size_of::<T>() + 16)
+    }
+}
+
+#[cfg(test)]
+fn check_tinyset<S: TinySet + std::fmt::Debug>()
+where
+    S::Item: From<u8> + Ord + std::fmt::Debug,
+{
+    let sorted = |s: &S| {
+        let mut v: Vec<S::Item> = s.iter().collect();
+        v.sort();
+        v
+    };
+    let elems = |r: std::ops::Range<u8>, step: usize| -> Vec<S::Item> {
+        r.step_by(step).map(S::Item::from).collect()
+    };
+    let mut a = S::default();
+    for x in elems(0..200, 2) {
+        assert!(a.insert(x));
+        assert!(!a.insert(x));
+    }
+    let mut b = S::default();
+    for x in elems(0..200, 3) {
+        b.insert(x);
+    }
+    assert_eq!(a.len(), 100);
+    assert!(a.contains(S::Item::from(4)) && !a.contains(S::Item::from(5)));
+    assert!(a.mem_used() >= std::mem::size_of::<S>());
+    assert_eq!(sorted(&a.union(&b)), {
+        let mut v = elems(0..200, 2);
+        v.extend(elems(0..200, 3));
+        v.sort();
+        v.dedup();
+        v
+    });
+    assert_eq!(sorted(&a.intersection(&b)), elems(0..200, 6));
+    assert_eq!(a.difference(&b).len(), 100 - 34);
+    assert!(a.intersection(&b).is_subset(&a));
+    assert!(b.is_superset(&a.intersection(&b)));
+    assert!(a.difference(&b).is_disjoint(&b));
+    assert!(!a.is_disjoint(&b));
+    assert!(S::with_capacity_of(&a).is_empty());
+    assert!(a.remove(S::Item::from(4)));
+    assert!(!a.remove(S::Item::from(4)));
+    let mut drained: Vec<S::Item> = a.drain().collect();
+    drained.sort();
+    assert_eq!(drained.len(), 99);
+    assert!(a.is_empty());
+}
+
+#[test]
+fn tinyset_impls() {
+    check_tinyset::<crate::SetU64>();
+    check_tinyset::<crate::SetU32>();
+    check_tinyset::<crate::SetU32b>();
+    check_tinyset::<crate::SetUsize>();
+    check_tinyset::<crate::Set64<u16>>();
+    check_tinyset::<crate::Set64<i64>>();
+    check_tinyset::<HashSet<u32>>();
+    check_tinyset::<BTreeSet<u64>>();
+}
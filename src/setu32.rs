@@ -4,6 +4,12 @@
 mod iter;
 pub use iter::IntoIter;
 
+mod view;
+pub use view::SetU32Ref;
+
+mod cursor;
+pub use cursor::Cursor;
+
 const fn num_bits<T>() -> u32 {
     std::mem::size_of::<T>() as u32 * 8
 }
@@ -70,7 +76,7 @@ struct S {
 }
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct Sbeginning {
     sz: u32,
     cap: u32,
@@ -109,10 +115,11 @@ impl Iterator for Tiny {
         }
     }
     fn count(self) -> usize {
-        self.sz as usize
+        (self.sz - self.sz_spent) as usize
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.sz as usize, Some(self.sz as usize))
+        let left = (self.sz - self.sz_spent) as usize;
+        (left, Some(left))
     }
     fn min(mut self) -> Option<u32> {
         self.next()
@@ -399,20 +406,198 @@ impl crate::copyset::CopySet for SetU32 {
     }
 }
 
+/// Something that holds a set in our internal representation, either a
+/// [`SetU32`] or a borrowed view of one.
+trait Storage {
+    fn internal(&self) -> Internal<'_>;
+}
+
+impl Storage for SetU32 {
+    #[inline]
+    fn internal(&self) -> Internal<'_> {
+        SetU32::internal(self)
+    }
+}
+
+impl<T: Storage + ?Sized> Storage for &T {
+    #[inline]
+    fn internal(&self) -> Internal<'_> {
+        (**self).internal()
+    }
+}
+
+impl<'a> Internal<'a> {
+    fn from_parts(b: &'a Sbeginning, a: &'a [u32]) -> Self {
+        if b.bits == 0 || b.bits > 32 {
+            Internal::Big { s: b, a }
+        } else if b.bits == 32 {
+            Internal::Dense { sz: b.sz, a }
+        } else {
+            Internal::Heap { s: b, a }
+        }
+    }
+    fn len(&self) -> usize {
+        match *self {
+            Internal::Empty => 0,
+            Internal::Stack(t) => t.sz as usize,
+            Internal::Heap { s, .. } => s.sz as usize,
+            Internal::Big { s, .. } => s.sz as usize,
+            Internal::Dense { sz, .. } => sz as usize,
+        }
+    }
+    fn contains(&self, e: u32) -> bool {
+        match *self {
+            Internal::Empty => false,
+            Internal::Stack(t) => t.contains(e),
+            Internal::Dense { a, .. } => {
+                let key = e >> 5;
+                if let Some(bits) = a.get(key as usize) {
+                    bits & (1 << (e & 31)) != 0
+                } else {
+                    false
+                }
+            }
+            Internal::Heap { s, a } => {
+                if compute_array_bits(e) < s.bits {
+                    // println!("too big a thing");
+                    return false;
+                }
+                let (key, offset) = split_u32(e, s.bits);
+                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
+                    a[idx] & (1 << offset) != 0
+                } else {
+                    // println!("did not find key {} from {}: {:#?}", key, e, self);
+                    false
+                }
+            }
+            Internal::Big { s, a } => {
+                if e == s.bits {
+                    return false;
+                }
+                let e = if e == 0 { s.bits } else { e };
+                p_lookfor(e, a, 0).key_found()
+            }
+        }
+    }
+    /// Calls `f` with each value and whether it is in the set, stopping
+    /// early if `f` returns false.  Consecutive values that fall in the
+    /// same dense word or heap bucket share a single lookup, so sorted
+    /// input walks through the set in order.
+    fn probe(&self, vals: impl Iterator<Item = u32>, mut f: impl FnMut(u32, bool) -> bool) {
+        match *self {
+            Internal::Dense { a, .. } => {
+                for e in vals {
+                    let bits = a.get((e >> 5) as usize).cloned().unwrap_or(0);
+                    if !f(e, bits & (1 << (e & 31)) != 0) {
+                        return;
+                    }
+                }
+            }
+            Internal::Heap { s, a } => {
+                let mut bucket = None;
+                for e in vals {
+                    let found = if compute_array_bits(e) < s.bits {
+                        false
+                    } else {
+                        let (key, offset) = split_u32(e, s.bits);
+                        let bitmap = match bucket {
+                            Some((k, bitmap)) if k == key => bitmap,
+                            _ => {
+                                let bitmap = match p_lookfor(key, a, s.bits) {
+                                    LookedUp::KeyFound(idx) => a[idx],
+                                    _ => 0,
+                                };
+                                bucket = Some((key, bitmap));
+                                bitmap
+                            }
+                        };
+                        bitmap & (1 << offset) != 0
+                    };
+                    if !f(e, found) {
+                        return;
+                    }
+                }
+            }
+            _ => {
+                for e in vals {
+                    if !f(e, self.contains(e)) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How many elements `collect` and `extend` buffer and sort at a time.
+/// This bounds the memory they need beyond that of the set itself.
+const CHUNK: usize = 4096;
+
 impl Extend<u32> for SetU32 {
+    /// Adds the elements a chunk at a time, so the iterator is never
+    /// collected into one big buffer.  An empty set is sized for the
+    /// first chunk, and grows as needed for the rest.
     fn extend<T: IntoIterator<Item = u32>>(&mut self, iter: T) {
-        for i in iter.into_iter() {
-            self.insert(i);
+        let mut iter = iter.into_iter();
+        let mut buf = Vec::with_capacity(iter.size_hint().0.clamp(1, CHUNK));
+        loop {
+            buf.extend(iter.by_ref().take(CHUNK));
+            if buf.is_empty() {
+                return;
+            }
+            if self.0.is_null() {
+                let ascending = buf.windows(2).all(|w| w[0] < w[1]);
+                if !ascending {
+                    buf.sort_unstable();
+                    buf.dedup();
+                }
+                // Only trust the hint when it is exact and the input
+                // arrived in order.
+                let more = match iter.size_hint() {
+                    (lo, Some(hi)) if lo == hi && ascending => lo,
+                    _ => 0,
+                };
+                *self = SetU32::from_sorted_deduped(&buf, more);
+            } else {
+                for &x in buf.iter() {
+                    self.insert(x);
+                }
+            }
+            buf.clear();
         }
     }
 }
 
-#[cfg(feature = "compactserde")]
 impl SetU32 {
+    /// Returns the set in the compact layout read by [`SetU32Ref`].
+    ///
+    /// The layout is the in-memory representation of the set, so it
+    /// depends on the platform and is not stable between versions of
+    /// `tinyset`.
+    ///
+    /// ```
+    /// use tinyset::{SetU32, SetU32Ref};
+    ///
+    /// let a: SetU32 = (0..1000).map(|x| x * 3).collect();
+    /// let words = a.to_compact();
+    /// let view = SetU32Ref::new(&words).unwrap();
+    /// assert_eq!(view.len(), 1000);
+    /// assert!(view.contains(999));
+    /// assert!(!view.contains(1000));
+    /// ```
+    pub fn to_compact(&self) -> Vec<u32> {
+        self.to_array()
+    }
     fn to_array(&self) -> Vec<u32> {
         let mut out = Vec::new();
-        if self.0 as usize == 0 || self.0 as usize & 7 != 0 {
-            out.push(self.0 as u32);
+        if self.0 as usize == 0 || self.0 as usize & 3 != 0 {
+            // A stack set may need more than 32 bits, in which case we
+            // store it in two words.
+            let x = self.0 as usize as u64;
+            out.push(x as u32);
+            if x >> 32 != 0 {
+                out.push((x >> 32) as u32);
+            }
         } else {
             let s = unsafe { &*self.0 };
             let b = &s.b;
@@ -424,7 +609,7 @@ impl SetU32 {
         out
     }
     fn from_array(v: &[u32]) -> SetU32 {
-        if v.len() > 1 {
+        if v.len() > 2 {
             let cap = v.len() - 2;
             let mut set = SetU32::with_capacity_and_bits(cap, v[1]);
             match set.internal_mut() {
@@ -453,12 +638,12 @@ impl SetU32 {
             }
             set
         } else {
-            SetU32(v[0] as *mut S)
+            let x = v[0] as u64 | (v.get(1).cloned().unwrap_or(0) as u64) << 32;
+            SetU32(x as usize as *mut S)
         }
     }
 }
 
-#[cfg(feature = "compactserde")]
 #[test]
 fn to_from_array() {
     use std::iter::FromIterator;
@@ -509,13 +694,14 @@ mod serde {
             }
             seq.end()
         }
+        /// The elements are written in ascending order.
         #[cfg(not(feature = "compactserde"))]
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: Serializer,
         {
             let mut seq = serializer.serialize_seq(Some(self.len()))?;
-            for e in self.iter() {
+            for e in self.iter_sorted() {
                 seq.serialize_element(&e)?;
             }
             seq.end()
@@ -619,6 +805,25 @@ mod serde {
         let s = serde_json::to_string(&set).unwrap();
         assert_eq!(set, serde_json::from_str(&s).unwrap());
     }
+
+    #[cfg(not(feature = "compactserde"))]
+    #[test]
+    fn serialize_sorted() {
+        for v in [
+            vec![3, 1, 2],
+            (0..1000).rev().map(|x| x * 7).collect::<Vec<_>>(),
+            (0..1000).map(|x| x * 7919 % 100003).collect(),
+            vec![u32::MAX, 0, 5, 1 << 20],
+        ] {
+            let set: SetU32 = v.iter().cloned().collect();
+            let mut sorted = v.clone();
+            sorted.sort();
+            sorted.dedup();
+            let s = serde_json::to_string(&set).unwrap();
+            assert_eq!(s, serde_json::to_string(&sorted).unwrap());
+            assert_eq!(set, serde_json::from_str(&s).unwrap());
+        }
+    }
 }
 
 impl Clone for SetU32 {
@@ -688,13 +893,7 @@ impl SetU32 {
     /// The number of elements in the set
     #[inline]
     pub fn len(&self) -> usize {
-        match self.internal() {
-            Internal::Empty => 0,
-            Internal::Stack(t) => t.sz as usize,
-            Internal::Heap { s, .. } => s.sz as usize,
-            Internal::Big { s, .. } => s.sz as usize,
-            Internal::Dense { sz, .. } => sz as usize,
-        }
+        self.internal().len()
     }
     /// The capacity of the set
     #[inline]
@@ -707,54 +906,30 @@ impl SetU32 {
             Internal::Dense { a, .. } => a.len(),
         }
     }
-    /// Print debugging information about this set.
-    pub fn debug_me(&self, msg: &str) {
+    /// The name of the representation and the number of bits in each
+    /// entry of its table that hold a bitmap, for `{:#?}`.
+    pub(crate) fn layout(&self) -> (&'static str, u64) {
         match self.internal() {
-            Internal::Empty => println!("empty set: {}", msg),
-            Internal::Stack(t) => {
-                println!("{}: stack {:?} => {:?}", msg, t, t.collect::<Vec<_>>());
-                println!("    bits {:032b}", t.bits);
-            }
-            Internal::Heap { s, a } => {
-                println!("{}: heap {:?}", msg, s);
-                for (i, x) in a.iter().cloned().enumerate() {
-                    println!(
-                        "      {} (key {} pov {}): {:0b} ({})",
-                        (x >> s.bits) * s.bits,
-                        x >> s.bits,
-                        p_poverty(x >> s.bits, i, a.len()),
-                        x & mask(s.bits as usize),
-                        x
-                    );
-                }
-                println!("    => {:?}", self.iter().collect::<Vec<_>>());
-            }
-            Internal::Big { s, a } => {
-                println!("{}: big {:?}\n    {:?}", msg, s, a);
-                let v: Vec<_> = a.iter().cloned().map(|x| x % a.len() as u32).collect();
-                println!("     >>>{:?}", v);
-            }
-            Internal::Dense { sz, a } => {
-                println!(
-                    "{}: dense {:?}\n    {:?}\n    => {:?}",
-                    msg,
-                    sz,
-                    a,
-                    self.iter().collect::<Vec<u32>>()
-                );
-                println!("    foo {:?}", self.iter());
-            }
+            Internal::Empty => ("empty", 0),
+            Internal::Stack(_) => ("stack", 0),
+            Internal::Heap { s, .. } => ("heap", s.bits as u64),
+            Internal::Big { .. } => ("big", 0),
+            Internal::Dense { .. } => ("dense", 32),
         }
     }
-    /// Tally up how much memory is in use.
+    /// Tally up how much memory is in use, including the set itself.
     #[inline]
     pub fn mem_used(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_bytes()
+    }
+    /// The number of bytes allocated on the heap for this set.
+    #[inline]
+    pub fn heap_bytes(&self) -> usize {
         match self.internal() {
-            Internal::Empty => std::mem::size_of::<Self>(),
-            Internal::Stack(_) => std::mem::size_of::<Self>(),
-            Internal::Heap { s, .. } => std::mem::size_of::<Self>() + s.cap as usize * 4 - 4,
-            Internal::Dense { a, .. } => std::mem::size_of::<Self>() + a.len() * 4 - 4,
-            Internal::Big { s, .. } => std::mem::size_of::<Self>() + s.cap as usize * 4 - 4,
+            Internal::Empty | Internal::Stack(_) => 0,
+            Internal::Heap { a, .. } | Internal::Big { a, .. } | Internal::Dense { a, .. } => {
+                bytes_for_capacity(a.len())
+            }
         }
     }
     /// This requires that we currently be a dense! It also requires
@@ -829,8 +1004,12 @@ impl SetU32 {
                 (*x.0).b.cap = cap as u32;
                 (*x.0).b.bits = if bits == 0 {
                     let mut b = 0;
+                    let mut tries = 0;
                     while b <= 32 {
-                        b = crate::rand::rand32(cap as u32, bits);
+                        // A seeded random number depends only on its
+                        // arguments, so vary them when trying again.
+                        b = crate::rand::rand32(cap as u32, tries);
+                        tries += 1;
                     }
                     b
                 } else {
@@ -842,6 +1021,61 @@ impl SetU32 {
             SetU32(0 as *mut S)
         }
     }
+    /// A set of `sz` elements in the dense or hash-table layout given by
+    /// `bits`, whose table is copied from `words`.
+    fn from_raw_parts(sz: u32, bits: u32, words: impl ExactSizeIterator<Item = u32>) -> SetU32 {
+        debug_assert!(bits > 0 && bits <= 32);
+        if sz == 0 {
+            return SetU32::new();
+        }
+        let mut set = SetU32::with_capacity_and_bits(words.len(), bits);
+        match set.internal_mut() {
+            InternalMut::Dense { sz: n, a } => {
+                a.iter_mut().zip(words).for_each(|(w, x)| *w = x);
+                *n = sz;
+            }
+            InternalMut::Heap { s, a } => {
+                a.iter_mut().zip(words).for_each(|(w, x)| *w = x);
+                s.sz = sz;
+            }
+            _ => unreachable!(),
+        }
+        set
+    }
+    /// Widens the set to a [`SetU64`](crate::SetU64).  Dense and
+    /// hash-table layouts are copied word for word, since a `SetU64` finds
+    /// each bucket in the same slot, unless `keyed_hashing` gives the new
+    /// table a key.
+    pub(crate) fn to_setu64(&self) -> crate::SetU64 {
+        match self.internal() {
+            Internal::Dense { sz, a } => crate::SetU64::from_raw_parts(
+                sz as usize,
+                64,
+                a.chunks(2)
+                    .map(|c| c[0] as u64 | (c.get(1).cloned().unwrap_or(0) as u64) << 32),
+            ),
+            Internal::Heap { s, a } => crate::SetU64::from_raw_parts(
+                s.sz as usize,
+                s.bits as u64,
+                a.iter().map(|&w| w as u64),
+            ),
+            _ => self.iter().map(|x| x as u64).collect(),
+        }
+    }
+    /// Narrows a [`SetU64`](crate::SetU64), or returns `None` if it holds
+    /// an element too large for a `u32`.  A dense bitmap is split word for
+    /// word.
+    pub(crate) fn from_setu64(set: &crate::SetU64) -> Option<SetU32> {
+        if set.iter().max().map_or(false, |mx| mx > u32::MAX as u64) {
+            return None;
+        }
+        if let Some(a) = set.dense_words() {
+            let used = a.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+            let words = (0..2 * used).map(|i| (a[i / 2] >> (32 * (i % 2))) as u32);
+            return Some(SetU32::from_raw_parts(set.len() as u32, 32, words));
+        }
+        Some(set.iter().map(|x| x as u32).collect())
+    }
     /// An empty set
     #[inline]
     pub const fn new() -> Self {
@@ -867,13 +1101,13 @@ impl SetU32 {
                 let mx = t.max().unwrap();
                 let mx = if e > mx { e } else { mx };
                 *self = Self::with_capacity_and_max(t.sz as usize + 1, mx);
-                // self.debug_me("empty array");
+                // println!("empty array: {:#?}", self);
                 for x in t {
                     self.insert(x);
-                    // self.debug_me(&format!("   ...after inserting {}", x));
+                    // println!("   ...after inserting {}: {:#?}", x, self);
                 }
                 self.insert(e);
-                // self.debug_me(&format!("   ...and inserting {}", e));
+                // println!("   ...and inserting {}: {:#?}", e, self);
                 return true;
             }
             _ => (),
@@ -916,13 +1150,13 @@ impl SetU32 {
                     let newcap = s.cap + 1 + (crate::rand::rand32(s.cap, s.bits) % s.cap);
                     let mut new =
                         Self::with_capacity_and_bits(newcap as usize, compute_array_bits(e));
-                    // new.debug_me("\n\nnew set");
+                    // println!("\n\nnew set: {:#?}", new);
                     for d in self.iter() {
                         new.insert(d);
-                        // new.debug_me(&format!("\n -- after inserting {}", d));
+                        // println!("\n -- after inserting {}: {:#?}", d, new);
                     }
                     new.insert(e);
-                    // new.debug_me(&format!("\n -- after inserting {}", e));
+                    // println!("\n -- after inserting {}: {:#?}", e, new);
                     *self = new;
                     return true;
                 }
@@ -981,7 +1215,7 @@ impl SetU32 {
                     // A dense set will cost us memory
                     let newcap: u32 = s.cap + 1 + (crate::rand::rand32(s.cap, s.bits) % s.cap);
                     let mut new = Self::with_capacity_and_bits(newcap as usize, s.bits);
-                    // new.debug_me("initial new");
+                    // println!("initial new: {:#?}", new);
                     for v in self.iter() {
                         new.insert(v);
                     }
@@ -999,12 +1233,14 @@ impl SetU32 {
                     // changing the "bits" is $O(N)$, so it's worth
                     // a high O(1) cost to reduce collisions.
                     let had_zero = p_remove(s.bits, a, 0);
+                    let mut tries = 0;
                     loop {
-                        let i: u32 = crate::rand::rand32(s.cap, s.bits);
+                        let i: u32 = crate::rand::rand32(s.cap, s.bits.wrapping_add(tries));
                         if i > 32 && !a.iter().any(|&v| v == i) {
                             s.bits = i;
                             break;
                         }
+                        tries += 1;
                     }
                     if had_zero {
                         a[p_insert(s.bits, a, 0)] = s.bits;
@@ -1039,7 +1275,7 @@ impl SetU32 {
                 // println!("no room in the set... {:?}", a);
                 let newcap: u32 = s.cap + 1 + (crate::rand::rand32(s.cap, s.bits) % s.cap);
                 let mut new = Self::with_capacity_and_bits(newcap as usize, s.bits);
-                // new.debug_me("initial new");
+                // println!("initial new: {:#?}", new);
                 match new.internal_mut() {
                     InternalMut::Empty => unreachable!(),
                     InternalMut::Stack(_) => unreachable!(),
@@ -1056,7 +1292,7 @@ impl SetU32 {
                         // println!("  with {} gives {:?}", e, na);
                         ns.sz = s.sz + 1;
                         // println!("  size ends up as {}", ns.sz);
-                        // new.debug_me("aftr growing");
+                        // println!("aftr growing: {:#?}", new);
                     }
                 }
                 *self = new;
@@ -1136,38 +1372,71 @@ impl SetU32 {
 
     /// Contais
     pub fn contains(&self, e: u32) -> bool {
-        match self.internal() {
-            Internal::Empty => false,
-            Internal::Stack(t) => t.contains(e),
-            Internal::Dense { a, .. } => {
-                let key = e >> 5;
-                if let Some(bits) = a.get(key as usize) {
-                    bits & (1 << (e & 31)) != 0
-                } else {
-                    false
-                }
-            }
-            Internal::Heap { s, a } => {
-                if compute_array_bits(e) < s.bits {
-                    // println!("too big a thing");
-                    return false;
-                }
-                let (key, offset) = split_u32(e, s.bits);
-                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
-                    a[idx] & (1 << offset) != 0
-                } else {
-                    // self.debug_me(&format!("did not find key {} from {}", key, e));
-                    false
-                }
-            }
-            Internal::Big { s, a } => {
-                if e == s.bits {
-                    return false;
-                }
-                let e = if e == 0 { s.bits } else { e };
-                p_lookfor(e, a, 0).key_found()
+        self.internal().contains(e)
+    }
+
+    /// Calls `f` with each value and whether it is in the set, stopping
+    /// early if `f` returns false.
+    pub(crate) fn probe(&self, vals: impl Iterator<Item = u32>, f: impl FnMut(u32, bool) -> bool) {
+        self.internal().probe(vals, f)
+    }
+
+    /// Returns true if the set contains every one of `vals`.
+    ///
+    /// This and the other batch queries are faster than calling
+    /// [`contains`](SetU32::contains) for each value, particularly when
+    /// `vals` is sorted.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let s: SetU32 = (0..100).map(|x| x * 3).collect();
+    /// assert!(s.contains_all(&[0, 3, 297]));
+    /// assert!(!s.contains_all(&[0, 1]));
+    /// assert!(s.contains_any(&[1, 2, 3]));
+    /// assert_eq!(s.count_contained(&[1, 2, 3, 4, 5, 6]), 2);
+    /// assert_eq!(s.filter_contained(&[1, 2, 3, 4, 5, 6]), vec![3, 6]);
+    /// ```
+    pub fn contains_all(&self, vals: &[u32]) -> bool {
+        let mut all = true;
+        self.probe(vals.iter().cloned(), |_, found| {
+            all = found;
+            found
+        });
+        all
+    }
+
+    /// Returns true if the set contains at least one of `vals`.
+    pub fn contains_any(&self, vals: &[u32]) -> bool {
+        let mut any = false;
+        self.probe(vals.iter().cloned(), |_, found| {
+            any = found;
+            !found
+        });
+        any
+    }
+
+    /// Counts how many of `vals` are in the set, including repeats.
+    pub fn count_contained(&self, vals: &[u32]) -> usize {
+        let mut count = 0;
+        self.probe(vals.iter().cloned(), |_, found| {
+            count += found as usize;
+            true
+        });
+        count
+    }
+
+    /// Returns those of `vals` that are in the set, in their original
+    /// order.
+    pub fn filter_contained(&self, vals: &[u32]) -> Vec<u32> {
+        let mut out = Vec::new();
+        self.probe(vals.iter().cloned(), |e, found| {
+            if found {
+                out.push(e);
             }
-        }
+            true
+        });
+        out
     }
 
     /// Clears the set, returning all elements in an iterator.
@@ -1194,13 +1463,7 @@ impl SetU32 {
                 )
             };
             let a = unsafe { std::slice::from_raw_parts(array, b.cap as usize) };
-            if b.bits == 0 || b.bits > 32 {
-                Internal::Big { s: b, a }
-            } else if b.bits == 32 {
-                Internal::Dense { sz: b.sz, a }
-            } else {
-                Internal::Heap { s: b, a }
-            }
+            Internal::from_parts(b, a)
         }
     }
 
@@ -1244,42 +1507,608 @@ impl std::iter::FromIterator<u32> for SetU32 {
     where
         T: IntoIterator<Item = u32>,
     {
-        let v: Vec<_> = iter.into_iter().collect();
-        if let Some(mx) = v.iter().cloned().max() {
-            if let Some(t) = Tiny::new(v.clone()) {
-                SetU32(t.to_usize() as *mut S)
+        let mut s = SetU32::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl SetU32 {
+    /// Create a set sized for sorted and deduplicated `v`, and for `more`
+    /// elements that are expected to continue the way `v` started.
+    fn from_sorted_deduped(v: &[u32], more: usize) -> SetU32 {
+        let mx = match v.last() {
+            Some(&mx) => mx,
+            None => return SetU32::new(),
+        };
+        if more == 0 {
+            if let Some(t) = Tiny::new(v.to_vec()) {
+                return SetU32(t.to_usize() as *mut S);
+            }
+        }
+        let mut s = if v.len() as u32 > mx >> 4 {
+            // This should be stored in a dense bitset.
+            let span = (mx - v[0]) as u64 + 1;
+            let more = more as u64 * span / v.len() as u64;
+            SetU32::with_capacity_and_max(v.len(), (mx as u64 + more).min(u32::MAX as u64) as u32)
+        } else {
+            let bits = compute_array_bits(mx);
+            if bits == 0 {
+                SetU32::with_capacity_and_bits(v.len(), bits)
             } else {
-                if v.len() as u32 > mx >> 4 {
-                    // This should be stored in a dense bitset.
-                    let mut s = SetU32::with_capacity_and_max(v.len(), mx);
-                    for value in v.into_iter() {
-                        s.insert(value);
+                let mut keys: Vec<_> = v.iter().map(|&x| x / bits).collect();
+                keys.dedup();
+                let sz = (keys.len() + 1) * 11 / 10;
+                SetU32::with_capacity_and_bits(sz, bits)
+            }
+        };
+        for &value in v {
+            s.insert(value);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+fn check_batch(elems: &[u32], mut vals: Vec<u32>) {
+    let set: SetU32 = elems.iter().cloned().collect();
+    for _ in 0..2 {
+        let found: Vec<u32> = vals.iter().cloned().filter(|&x| set.contains(x)).collect();
+        assert_eq!(set.filter_contained(&vals), found);
+        assert_eq!(set.count_contained(&vals), found.len());
+        assert_eq!(set.contains_all(&vals), found.len() == vals.len());
+        assert_eq!(set.contains_any(&vals), !found.is_empty());
+        vals.sort_unstable();
+    }
+}
+
+#[test]
+fn batch_queries() {
+    check_batch(&[], vec![]);
+    check_batch(&[], vec![1, 2]);
+    check_batch(&[1, 2, 3], vec![]);
+    check_batch(&[1, 2, 3], vec![3, 2, 1, 1]);
+    let dense: Vec<u32> = (0..1000).filter(|x| x % 3 != 0).collect();
+    check_batch(&dense, (0..2000).rev().collect());
+    let sparse: Vec<u32> = (0..1000).map(|x| x * 37).collect();
+    check_batch(&sparse, (0..40000).step_by(7).collect());
+    let big: Vec<u32> = (0..100).map(|x| (1 << 24) * x + 1).collect();
+    check_batch(
+        &big,
+        big.iter()
+            .map(|&x| x ^ 1)
+            .chain(big.iter().cloned())
+            .collect(),
+    );
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn batch_matches_contains(elems: Vec<u32>, vals: Vec<u32>) {
+        let vals = vals.into_iter().chain(elems.iter().cloned().step_by(2)).collect();
+        check_batch(&elems, vals);
+    }
+    #[test]
+    fn batch_matches_contains_small(elems in proptest::collection::vec(0u32..1000, 0usize..200),
+                                    vals in proptest::collection::vec(0u32..1000, 0usize..200)) {
+        check_batch(&elems, vals);
+    }
+}
+
+#[test]
+fn hash_ignores_layout() {
+    use std::collections::HashSet;
+    let mut shrunk: SetU32 = (0..50000).rev().collect();
+    for x in 10..50000 {
+        shrunk.remove(x);
+    }
+    let mut sets = HashSet::new();
+    sets.insert((0..10).collect::<SetU32>());
+    assert!(sets.contains(&shrunk));
+    assert!(!sets.contains(&(1..11).collect::<SetU32>()));
+    let mut us = HashSet::new();
+    us.insert((0..10).collect::<crate::SetUsize>());
+    assert!(us.contains(&(0..10).rev().collect::<crate::SetUsize>()));
+}
+
+#[cfg(test)]
+fn check_cmp(a: &[u32], b: &[u32]) {
+    use std::collections::BTreeSet;
+    let (sa, sb): (SetU32, SetU32) = (a.iter().cloned().collect(), b.iter().cloned().collect());
+    let (ba, bb): (BTreeSet<u32>, BTreeSet<u32>) =
+        (a.iter().cloned().collect(), b.iter().cloned().collect());
+    assert_eq!(sa.cmp(&sb), ba.cmp(&bb));
+    assert_eq!(sa.partial_cmp(&sb), ba.partial_cmp(&bb));
+}
+
+#[test]
+fn ordering() {
+    check_cmp(&[], &[]);
+    check_cmp(&[], &[0]);
+    check_cmp(&[1, 2], &[1, 2, 3]);
+    check_cmp(&[1, 3], &[1, 2, 3]);
+    let big: Vec<u32> = (0..1000).map(|x| x * 1000).collect();
+    check_cmp(&big, &big[1..]);
+    check_cmp(&big, &big[..999]);
+    let mut sets: Vec<SetU32> = vec![
+        (0..5).collect(),
+        (1..2).collect(),
+        SetU32::new(),
+        (0..3).collect(),
+    ];
+    sets.sort();
+    assert_eq!(sets[0], SetU32::new());
+    assert_eq!(sets[3], (1..2).collect());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn ordering_matches_btreeset(a: Vec<u32>, b: Vec<u32>) {
+        check_cmp(&a, &b);
+    }
+    #[test]
+    fn ordering_matches_btreeset_small(a in proptest::collection::vec(0u32..50, 0usize..20),
+                                       b in proptest::collection::vec(0u32..50, 0usize..20)) {
+        check_cmp(&a, &b);
+    }
+}
+
+/// The index and mask of each word of a bitmap that holds `lo..=hi`.
+fn range_words(lo: u32, hi: u32) -> impl Iterator<Item = (usize, u32)> {
+    let (first, last) = ((lo >> 5) as usize, (hi >> 5) as usize);
+    (first..=last).map(move |i| {
+        let mut m = !0;
+        if i == first {
+            m &= !0 << (lo & 31);
+        }
+        if i == last {
+            m &= !0 >> (31 - (hi & 31));
+        }
+        (i, m)
+    })
+}
+
+/// Sets or clears bits `lo..=hi` of the bitmap `a`, returning how many
+/// bits changed.
+fn fill_bits(a: &mut [u32], lo: u32, hi: u32, set: bool) -> u32 {
+    let mut changed = 0;
+    for (i, m) in range_words(lo, hi) {
+        if set {
+            changed += (m & !a[i]).count_ones();
+            a[i] |= m;
+        } else {
+            changed += (m & a[i]).count_ones();
+            a[i] &= !m;
+        }
+    }
+    changed
+}
+
+/// The bitmap of a heap bucket `key` that covers `lo..=hi`, which must
+/// overlap the bucket.
+fn bucket_mask(key: u32, bits: u32, lo: u32, hi: u32) -> u32 {
+    let start = key * bits;
+    let from = lo.saturating_sub(start);
+    let to = (hi - start).min(bits - 1);
+    (!0 >> (31 - to)) & (!0 << from)
+}
+
+impl SetU32 {
+    /// Inserts every value in `range`, returning how many were not
+    /// already present.
+    ///
+    /// The layout is chosen up front from the size of the range, and
+    /// dense or heap storage is filled a word at a time rather than
+    /// element by element.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let mut s = SetU32::new();
+    /// assert_eq!(s.insert_range(10..1000), 990);
+    /// assert_eq!(s.insert_range(500..=1000), 1);
+    /// assert_eq!(s, (10..1001).collect());
+    /// ```
+    pub fn insert_range<R: std::ops::RangeBounds<u32>>(&mut self, range: R) -> usize {
+        match crate::copyset::inclusive_bounds(&range, u32::MAX as u64, |&x| x as u64) {
+            Some((lo, hi)) => self.insert_inclusive(lo as u32, hi as u32),
+            None => 0,
+        }
+    }
+
+    /// Removes every value in `range`, returning how many were present.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let mut s: SetU32 = (0..1000).collect();
+    /// assert_eq!(s.remove_range(100..), 900);
+    /// assert_eq!(s, (0..100).collect());
+    /// ```
+    pub fn remove_range<R: std::ops::RangeBounds<u32>>(&mut self, range: R) -> usize {
+        match crate::copyset::inclusive_bounds(&range, u32::MAX as u64, |&x| x as u64) {
+            Some((lo, hi)) => self.remove_inclusive(lo as u32, hi as u32),
+            None => 0,
+        }
+    }
+
+    /// Removes every value in `range`, returning them in an iterator.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let mut s: SetU32 = (0..10).collect();
+    /// let mut gone: Vec<u32> = s.drain_range(3..6).collect();
+    /// gone.sort();
+    /// assert_eq!(gone, vec![3, 4, 5]);
+    /// assert_eq!(s.len(), 7);
+    /// ```
+    pub fn drain_range<R: std::ops::RangeBounds<u32>>(&mut self, range: R) -> IntoIter {
+        let (lo, hi) =
+            match crate::copyset::inclusive_bounds(&range, u32::MAX as u64, |&x| x as u64) {
+                Some((lo, hi)) => (lo as u32, hi as u32),
+                None => return SetU32::new().into_iter(),
+            };
+        let drained: SetU32 = self.iter().filter(|&x| lo <= x && x <= hi).collect();
+        self.remove_inclusive(lo, hi);
+        drained.into_iter()
+    }
+
+    fn insert_inclusive(&mut self, lo: u32, hi: u32) -> usize {
+        let before = self.len();
+        let span = hi - lo;
+        if span < 32 {
+            for x in lo..=hi {
+                self.insert(x);
+            }
+            return self.len() - before;
+        }
+        if let InternalMut::Dense { sz, a } = self.internal_mut() {
+            if hi >> 5 < a.len() as u32 {
+                let added = fill_bits(a, lo, hi, true);
+                *sz += added;
+                return added as usize;
+            }
+        }
+        let mx = self.iter().fold(hi, u32::max);
+        let total = (before as u64) + span as u64 + 1;
+        if total > (mx >> 4) as u64 {
+            let mut set = SetU32::dense_with_max(mx);
+            if let InternalMut::Dense { sz, a } = set.internal_mut() {
+                match self.internal() {
+                    Internal::Dense { a: old, .. } => a[..old.len()].copy_from_slice(old),
+                    _ => {
+                        for x in self.iter() {
+                            a[(x >> 5) as usize] |= 1 << (x & 31);
+                        }
                     }
-                    return s;
                 }
-                let bits = compute_array_bits(mx);
-                if bits == 0 {
-                    let mut s = SetU32::with_capacity_and_bits(v.len(), bits);
-                    for value in v.into_iter() {
-                        s.insert(value);
+                fill_bits(a, lo, hi, true);
+                *sz = a.iter().map(|w| w.count_ones()).sum();
+            }
+            *self = set;
+            return self.len() - before;
+        }
+        let bits = compute_array_bits(mx);
+        if bits == 0 || bits >= 32 {
+            for x in lo..=hi {
+                self.insert(x);
+            }
+            return self.len() - before;
+        }
+        // Rebuild as a heap, with room for the existing buckets plus
+        // those of the range.
+        let (klo, khi) = (lo / bits, hi / bits);
+        let mut keys = (khi - klo) as usize + 1;
+        let mut last = None;
+        for x in self.iter_sorted() {
+            let key = x / bits;
+            if last != Some(key) && (key < klo || key > khi) {
+                keys += 1;
+            }
+            last = Some(key);
+        }
+        let mut set = SetU32::with_capacity_and_bits((keys + 1) * 11 / 10, bits);
+        if let InternalMut::Heap { s, a } = set.internal_mut() {
+            for x in self.iter() {
+                let (key, offset) = split_u32(x, bits);
+                let idx = p_insert(key, a, bits);
+                a[idx] |= key << bits | 1 << offset;
+            }
+            for key in klo..=khi {
+                let idx = p_insert(key, a, bits);
+                a[idx] |= key << bits | bucket_mask(key, bits, lo, hi);
+            }
+            s.sz = a
+                .iter()
+                .map(|&w| (w & mask(bits as usize)).count_ones())
+                .sum();
+        }
+        *self = set;
+        self.len() - before
+    }
+
+    fn remove_inclusive(&mut self, lo: u32, hi: u32) -> usize {
+        match self.internal_mut() {
+            InternalMut::Empty => 0,
+            InternalMut::Dense { sz, a } => {
+                if lo >> 5 >= a.len() as u32 {
+                    return 0;
+                }
+                let top = (a.len() as u64 * 32 - 1).min(hi as u64) as u32;
+                let removed = fill_bits(a, lo, top, false);
+                *sz -= removed;
+                removed as usize
+            }
+            InternalMut::Heap { s, a } => {
+                let bits = s.bits;
+                let (klo, khi) = (lo / bits, hi / bits);
+                let mut emptied = Vec::new();
+                let mut removed = 0;
+                let mut clear = |idx: usize, a: &mut [u32]| {
+                    let key = a[idx] >> bits;
+                    let m = bucket_mask(key, bits, lo, hi);
+                    removed += (a[idx] & m).count_ones();
+                    if a[idx] & mask(bits as usize) & !m == 0 {
+                        emptied.push(key);
+                    } else {
+                        a[idx] &= !m;
+                    }
+                };
+                if ((khi - klo) as usize) < a.len() {
+                    for key in klo..=khi {
+                        if let LookedUp::KeyFound(idx) = p_lookfor(key, a, bits) {
+                            clear(idx, a);
+                        }
                     }
-                    s
                 } else {
-                    let mut keys: Vec<_> = v.iter().map(|&x| x / bits).collect();
-                    keys.sort();
-                    keys.dedup();
-                    let sz = (keys.len() + 1) * 11 / 10;
-                    let mut s = SetU32::with_capacity_and_bits(sz, bits);
-                    for value in v.into_iter() {
-                        s.insert(value);
+                    for idx in 0..a.len() {
+                        let key = a[idx] >> bits;
+                        if a[idx] != 0 && klo <= key && key <= khi {
+                            clear(idx, a);
+                        }
                     }
-                    s
                 }
+                for key in emptied {
+                    p_remove(key, a, bits);
+                }
+                s.sz -= removed;
+                removed as usize
+            }
+            InternalMut::Stack(_) | InternalMut::Big { .. } => {
+                let doomed: Vec<u32> = self.iter().filter(|&x| lo <= x && x <= hi).collect();
+                for &x in doomed.iter() {
+                    self.remove(x);
+                }
+                doomed.len()
             }
+        }
+    }
+}
+
+#[cfg(test)]
+fn check_ranges(elems: &[u32], lo: u32, hi: u32) {
+    use std::collections::BTreeSet;
+    let expected: BTreeSet<u32> = elems.iter().cloned().collect();
+    let inserted: BTreeSet<u32> = expected.iter().cloned().chain(lo..hi).collect();
+    let mut set: SetU32 = elems.iter().cloned().collect();
+    assert_eq!(set.insert_range(lo..hi), inserted.len() - expected.len());
+    assert_eq!(set.len(), inserted.len());
+    assert_eq!(set.iter_sorted().collect::<BTreeSet<_>>(), inserted);
+
+    let kept: BTreeSet<u32> = expected
+        .iter()
+        .cloned()
+        .filter(|x| !(lo..hi).contains(x))
+        .collect();
+    let gone: BTreeSet<u32> = expected
+        .iter()
+        .cloned()
+        .filter(|x| (lo..hi).contains(x))
+        .collect();
+    let mut set: SetU32 = elems.iter().cloned().collect();
+    assert_eq!(set.remove_range(lo..hi), gone.len());
+    assert_eq!(set.len(), kept.len());
+    assert_eq!(set.iter().collect::<BTreeSet<_>>(), kept);
+
+    let mut set: SetU32 = elems.iter().cloned().collect();
+    assert_eq!(set.drain_range(lo..hi).collect::<BTreeSet<_>>(), gone);
+    assert_eq!(set.iter().collect::<BTreeSet<_>>(), kept);
+}
+
+#[test]
+fn ranges() {
+    check_ranges(&[], 0, 0);
+    check_ranges(&[1, 2, 3], 0, 10);
+    check_ranges(&[1, 2, 3], 2, 1000);
+    check_ranges(&(0..1000).collect::<Vec<_>>(), 31, 129);
+    check_ranges(&(0..1000).collect::<Vec<_>>(), 500, 5000);
+    check_ranges(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 5000, 6000);
+    check_ranges(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 0, 1 << 20);
+    check_ranges(
+        &(0..100).map(|x| x << 25).collect::<Vec<_>>(),
+        7 << 25,
+        (7 << 25) + 100,
+    );
+
+    let mut s = SetU32::new();
+    assert_eq!(s.insert_range(u32::MAX - 100..), 101);
+    assert!(s.contains(u32::MAX));
+    assert_eq!(s.remove_range(..u32::MAX - 50), 50);
+    assert_eq!(s.drain_range(..).count(), 51);
+    assert_eq!(s.len(), 0);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn ranges_match_btreeset(elems: Vec<u32>, lo: u32, len in 0u32..3000) {
+        check_ranges(&elems, lo, lo.saturating_add(len));
+    }
+    #[test]
+    fn ranges_match_btreeset_small(elems in proptest::collection::vec(0u32..5000, 0usize..500),
+                                   lo in 0u32..5000, len in 0u32..3000) {
+        check_ranges(&elems, lo, lo + len);
+    }
+}
+
+impl SetU32 {
+    /// The values in `universe` that are not in the set.
+    ///
+    /// A complement that is dense within `0..universe.end` is built by
+    /// inverting the bitmap a word at a time, and a sparse one from the
+    /// gaps between the elements of the set.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let live: SetU32 = [1, 3, 4].iter().cloned().collect();
+    /// assert_eq!(live.complement(0..6), [0, 2, 5].iter().cloned().collect());
+    /// ```
+    pub fn complement(&self, universe: std::ops::Range<u32>) -> SetU32 {
+        let (lo, hi) = (universe.start, universe.end);
+        if lo >= hi {
+            return SetU32::new();
+        }
+        let inside = match self.internal() {
+            Internal::Dense { a, .. } if ((lo >> 5) as usize) < a.len() => {
+                let top = ((hi - 1) as u64).min(a.len() as u64 * 32 - 1) as u32;
+                range_words(lo, top)
+                    .map(|(i, m)| (a[i] & m).count_ones())
+                    .sum()
+            }
+            Internal::Dense { .. } => 0,
+            _ => self.iter().filter(|&x| lo <= x && x < hi).count() as u32,
+        };
+        let n = hi - lo - inside;
+        if n == 0 {
+            SetU32::new()
+        } else if n > (hi - 1) >> 4 {
+            let mut set = SetU32::dense_with_max(hi - 1);
+            if let InternalMut::Dense { sz, a } = set.internal_mut() {
+                fill_bits(a, lo, hi - 1, true);
+                match self.internal() {
+                    Internal::Dense { a: old, .. } => {
+                        for (w, o) in a.iter_mut().zip(old.iter()) {
+                            *w &= !o;
+                        }
+                    }
+                    _ => {
+                        for x in self.iter().filter(|&x| lo <= x && x < hi) {
+                            a[(x >> 5) as usize] &= !(1 << (x & 31));
+                        }
+                    }
+                }
+                *sz = a.iter().map(|w| w.count_ones()).sum();
+            }
+            set
         } else {
-            SetU32(0 as *mut S)
+            let mut v = Vec::with_capacity(n as usize);
+            let mut next = lo;
+            for x in self.iter_sorted().filter(|&x| lo <= x && x < hi) {
+                v.extend(next..x);
+                next = x + 1;
+            }
+            v.extend(next..hi);
+            SetU32::from_sorted_deduped(&v, 0)
         }
     }
+
+    /// Replaces the set with the values in `0..n` that are not in it.
+    ///
+    /// A dense set whose complement is also dense is inverted without
+    /// reallocating.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let mut s: SetU32 = (0..100).filter(|x| x % 3 == 0).collect();
+    /// s.complement_in_place(100);
+    /// assert_eq!(s, (0..100).filter(|x| x % 3 != 0).collect());
+    /// ```
+    pub fn complement_in_place(&mut self, n: u32) {
+        if let InternalMut::Dense { sz, a } = self.internal_mut() {
+            let len = a.len() as u64 * 32;
+            let outside = if (n as u64) < len {
+                fill_bits(a, n, (len - 1) as u32, false)
+            } else {
+                0
+            };
+            let missing = n.saturating_sub(*sz - outside);
+            if n > 0 && n as u64 <= len && missing > (n - 1) >> 4 {
+                let (head, last) = a.split_at_mut(((n - 1) >> 5) as usize);
+                for w in head.iter_mut().chain(last.iter_mut().take(1)) {
+                    *w = !*w;
+                }
+                if (n as u64) < len {
+                    fill_bits(a, n, (len - 1) as u32, false);
+                }
+                *sz = missing;
+                return;
+            }
+            *sz -= outside;
+        }
+        *self = self.complement(0..n);
+    }
+}
+
+#[cfg(test)]
+fn check_complement(elems: &[u32], lo: u32, hi: u32) {
+    use std::collections::BTreeSet;
+    let set: SetU32 = elems.iter().cloned().collect();
+    let have: BTreeSet<u32> = elems.iter().cloned().collect();
+    let expected: BTreeSet<u32> = (lo..hi).filter(|x| !have.contains(x)).collect();
+    let c = set.complement(lo..hi);
+    assert_eq!(c.len(), expected.len());
+    assert_eq!(c.iter_sorted().collect::<BTreeSet<_>>(), expected);
+
+    let expected: BTreeSet<u32> = (0..hi).filter(|x| !have.contains(x)).collect();
+    let mut set = set;
+    set.complement_in_place(hi);
+    assert_eq!(set.len(), expected.len());
+    assert_eq!(set.iter_sorted().collect::<BTreeSet<_>>(), expected);
+}
+
+#[test]
+fn complement() {
+    check_complement(&[], 0, 0);
+    check_complement(&[], 0, 1000);
+    check_complement(&[1, 3, 4], 0, 6);
+    check_complement(&[1, 3, 4, 1 << 30], 2, 6);
+    check_complement(&(0..1000).collect::<Vec<_>>(), 10, 2000);
+    check_complement(
+        &(0..1000).filter(|&x| x != 500).collect::<Vec<_>>(),
+        100,
+        700,
+    );
+    check_complement(
+        &(0..1000).filter(|x| x % 3 != 0).collect::<Vec<_>>(),
+        0,
+        999,
+    );
+    check_complement(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 0, 10000);
+    check_complement(
+        &(0..5000).filter(|x| x % 100 != 7).collect::<Vec<_>>(),
+        0,
+        60000,
+    );
+
+    let dense: SetU32 = (0..10000).filter(|x| x % 2 == 0).collect();
+    let mut odd = dense.clone();
+    odd.complement_in_place(10000);
+    assert_eq!(odd.capacity(), dense.capacity());
+    assert_eq!(odd, (0..10000).filter(|x| x % 2 == 1).collect());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn complement_matches_btreeset(elems in proptest::collection::vec(0u32..5000, 0usize..3000),
+                                   lo in 0u32..5000, len in 0u32..5000) {
+        check_complement(&elems, lo, lo + len);
+    }
 }
 
 #[cfg(test)]
@@ -1300,6 +2129,31 @@ fn test_collect() {
     test_a_collect((0..1024).collect());
 }
 
+#[test]
+fn collect_in_chunks() {
+    for &stride in &[1u32, 7, 1 << 12, 0x9E37_79B9] {
+        let v: Vec<u32> = (0..3 * CHUNK as u32 + 5)
+            .map(|x| x.wrapping_mul(0x4F6C_DD1D) % 10000)
+            .map(|x| x.wrapping_mul(stride))
+            .collect();
+        let expected: std::collections::HashSet<u32> = v.iter().cloned().collect();
+        let s: SetU32 = v.iter().cloned().collect();
+        assert_eq!(s.len(), expected.len());
+        for &x in expected.iter() {
+            assert!(s.contains(x));
+        }
+        let mut s2 = SetU32::new();
+        s2.extend(v.iter().cloned().filter(|_| true));
+        assert_eq!(s, s2);
+    }
+    let n = 100 * CHUNK as u32;
+    let s: SetU32 = (0..n).collect();
+    assert_eq!(s.len(), n as usize);
+    let s: SetU32 = (0..CHUNK as u32).chain(u32::MAX - 10..=u32::MAX).collect();
+    assert_eq!(s.len(), CHUNK + 11);
+    assert!(s.contains(u32::MAX));
+}
+
 fn bytes_for_capacity(sz: usize) -> usize {
     sz * 4 + std::mem::size_of::<S>() - 4
 }
@@ -1326,19 +2180,6 @@ impl Drop for SetU32 {
     }
 }
 
-#[cfg(test)]
-impl heapsize::HeapSizeOf for SetU32 {
-    fn heap_size_of_children(&self) -> usize {
-        match self.internal() {
-            Internal::Empty => 0,
-            Internal::Stack(_) => 0,
-            Internal::Heap { a, .. } => std::mem::size_of::<S>() - 4 + a.len() * 4,
-            Internal::Big { a, .. } => std::mem::size_of::<S>() - 4 + a.len() * 4,
-            Internal::Dense { a, .. } => std::mem::size_of::<S>() - 4 + a.len() * 4,
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1347,19 +2188,19 @@ mod tests {
     fn check_set(elems: &[u32]) {
         println!("\n\ncheck_set {:?}\n", elems);
         let mut s = SetU32::default();
-        s.debug_me("default set");
+        println!("default set: {:#?}", s);
         let mut count = 0;
         for x in elems.iter().cloned() {
             let was_here = s.contains(x);
-            s.debug_me(&format!("\n\n\nabout to insert {}", x));
+            println!("\n\n\nabout to insert {}: {:#?}", x, s);
             let changed_something = s.insert(x);
-            s.debug_me(&format!("\n\nafter inserting {}", x));
+            println!("\n\nafter inserting {}: {:#?}", x, s);
             if changed_something {
                 count += 1;
                 println!("    {} is new now count {}", x, count);
             }
             assert_eq!(!was_here, changed_something);
-            s.debug_me(&format!("after inserting {} length is {}", x, s.len()));
+            println!("after inserting {} length is {}: {:#?}", x, s.len(), s);
             println!("what is this? count {} does it have {}?", count, x);
             assert!(s.contains(x));
             assert_eq!(s.len(), count);
@@ -1371,7 +2212,10 @@ mod tests {
         for x in s.iter() {
             println!("    {}", x);
         }
-        s.debug_me("finally");
+        println!("finally: {:#?}", s);
+        let mut sorted: Vec<_> = s.iter().collect();
+        sorted.sort();
+        assert_eq!(s.iter_sorted().collect::<Vec<_>>(), sorted);
         assert_eq!(s.iter().count(), s.len());
         for x in s.iter() {
             println!("looking for {}", x);
@@ -1388,13 +2232,13 @@ mod tests {
         for x in elems.iter().cloned() {
             println!("removing {}", x);
             s.remove(x);
-            s.debug_me("  after remove");
+            println!("  after remove: {:#?}", s);
         }
         for x in elems.iter().cloned() {
             println!("XXXX looking for {}", x);
             assert!(!s.contains(x));
         }
-        s.debug_me("after everything was removed");
+        println!("after everything was removed: {:#?}", s);
         assert_eq!(s.len(), 0);
         check_size(elems);
     }
@@ -1468,6 +2312,20 @@ mod tests {
         check_tiny_from_vec(vec![1, 130, 131, 132, 133, 251]);
     }
 
+    #[test]
+    fn partly_consumed_tiny() {
+        let mut t = Tiny::from_singleton(2).unwrap();
+        for x in [2, 3, 143, 251, 1].iter().cloned() {
+            t = t.insert(x).unwrap();
+        }
+        let n = t.sz as usize;
+        for left in (0..n).rev() {
+            t.next();
+            assert_eq!(t.size_hint(), (left, Some(left)));
+            assert_eq!(t.count(), left);
+        }
+    }
+
     use proptest::prelude::*;
     proptest! {
         #[test]
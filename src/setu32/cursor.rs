@@ -0,0 +1,17 @@
+use super::{mask, unsplit_u32, Internal, SetU32, Tiny};
+use crate::cursor::{impl_cursor, next_nonzero_u32};
+
+impl_cursor!(SetU32, u32, unsplit_u32, next_nonzero_u32);
+
+#[test]
+fn cursor() {
+    check_cursor(&[], &[0, 5]);
+    check_cursor(&[3, 1, 2], &[2, 2, 0]);
+    check_cursor(&[0, u32::MAX], &[1, u32::MAX]);
+    let dense: Vec<u32> = (0..10000).filter(|x| x % 1000 < 3).collect();
+    check_cursor(&dense, &[0, 1, 1000, 1002, 1003, 5500, 9002]);
+    let sparse: Vec<u32> = (0..1000).map(|x| x * 1000 + x % 7).collect();
+    check_cursor(&sparse, &[0, 5, 1000, 1001, 50_000, 50_004, 999_999]);
+    let big: Vec<u32> = (0..32).map(|x| x << 27).collect();
+    check_cursor(&big, &[1, 3 << 27, (3 << 27) + 1, 31 << 27]);
+}
@@ -1,43 +1,25 @@
-use std::borrow::Borrow;
-
-use super::{mask, unsplit_u32, Internal, SetU32};
+use super::{mask, unsplit_u32, Internal, SetU32, Storage};
 
 impl SetU32 {
     /// Iterate over
     #[inline]
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = u32> + 'a + std::fmt::Debug {
-        self.inner_iter()
+        Inner::new(self)
     }
-}
-
-impl SetU32 {
-    fn inner_iter(&self) -> Inner<&SetU32> {
-        match self.internal() {
-            Internal::Empty => Inner::empty(self),
-            Internal::Stack(t) => Inner {
-                sz: t.sz as u32,
-                sz_left: t.sz as u32,
-                stack_bits: t.bits,
-                ..Inner::empty(self)
-            },
-            Internal::Heap { s, .. } => Inner {
-                sz: s.sz,
-                sz_left: s.sz,
-                bits: s.bits,
-                ..Inner::empty(self)
-            },
-            Internal::Big { s, .. } => Inner {
-                sz: s.sz,
-                sz_left: s.sz,
-                bits: s.bits,
-                ..Inner::empty(self)
-            },
-            Internal::Dense { sz, .. } => Inner {
-                sz: sz,
-                sz_left: sz,
-                ..Inner::empty(self)
-            },
-        }
+    /// Iterate over the elements in ascending order.
+    ///
+    /// Small and dense sets are stored in order already.  Otherwise this
+    /// sorts the underlying table, which is never larger than the set.
+    ///
+    /// ```
+    /// use tinyset::SetU32;
+    ///
+    /// let a: SetU32 = [30, 1, 2000, 7].iter().cloned().collect();
+    /// assert_eq!(a.iter_sorted().collect::<Vec<_>>(), vec![1, 7, 30, 2000]);
+    /// ```
+    #[inline]
+    pub fn iter_sorted<'a>(&'a self) -> impl Iterator<Item = u32> + 'a + std::fmt::Debug {
+        Sorted::new(self)
     }
 }
 
@@ -46,18 +28,9 @@ impl IntoIterator for SetU32 {
     type IntoIter = IntoIter;
 
     fn into_iter(self) -> IntoIter {
-        let x = self.inner_iter();
-        let inner = Inner {
-            sz: x.sz,
-            sz_left: x.sz_left,
-            bits: x.bits,
-            stack_bits: x.stack_bits,
-            whichbit: x.whichbit,
-            last: x.last,
-            index: x.index,
-            set: self,
-        };
-        IntoIter { inner }
+        IntoIter {
+            inner: Inner::new(self),
+        }
     }
 }
 
@@ -90,7 +63,7 @@ impl Iterator for IntoIter {
 }
 
 #[derive(Debug, Clone)]
-struct Inner<T: Borrow<SetU32>> {
+pub(super) struct Inner<T: Storage> {
     sz: u32,
     sz_left: u32,
     stack_bits: usize,
@@ -101,13 +74,20 @@ struct Inner<T: Borrow<SetU32>> {
     set: T,
 }
 
-impl<T: Borrow<SetU32>> Inner<T> {
-    fn empty(set: T) -> Self {
+impl<T: Storage> Inner<T> {
+    pub(super) fn new(set: T) -> Self {
+        let (sz, stack_bits, bits) = match set.internal() {
+            Internal::Empty => (0, 0, 0),
+            Internal::Stack(t) => (t.sz as u32, t.bits, 0),
+            Internal::Heap { s, .. } => (s.sz, 0, s.bits),
+            Internal::Big { s, .. } => (s.sz, 0, s.bits),
+            Internal::Dense { sz, .. } => (sz, 0, 0),
+        };
         Inner {
-            sz: 0,
-            sz_left: 0,
-            stack_bits: 0,
-            bits: 0,
+            sz,
+            sz_left: sz,
+            stack_bits,
+            bits,
             whichbit: 0,
             index: 0,
             last: 0,
@@ -116,11 +96,11 @@ impl<T: Borrow<SetU32>> Inner<T> {
     }
 }
 
-impl<T: Borrow<SetU32>> Iterator for Inner<T> {
+impl<T: Storage> Iterator for Inner<T> {
     type Item = u32;
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(_) => {
                 let bitsplits = super::BITSPLITS[self.sz as usize];
@@ -195,7 +175,7 @@ impl<T: Borrow<SetU32>> Iterator for Inner<T> {
         if self.sz_left == 0 {
             return None;
         }
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(t) => t.max(),
             Internal::Heap { a, .. } => a
@@ -226,7 +206,7 @@ impl<T: Borrow<SetU32>> Iterator for Inner<T> {
         if self.sz_left == 0 {
             return None;
         }
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(t) => t.min(),
             Internal::Heap { a, .. } => {
@@ -257,7 +237,7 @@ impl<T: Borrow<SetU32>> Iterator for Inner<T> {
         if self.sz_left == 0 {
             return None;
         }
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(t) => t.max(),
             Internal::Heap { a, .. } => {
@@ -298,3 +278,89 @@ impl<T: Borrow<SetU32>> Iterator for Inner<T> {
         (self.sz_left as usize, Some(self.sz_left as usize))
     }
 }
+
+/// An iterator in ascending order.
+#[derive(Debug, Clone)]
+pub(super) enum Sorted<T: Storage> {
+    /// The set is stored in order, so we can just iterate over it.
+    InOrder(Inner<T>),
+    /// The (sorted) nonzero words of a hash table of bitmaps.
+    Heap {
+        bits: u32,
+        words: std::vec::IntoIter<u32>,
+        key: u32,
+        bitmap: u32,
+        sz_left: usize,
+    },
+    /// The sorted elements of a big set.
+    Big(std::vec::IntoIter<u32>),
+}
+
+impl<T: Storage> Sorted<T> {
+    pub(super) fn new(set: T) -> Self {
+        match set.internal() {
+            Internal::Heap { s, a } => {
+                // The key is in the high bits, so sorting the words sorts
+                // the bitmaps.
+                let mut words: Vec<u32> = a.iter().cloned().filter(|&x| x != 0).collect();
+                words.sort_unstable();
+                Sorted::Heap {
+                    bits: s.bits,
+                    words: words.into_iter(),
+                    key: 0,
+                    bitmap: 0,
+                    sz_left: s.sz as usize,
+                }
+            }
+            Internal::Big { s, a } => {
+                let mut v: Vec<u32> = a
+                    .iter()
+                    .cloned()
+                    .filter(|&x| x != 0)
+                    .map(|x| if x == s.bits { 0 } else { x })
+                    .collect();
+                v.sort_unstable();
+                Sorted::Big(v.into_iter())
+            }
+            Internal::Empty | Internal::Stack(_) | Internal::Dense { .. } => {
+                Sorted::InOrder(Inner::new(set))
+            }
+        }
+    }
+}
+
+impl<T: Storage> Iterator for Sorted<T> {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            Sorted::InOrder(i) => i.next(),
+            Sorted::Heap {
+                bits,
+                words,
+                key,
+                bitmap,
+                sz_left,
+            } => {
+                while *bitmap == 0 {
+                    let x = words.next()?;
+                    *key = x >> *bits;
+                    *bitmap = x & mask(*bits as usize);
+                }
+                let offset = bitmap.trailing_zeros();
+                *bitmap &= *bitmap - 1;
+                *sz_left -= 1;
+                Some(unsplit_u32(*key, offset, *bits))
+            }
+            Sorted::Big(v) => v.next(),
+        }
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Sorted::InOrder(i) => i.size_hint(),
+            Sorted::Heap { sz_left, .. } => (*sz_left, Some(*sz_left)),
+            Sorted::Big(v) => v.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use super::iter::Inner;
+use super::{
+    compute_array_bits, mask, p_lookfor, Internal, LookedUp, Sbeginning, SetU32, Storage, Tiny,
+};
+
+/// A read-only view of a [`SetU32`] stored in its compact layout.
+///
+/// The layout is the one produced by [`SetU32::to_compact`], and is
+/// intended for sets that are precomputed and stored in a file which is
+/// then memory mapped.  A `SetU32Ref` never allocates: it answers queries
+/// directly from the borrowed buffer, which is validated once when the view
+/// is created.
+///
+/// ```
+/// use tinyset::{SetU32, SetU32Ref};
+///
+/// let a: SetU32 = [1, 5, 1 << 30].iter().cloned().collect();
+/// let words = a.to_compact();
+/// let view = SetU32Ref::new(&words).unwrap();
+/// assert!(view.contains(1 << 30));
+/// assert_eq!(view.min(), Some(1));
+/// assert_eq!(view.max(), Some(1 << 30));
+/// assert_eq!(view.to_set(), a);
+/// ```
+#[derive(Clone, Copy)]
+pub struct SetU32Ref<'a> {
+    words: &'a [u32],
+    b: Sbeginning,
+}
+
+impl<'a> Storage for SetU32Ref<'a> {
+    #[inline]
+    fn internal(&self) -> Internal<'_> {
+        match self.words.len() {
+            1 if self.words[0] == 0 => Internal::Empty,
+            1 => Internal::Stack(Tiny::from_usize(self.words[0] as usize)),
+            2 => {
+                let w = self.words[0] as u64 | (self.words[1] as u64) << 32;
+                Internal::Stack(Tiny::from_usize(w as usize))
+            }
+            _ => Internal::from_parts(&self.b, &self.words[2..]),
+        }
+    }
+}
+
+impl<'a> SetU32Ref<'a> {
+    /// Interpret `words` as a set in the compact layout.
+    ///
+    /// Returns `None` if `words` is not a valid set.
+    pub fn new(words: &'a [u32]) -> Option<Self> {
+        let b = match words.len() {
+            0 => return None,
+            1 | 2 => {
+                let w = words[0] as u64 | (words.get(1).cloned().unwrap_or(0) as u64) << 32;
+                if (words.len() == 2 && words[1] == 0)
+                    || w > usize::MAX as u64
+                    || (w != 0 && w & 3 == 0)
+                {
+                    // This would be a pointer rather than a set.
+                    return None;
+                }
+                Sbeginning {
+                    sz: 0,
+                    cap: 0,
+                    bits: 0,
+                }
+            }
+            n => Sbeginning {
+                sz: words[0],
+                cap: n as u32 - 2,
+                bits: words[1],
+            },
+        };
+        let view = SetU32Ref { words, b };
+        if view.is_valid() {
+            Some(view)
+        } else {
+            None
+        }
+    }
+
+    /// Interpret `bytes` as a set in the compact layout, using the native
+    /// byte order.
+    ///
+    /// Returns `None` if `bytes` is not aligned for `u32`, or is not a
+    /// valid set.
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.as_ptr() as usize % std::mem::align_of::<u32>() != 0 || bytes.len() % 4 != 0 {
+            return None;
+        }
+        // SAFETY: the pointer is aligned, the length is a whole number of
+        // `u32`, and any bit pattern is a valid `u32`.
+        let words =
+            unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u32, bytes.len() / 4) };
+        SetU32Ref::new(words)
+    }
+
+    fn is_valid(&self) -> bool {
+        match self.internal() {
+            Internal::Empty | Internal::Stack(_) => true,
+            Internal::Dense { sz, a } => a.iter().map(|w| w.count_ones()).sum::<u32>() == sz,
+            Internal::Heap { s, a } => {
+                let mut count = 0;
+                for (i, &x) in a.iter().enumerate() {
+                    if x == 0 {
+                        continue;
+                    }
+                    let key = x >> s.bits;
+                    let bitmap = x & mask(s.bits as usize);
+                    if bitmap == 0 || p_lookfor(key, a, s.bits) != LookedUp::KeyFound(i) {
+                        return false;
+                    }
+                    let highest = 31 - bitmap.leading_zeros();
+                    match key.checked_mul(s.bits).and_then(|k| k.checked_add(highest)) {
+                        Some(mx) if compute_array_bits(mx) >= s.bits => (),
+                        _ => return false,
+                    }
+                    count += bitmap.count_ones();
+                }
+                count == s.sz
+            }
+            Internal::Big { s, a } => {
+                let mut count = 0;
+                for (i, &x) in a.iter().enumerate() {
+                    if x == 0 {
+                        continue;
+                    }
+                    if p_lookfor(x, a, 0) != LookedUp::KeyFound(i) {
+                        return false;
+                    }
+                    count += 1;
+                }
+                count == s.sz
+            }
+        }
+    }
+
+    /// The number of elements in the set
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.internal().len()
+    }
+    /// Returns true if the set is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns true if the set contains a value.
+    #[inline]
+    pub fn contains(&self, e: u32) -> bool {
+        self.internal().contains(e)
+    }
+    /// Iterate over the elements, in no particular order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = u32> + 'a + std::fmt::Debug {
+        Inner::new(*self)
+    }
+    /// The smallest element of the set.
+    pub fn min(&self) -> Option<u32> {
+        self.iter().min()
+    }
+    /// The largest element of the set.
+    pub fn max(&self) -> Option<u32> {
+        self.iter().max()
+    }
+    /// Copy the viewed set into a [`SetU32`].
+    pub fn to_set(&self) -> SetU32 {
+        SetU32::from_array(self.words)
+    }
+}
+
+impl<'a> std::fmt::Debug for SetU32Ref<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "SetU32Ref {:?}", self.iter().collect::<Vec<_>>())
+    }
+}
+
+impl<'a> PartialEq<SetU32> for SetU32Ref<'a> {
+    fn eq(&self, other: &SetU32) -> bool {
+        self.len() == other.len() && self.iter().all(|x| other.contains(x))
+    }
+}
+
+#[cfg(test)]
+fn check_view(v: &[u32]) {
+    let set: SetU32 = v.iter().cloned().collect();
+    let words = set.to_compact();
+    let view = SetU32Ref::new(&words).unwrap();
+    assert_eq!(view, set);
+    assert_eq!(view.len(), set.len());
+    assert_eq!(view.min(), v.iter().cloned().min());
+    assert_eq!(view.max(), v.iter().cloned().max());
+    for &x in v {
+        assert!(view.contains(x));
+        assert!(!view.contains(x.wrapping_add(1)) || set.contains(x.wrapping_add(1)));
+    }
+    assert_eq!(view.to_set(), set);
+    let bytes = unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 4) };
+    assert_eq!(SetU32Ref::from_bytes(bytes).unwrap(), set);
+    if bytes.len() > 4 {
+        assert!(SetU32Ref::from_bytes(&bytes[1..]).is_none());
+    }
+}
+
+#[test]
+fn views() {
+    check_view(&[]);
+    check_view(&[0]);
+    check_view(&[1, 2, 3]);
+    check_view(&(0..1000).collect::<Vec<_>>());
+    check_view(&(0..1000).map(|x| x * 100).collect::<Vec<_>>());
+    check_view(&[0, 1 << 31, u32::MAX, 7]);
+    check_view(&[u32::MAX, u32::MAX - 100]);
+}
+
+#[test]
+fn invalid_views() {
+    assert!(SetU32Ref::new(&[]).is_none());
+    assert!(SetU32Ref::new(&[4]).is_none());
+    assert!(SetU32Ref::new(&[0, 0]).is_none());
+    assert!(SetU32Ref::new(&[1, 0]).is_none());
+    // A dense set whose size does not match its bitmap.
+    assert!(SetU32Ref::new(&[3, 32, 0b11]).is_none());
+    assert!(SetU32Ref::new(&[2, 32, 0b11]).is_some());
+
+    let set: SetU32 = (0..100).map(|x| x * 1000).collect();
+    let mut words = set.to_compact();
+    assert!(SetU32Ref::new(&words).is_some());
+    words[0] += 1;
+    assert!(SetU32Ref::new(&words).is_none());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn view_matches_set(v: Vec<u32>) {
+        check_view(&v);
+    }
+    #[test]
+    fn view_matches_small_set(v in proptest::collection::vec(0u32..5000, 0usize..200)) {
+        check_view(&v);
+    }
+}
@@ -1,4 +1,13 @@
-//! This is a an awesome module.
+//! An alternative implementation of a set of `u32`.
+//!
+//! [`SetU32b`] keeps its hash table as `(key, bitmap)` pairs in which
+//! each bitmap covers 32 consecutive values, where [`SetU32`](crate::SetU32)
+//! packs a key and a bitmap of varying width into each word.  Small sets
+//! are held in a single pointer-sized bitmap, and sets that are dense
+//! enough are stored as one plain bitmap.
+//!
+//! The two types share their interface for the common operations, so
+//! either can be benchmarked on a given workload and the faster one kept.
 
 #[cfg(test)]
 use proptest::prelude::*;
@@ -15,8 +24,8 @@ impl Iterator for Tiny {
         if self.bits != 0 {
             let off = self.bits.trailing_zeros();
             let v = self.start + off;
-            self.bits = self.bits >> (off + 1);
-            self.start += off + 1;
+            self.bits >>= off + 1;
+            self.start = v.wrapping_add(1);
             Some(v)
         } else {
             None
@@ -26,32 +35,46 @@ impl Iterator for Tiny {
         self.bits.count_ones() as usize
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.bits.count_ones()  as usize, Some(self.bits.count_ones() as usize))
+        (
+            self.bits.count_ones() as usize,
+            Some(self.bits.count_ones() as usize),
+        )
     }
     fn min(self) -> Option<u32> {
-        Some(self.start + self.bits.trailing_zeros())
-    }
-    #[cfg(target_pointer_width = "64")]
-    fn max(self) -> Option<u32> {
-        Some(self.start + 63 - self.bits.leading_zeros())
+        if self.bits == 0 {
+            None
+        } else {
+            Some(self.start + self.bits.trailing_zeros())
+        }
     }
-    #[cfg(target_pointer_width = "32")]
     fn max(self) -> Option<u32> {
-        Some(self.start + 31 - self.bits.leading_zeros())
+        if self.bits == 0 {
+            None
+        } else {
+            Some(self.start + (usize::BITS - 1 - self.bits.leading_zeros()))
+        }
     }
 }
 
 #[cfg(target_pointer_width = "64")]
-const MAX_TINY: u32 = std::u32::MAX;
+const MAX_TINY: u32 = u32::MAX;
 #[cfg(target_pointer_width = "32")]
-const MAX_TINY: u32 = std::u16::MAX as u32;
+const MAX_TINY: u32 = u16::MAX as u32;
 
 #[cfg(target_pointer_width = "64")]
 const START_OFFSET: u32 = 32;
 #[cfg(target_pointer_width = "32")]
 const START_OFFSET: u32 = 16;
 
-const NUM_BITS: u32 = START_OFFSET-2;
+/// The number of bits available for the bitmap, which holds elements
+/// from `start` to `start + NUM_BITS - 1`.
+const NUM_BITS: u32 = START_OFFSET - 2;
+
+/// Whether `x` fits in the `start` of a [`Tiny`], which any `u32` does on
+/// a 64-bit platform.
+fn fits_tiny_start(x: u32) -> bool {
+    x as u64 <= MAX_TINY as u64
+}
 
 impl Tiny {
     fn to_usize(self) -> usize {
@@ -64,27 +87,21 @@ impl Tiny {
         }
     }
     fn from_singleton(x: u32) -> Option<Self> {
-        if x > MAX_TINY {
+        if !fits_tiny_start(x) {
             None
         } else {
             Some(Tiny { start: x, bits: 1 })
         }
     }
     fn from_slice(v: &[u32]) -> Option<Self> {
-        if v.len() > NUM_BITS as usize || v.len() == 0 {
-            return None;
-        }
-        let mn = v.iter().cloned().min().unwrap();
-        let mx = v.iter().cloned().max().unwrap();
-        if mx > mn + NUM_BITS || mn > MAX_TINY {
+        let mn = v.iter().cloned().min()?;
+        let mx = v.iter().cloned().max()?;
+        if mx - mn >= NUM_BITS || !fits_tiny_start(mn) {
             None
         } else {
-            let mut t = Tiny {
-                start: mn,
-                bits: 0,
-            };
+            let mut t = Tiny { start: mn, bits: 0 };
             for x in v.iter().cloned() {
-                t.bits = t.bits | (1 << x - mn);
+                t.bits |= 1 << (x - mn);
             }
             Some(t)
         }
@@ -93,36 +110,39 @@ impl Tiny {
         self.bits.count_ones() as usize
     }
     fn contains(&self, v: u32) -> bool {
-        v >= self.start && v <= self.start + NUM_BITS && self.bits >> (v-self.start) & 1 != 0
+        v >= self.start && v - self.start < NUM_BITS && self.bits >> (v - self.start) & 1 != 0
     }
+    /// Returns `None` if `v` does not fit, otherwise whether it was
+    /// already present.
     fn insert(&mut self, v: u32) -> Option<bool> {
-        if v >= self.start && v <= self.start + NUM_BITS {
-            if self.bits >> (v-self.start) & 1 != 0 {
-                return Some(true);
+        if self.contains(v) {
+            return Some(true);
+        }
+        let (mn, mx) = match ((*self).min(), (*self).max()) {
+            (Some(mn), Some(mx)) => (mn, mx),
+            _ => {
+                *self = Tiny::from_singleton(v)?;
+                return Some(false);
             }
-            self.bits = self.bits | 1 << (v-self.start);
-            return Some(false)
+        };
+        let lo = if v < mn { v } else { mn };
+        let hi = if v > mx { v } else { mx };
+        if hi - lo >= NUM_BITS || !fits_tiny_start(lo) {
+            return None;
         }
-        let mx = self.clone().max().unwrap();
-        let mn = self.clone().min().unwrap();
-        if v + NUM_BITS < mx || v > mn + NUM_BITS {
-            None
-        } else if v < mn {
-            self.bits = self.bits << (self.start-v) | 1;
-            self.start = v;
-            Some(false)
-        } else if self.start < mn {
-            self.bits = self.bits >> (mn - self.start) | 1 << (v-mn);
-            self.start = mn;
-            Some(false)
+        // Rebase the bitmap so that it starts at the smallest element.
+        self.bits = if lo < self.start {
+            self.bits << (self.start - lo)
         } else {
-            self.bits = self.bits | 1 << (v-self.start);
-            Some(false)
-        }
+            self.bits >> (lo - self.start)
+        };
+        self.start = lo;
+        self.bits |= 1 << (v - lo);
+        Some(false)
     }
     fn remove(&mut self, v: u32) -> bool {
         if self.contains(v) {
-            self.bits = self.bits & !(1 << v-self.start);
+            self.bits &= !(1 << (v - self.start));
             true
         } else {
             false
@@ -134,14 +154,14 @@ impl Tiny {
 #[test]
 fn check_tiny_insert() {
     let mut t = Tiny::from_singleton(0).unwrap();
-    println!("starting with {:?}", t.clone().collect::<Vec<_>>());
-    for v in [0,1,1,2,29,30].iter().cloned() {
+    println!("starting with {:?}", t.collect::<Vec<_>>());
+    for v in [0, 1, 1, 2, 28, 29].iter().cloned() {
         assert_eq!(Some(t.contains(v)), t.insert(v));
-        println!(" after inserting {}: {:?}", v, t.clone().collect::<Vec<_>>());
+        println!(" after inserting {}: {:?}", v, t.collect::<Vec<_>>());
         assert!(t.contains(v));
     }
 
-    for v in [0,30,2,29,1].iter().cloned() {
+    for v in [0, 29, 2, 28, 1].iter().cloned() {
         assert!(t.contains(v));
         assert!(t.remove(v));
         assert!(!t.remove(v));
@@ -150,14 +170,14 @@ fn check_tiny_insert() {
     assert_eq!(t.len(), 0);
 
     let mut t = Tiny::from_singleton(50).unwrap();
-    println!("starting with {:?}", t.clone().collect::<Vec<_>>());
-    for v in [49,40,30,21].iter().cloned() {
+    println!("starting with {:?}", t.collect::<Vec<_>>());
+    for v in [49, 40, 30, 21].iter().cloned() {
         assert_eq!(Some(t.contains(v)), t.insert(v));
-        println!(" after inserting {}: {:?}", v, t.clone().collect::<Vec<_>>());
+        println!(" after inserting {}: {:?}", v, t.collect::<Vec<_>>());
         assert!(t.contains(v));
     }
-    for v in [49,40,30,21].iter().cloned() {
-        println!("removing {} from {:?}", v, t.clone().collect::<Vec<_>>());
+    for v in [49, 40, 30, 21].iter().cloned() {
+        println!("removing {} from {:?}", v, t.collect::<Vec<_>>());
         assert!(t.contains(v));
         assert!(t.remove(v));
         assert!(!t.remove(v));
@@ -165,7 +185,7 @@ fn check_tiny_insert() {
     }
 }
 #[cfg(test)]
-proptest!{
+proptest! {
     #[test]
     fn check_tiny_from_slice(v in prop::collection::vec(0..NUM_BITS+4, 0usize..34)) {
         if let Some(t) = Tiny::from_slice(&v) {
@@ -201,17 +221,17 @@ proptest!{
     #[test]
     fn check_tiny_from_singleton(x in 0..=MAX_TINY) {
         let t = Tiny::from_singleton(x).unwrap();
-        assert_eq!(t.clone().next(), Some(x));
-        assert_eq!(t.clone().count(), 1);
+        assert_eq!(t.min(), Some(x));
+        assert_eq!(t.count(), 1);
         assert!(t.contains(x));
-        assert!(!t.contains(x+1));
+        assert!(!t.contains(x.wrapping_add(1)));
         assert_eq!(t.len(), 1);
     }
     #[test]
     fn check_tiny_from_inserts(x0 in 0..=MAX_TINY,
                                vals in prop::collection::vec(0..NUM_BITS, 1usize..10)) {
         let mut t = Tiny::from_singleton(x0).unwrap();
-        for v in vals.iter().cloned().map(|v| v+x0) {
+        for v in vals.iter().filter_map(|v| v.checked_add(x0)) {
             assert_eq!(Some(t.contains(v)), t.insert(v));
             assert!(t.contains(v));
         }
@@ -231,8 +251,24 @@ proptest!{
     }
 }
 
-/// A set of u32
-pub struct SetU32(I);
+/// A set of u32, stored as a table of `(key, bitmap)` pairs.
+///
+/// ```
+/// use tinyset::SetU32b;
+///
+/// let mut s: SetU32b = (0..100).map(|x| x * 1000).collect();
+/// assert!(s.insert(7));
+/// assert!(!s.insert(7));
+/// assert!(s.contains(5000));
+/// assert_eq!(s.len(), 101);
+/// ```
+pub struct SetU32b(I);
+
+unsafe impl Send for SetU32b {}
+unsafe impl Sync for SetU32b {}
+
+use crate::copyset::impl_set_methods;
+impl_set_methods!(SetU32b);
 
 union I {
     tiny: usize,
@@ -257,14 +293,8 @@ fn from_key(key: u32) -> u32 {
 enum Internal<'a> {
     Empty,
     Tiny(Tiny),
-    Table {
-        sz: u32,
-        a: &'a [(u32,u32)],
-    },
-    Dense {
-        sz: u32,
-        a: &'a [u32],
-    },
+    Table { sz: u32, a: &'a [(u32, u32)] },
+    Dense { sz: u32, a: &'a [u32] },
 }
 enum InternalMut<'a> {
     Empty,
@@ -273,7 +303,7 @@ enum InternalMut<'a> {
         sz: &'a mut u32,
         cap_ptr: &'a mut u32,
         available: u32,
-        a: &'a mut [(u32,u32)],
+        a: &'a mut [(u32, u32)],
     },
     Dense {
         sz: &'a mut u32,
@@ -281,16 +311,20 @@ enum InternalMut<'a> {
     },
 }
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug, PartialEq, Eq)]
 enum SetType {
     Table,
-    Dense
+    Dense,
 }
 fn decide_set_type(mx: u32, sz: u32) -> SetType {
-    if mx < (1 + sz)*64 { SetType::Dense } else { SetType::Table }
+    if mx < (1 + sz) * 64 {
+        SetType::Dense
+    } else {
+        SetType::Table
+    }
 }
 
-impl SetU32 {
+impl SetU32b {
     fn internal<'a>(&'a self) -> Internal<'a> {
         unsafe {
             if self.0.tiny == 0 {
@@ -301,15 +335,14 @@ impl SetU32 {
                 let s = &*self.0.ptr;
                 // assert_eq!((s.cap & 31).count_ones(), 1);
                 let cap = 1 << (s.cap & 31);
-                let start = &s.array as *const u32 as usize as *const (u32,u32);
+                let start = &s.array as *const u32 as usize as *const (u32, u32);
                 let a = std::slice::from_raw_parts(start, cap);
                 assert!(a.len().count_ones() == 1);
                 Internal::Table { sz: s.sz, a }
             } else {
                 let ptr = (self.0.tiny & !3) as *mut S;
                 let s = &*ptr;
-                let a = std::slice::from_raw_parts(&s.array as *const u32,
-                                                   s.cap as usize);
+                let a = std::slice::from_raw_parts(&s.array as *const u32, s.cap as usize);
                 Internal::Dense { sz: s.sz, a }
             }
         }
@@ -325,37 +358,43 @@ impl SetU32 {
                 let s = &mut *self.0.ptr;
                 let cap = 1 << (s.cap & 31);
                 let available = s.cap >> 5;
-                let start = &mut s.array as *mut u32 as usize as *mut (u32,u32);
+                let start = &mut s.array as *mut u32 as usize as *mut (u32, u32);
                 let a = std::slice::from_raw_parts_mut(start, cap);
                 assert!(a.len().count_ones() == 1);
-                InternalMut::Table { sz: &mut s.sz, cap_ptr: &mut s.cap, available, a }
+                InternalMut::Table {
+                    sz: &mut s.sz,
+                    cap_ptr: &mut s.cap,
+                    available,
+                    a,
+                }
             } else {
                 let ptr = (self.0.tiny & !3) as *mut S;
                 let s = &mut *ptr;
-                let a = std::slice::from_raw_parts_mut(&mut s.array as *mut u32,
-                                                       s.cap as usize);
+                let a = std::slice::from_raw_parts_mut(&mut s.array as *mut u32, s.cap as usize);
                 InternalMut::Dense { sz: &mut s.sz, a }
             }
         }
     }
     /// The total memory used by the set (including stack)
     pub fn mem_used(&self) -> usize {
-        let mut tot = std::mem::size_of::<usize>();
+        std::mem::size_of::<Self>() + self.heap_bytes()
+    }
+    /// The number of bytes allocated on the heap for this set.
+    pub fn heap_bytes(&self) -> usize {
+        let mut tot = 0;
         unsafe {
-            if self.0.tiny == 0 {
-                // nothing
-            } else if self.0.tiny & 3 == 1 {
-                // nothing
+            if self.0.tiny == 0 || self.0.tiny & 3 == 1 {
+                // nothing on the heap
             } else if self.0.tiny & 3 == 0 {
                 let s = &*self.0.ptr;
                 // assert_eq!((s.cap & 31).count_ones(), 1);
                 let cap = 1 << (s.cap & 31);
-                tot += std::mem::size_of::<S>() - 4 + cap*8;
+                tot += std::mem::size_of::<S>() - 4 + cap * 8;
                 // println!("mem_used for table {}", tot);
             } else {
                 let ptr = (self.0.tiny & !3) as *mut S;
                 let s = &*ptr;
-                tot += std::mem::size_of::<S>() - 4 + s.cap as usize*4;
+                tot += std::mem::size_of::<S>() - 4 + s.cap as usize * 4;
                 // println!("mem_used for dense {}", tot);
             }
         }
@@ -364,7 +403,7 @@ impl SetU32 {
 
     fn num_u32(&self) -> u32 {
         match self.internal() {
-            Internal::Table { a, .. } => a.len() as u32*2,
+            Internal::Table { a, .. } => a.len() as u32 * 2,
             Internal::Dense { a, .. } => a.len() as u32,
             _ => 0,
         }
@@ -379,26 +418,56 @@ impl SetU32 {
             Internal::Tiny(t) => t.len(),
         }
     }
+    /// The capacity of the set
+    pub fn capacity(&self) -> usize {
+        match self.internal() {
+            Internal::Table { a, .. } => a.len(),
+            Internal::Dense { a, .. } => a.len(),
+            Internal::Empty | Internal::Tiny(_) => 0,
+        }
+    }
+    /// The name of the representation and the number of bits in each
+    /// entry of its table that hold a bitmap, for `{:#?}`.
+    pub(crate) fn layout(&self) -> (&'static str, u64) {
+        match self.internal() {
+            Internal::Empty => ("empty", 0),
+            Internal::Tiny(_) => ("tiny", 0),
+            Internal::Table { .. } => ("table", 32),
+            Internal::Dense { .. } => ("dense", 32),
+        }
+    }
 
     /// Create a new empty set.
     ///
     /// This does no heap allocation.
     pub const fn new() -> Self {
-        SetU32(I { tiny: 0 })
+        SetU32b(I { tiny: 0 })
     }
 
     /// Iterate over the set
     #[inline]
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item=u32> + 'a {
-        match self.internal() {
-            Internal::Empty => Iter::Empty,
-            Internal::Tiny(t) => Iter::Tiny( t ),
-            Internal::Table { a, sz, .. } => {
-                Iter::Table ( TableIter::new(sz as usize, a) )
-            }
-            Internal::Dense { a, sz } => {
-                Iter::Dense( DenseIter::new(sz as usize, a) )
-            }
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        Iter::new(self)
+    }
+
+    /// Iterate over the elements in ascending order.
+    ///
+    /// Small and dense sets are stored in order already.  Otherwise this
+    /// collects and sorts the elements of the table.
+    ///
+    /// ```
+    /// use tinyset::SetU32b;
+    ///
+    /// let a: SetU32b = [30, 1, 2000, 7].iter().cloned().collect();
+    /// assert_eq!(a.iter_sorted().collect::<Vec<_>>(), vec![1, 7, 30, 2000]);
+    /// ```
+    pub fn iter_sorted<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        if let Internal::Table { .. } = self.internal() {
+            let mut v: Vec<u32> = self.iter().collect();
+            v.sort_unstable();
+            Sorted::Table(v.into_iter())
+        } else {
+            Sorted::InOrder(Iter::new(self))
         }
     }
 
@@ -427,6 +496,11 @@ impl SetU32 {
         }
     }
 
+    /// Remove all of the elements, returning an iterator over them.
+    pub fn drain(&mut self) -> IntoIter {
+        std::mem::take(self).into_iter()
+    }
+
     /// Remove a number from the set.
     ///
     /// Return a bool indicating if it was present.
@@ -435,7 +509,7 @@ impl SetU32 {
             InternalMut::Empty => false,
             InternalMut::Tiny(mut t) => {
                 let b = t.remove(e);
-                *self = SetU32(I { tiny: t.to_usize() });
+                *self = SetU32b(I { tiny: t.to_usize() });
                 b
             }
             InternalMut::Dense { a, sz } => {
@@ -443,7 +517,7 @@ impl SetU32 {
                 let bit = 1 << (e & 31);
                 if a.len() > key {
                     if a[key] & bit != 0 {
-                        a[key] = a[key] & !bit;
+                        a[key] &= !bit;
                         *sz -= 1;
                         true
                     } else {
@@ -480,31 +554,35 @@ impl SetU32 {
 
     /// Insert a number into the set.
     ///
-    /// Return a bool indicating if it was already present.
+    /// Return true if it was not already present.
     pub fn insert(&mut self, e: u32) -> bool {
         match self.internal_mut() {
             InternalMut::Empty => {
                 if let Some(t) = Tiny::from_singleton(e) {
-                    *self = SetU32::tiny(t);
+                    *self = SetU32b::tiny(t);
                 } else if e < 64 {
                     // println!("allocating dense from empty");
-                    *self = SetU32::dense_for_mx(e+1);
+                    *self = SetU32b::dense_for_mx(e + 1);
                     self.insert(e);
                 } else {
                     // println!("allocating from empty");
-                    *self = SetU32::table_with_cap(1);
+                    *self = SetU32b::table_with_cap(1);
                     self.insert(e);
                 }
-                false
+                true
             }
             InternalMut::Tiny(mut t) => {
                 if let Some(b) = t.insert(e) {
-                    *self = SetU32::tiny(t);
-                    b
+                    *self = SetU32b::tiny(t);
+                    !b
                 } else if e < 128 && t.max().unwrap() < 128 {
                     // println!("===allocating dense from tiny tinylen {} for e {}", t.len(), e);
-                    let mx = if e > t.max().unwrap() { e } else { t.max().unwrap() };
-                    *self = SetU32::dense_for_mx(mx);
+                    let mx = if e > t.max().unwrap() {
+                        e
+                    } else {
+                        t.max().unwrap()
+                    };
+                    *self = SetU32b::dense_for_mx(mx);
                     for x in t {
                         // println!("===  inserting {}", x);
                         self.insert(x);
@@ -512,10 +590,10 @@ impl SetU32 {
                     // println!("===  inserting e {}", e);
                     self.insert(e);
                     // println!("===should now have dense with len 2: {}", self.len());
-                    false
+                    true
                 } else {
                     // println!("== allocating from tiny");
-                    *self = SetU32::table_with_cap(1);
+                    *self = SetU32b::table_with_cap(1);
                     // self.mem_used();
                     for x in t {
                         // println!("== inserting {}", x);
@@ -525,7 +603,7 @@ impl SetU32 {
                     // self.mem_used();
                     // println!("== DONE");
                     self.insert(e);
-                    false
+                    true
                 }
             }
             InternalMut::Dense { sz, a } => {
@@ -533,22 +611,22 @@ impl SetU32 {
                 let bit = 1 << (e & 31);
                 if a.len() > key {
                     let was_here = a[key] & bit != 0;
-                    a[key] = a[key] | bit;
+                    a[key] |= bit;
                     if !was_here {
                         *sz += 1;
                     }
-                    was_here
+                    !was_here
                 } else {
-                    if key > 3*(*sz as usize) {
+                    if key > 3 * (*sz as usize) {
                         let nkeys = a.iter().filter(|&&x| x != 0).count() as u32;
                         // println!("thing is getting sparse adding {} new cap {} from dense nkeys {}/{} with sz {}!",
                         //          e, log2(nkeys+1), nkeys, a.len(), *sz);
                         // It is getting sparse, so let us switch back
                         // to a non-hash table.
-                        let mut new = SetU32::table_with_cap(log2(nkeys+1));
+                        let mut new = SetU32b::table_with_cap(log2(nkeys + 1));
                         // println!("   Dense thing was {:?}",
                         //          DenseIter::new( *sz as usize, a ).collect::<Vec<_>>());
-                        for x in DenseIter::new( *sz as usize, a ) {
+                        for x in DenseIter::new(*sz as usize, a) {
                             new.insert(x);
                             // println!("      addinng {}",
                             //          x,
@@ -561,10 +639,15 @@ impl SetU32 {
                         *sz += 1;
                         unsafe { self.dense_increase_mx(e)[key] = bit };
                     }
-                    false
+                    true
                 }
             }
-            InternalMut::Table { sz, cap_ptr, a, available } => {
+            InternalMut::Table {
+                sz,
+                cap_ptr,
+                a,
+                available,
+            } => {
                 // assert_eq!(available,
                 //            a.iter().filter(|&x| x == &(0,0)).count() as u32);
                 let (key, bits) = to_key_and_bit(e);
@@ -573,14 +656,20 @@ impl SetU32 {
                     let cap = a.len().trailing_zeros();
                     let newcap = cap + 1;
                     // println!("cap {} from {} -> {}", cap, a.len(), newcap);
-                    let mut new = SetU32::table_with_cap(newcap);
-                    if let InternalMut::Table { a: newa, sz: newsz, cap_ptr, .. } = new.internal_mut() {
+                    let mut new = SetU32b::table_with_cap(newcap);
+                    if let InternalMut::Table {
+                        a: newa,
+                        sz: newsz,
+                        cap_ptr,
+                        ..
+                    } = new.internal_mut()
+                    {
                         *newsz = *sz;
-                        for x in a.iter() {
+                        for x in a.iter().filter(|&x| x != &(0, 0)) {
                             let idx = p_insert_better(x.0, newa);
                             newa[idx.unwrap()] = *x;
                         }
-                        let av = newa.iter().filter(|&x| x == &(0,0)).count() as u32;
+                        let av = newa.iter().filter(|&x| x == &(0, 0)).count() as u32;
                         *cap_ptr = (av << 5) | newcap;
                     }
                     *self = new;
@@ -589,13 +678,13 @@ impl SetU32 {
                 match p_insert_better(key, a) {
                     Inserted::Present(i) => {
                         if a[i].1 & bits == 0 {
-                            a[i].1 = a[i].1 | bits;
+                            a[i].1 |= bits;
                             *sz += 1;
                             // println!("=> key already here");
-                            false
+                            true
                         } else {
                             // println!("=> already here");
-                            true
+                            false
                         }
                     }
                     Inserted::EmptySpot(i) => {
@@ -603,7 +692,7 @@ impl SetU32 {
                         *sz += 1;
                         *cap_ptr -= 32;
                         // println!("=> found an empty spot");
-                        false
+                        true
                     }
                     Inserted::NoRoom => {
                         // Need a bigger table!
@@ -611,7 +700,7 @@ impl SetU32 {
                         let mx = TableIter::new(*sz as usize, a).max().unwrap();
                         let mut new = match decide_set_type(mx, *sz) {
                             SetType::Dense => {
-                                let mut new = SetU32::dense_for_mx(mx);
+                                let mut new = SetU32b::dense_for_mx(mx);
                                 for x in self.iter() {
                                     new.insert(x);
                                 }
@@ -622,14 +711,20 @@ impl SetU32 {
                                 let newcap = cap + 1;
                                 // println!("XXX    {:?}", a);
                                 // println!("XXX foo cap {} from {} -> {}", cap, a.len(), newcap);
-                                let mut new = SetU32::table_with_cap(newcap);
-                                if let InternalMut::Table { a: newa, sz: newsz, cap_ptr, .. } = new.internal_mut() {
+                                let mut new = SetU32b::table_with_cap(newcap);
+                                if let InternalMut::Table {
+                                    a: newa,
+                                    sz: newsz,
+                                    cap_ptr,
+                                    ..
+                                } = new.internal_mut()
+                                {
                                     *newsz = *sz;
-                                    for x in a.iter() {
+                                    for x in a.iter().filter(|&x| x != &(0, 0)) {
                                         let idx = p_insert_better(x.0, newa);
                                         newa[idx.unwrap()] = *x;
                                     }
-                                    let av = newa.iter().filter(|&x| x == &(0,0)).count() as u32;
+                                    let av = newa.iter().filter(|&x| x == &(0, 0)).count() as u32;
                                     *cap_ptr = (av << 5) | newcap;
                                 }
                                 new
@@ -637,7 +732,7 @@ impl SetU32 {
                         };
                         new.insert(e);
                         *self = new;
-                        false
+                        true
                     }
                 }
             }
@@ -645,27 +740,34 @@ impl SetU32 {
     }
 
     fn tiny(t: Tiny) -> Self {
-        SetU32(I { tiny: t.to_usize()})
+        SetU32b(I { tiny: t.to_usize() })
     }
 
     fn dense_for_mx(mx: u32) -> Self {
-        let n = 1 + mx/32 + mx/128;
+        SetU32b::dense_with_cap(1 + mx / 32 + mx / 128)
+    }
+
+    fn dense_with_cap(n: u32) -> Self {
         unsafe {
             let newptr = std::alloc::alloc_zeroed(layout_for_num_u32(n)) as *mut S;
             (*newptr).cap = n;
-            SetU32(I { tiny: newptr as usize | 2 })
+            SetU32b(I {
+                tiny: newptr as usize | 2,
+            })
         }
     }
 
     /// This requires that we currently be a dense!
     unsafe fn dense_increase_mx(&mut self, mx: u32) -> &mut [u32] {
         let ptr = (self.0.tiny & !3) as *mut S;
-        let n = 1 + mx/32 + mx/128;
+        let n = 1 + mx / 32 + mx / 128;
 
         let oldcap = (*ptr).cap;
-        let newptr = std::alloc::realloc(ptr as *mut u8,
-                                         layout_for_num_u32(oldcap),
-                                         bytes_for_num_u32(n)) as *mut S;
+        let newptr = std::alloc::realloc(
+            ptr as *mut u8,
+            layout_for_num_u32(oldcap),
+            bytes_for_num_u32(n),
+        ) as *mut S;
         if newptr as usize == 0 {
             std::alloc::handle_alloc_error(layout_for_num_u32(n));
         }
@@ -673,8 +775,8 @@ impl SetU32 {
         self.0.tiny = newptr as usize | 2;
         match self.internal_mut() {
             InternalMut::Dense { a, .. } => {
-                for i in oldcap as usize .. a.len() {
-                    a[i] = 0;
+                for x in a[oldcap as usize..].iter_mut() {
+                    *x = 0;
                 }
                 a
             }
@@ -685,7 +787,9 @@ impl SetU32 {
     fn table_with_cap(cap: u32) -> Self {
         // assert_eq!(cap.count_ones(), 1);
         unsafe {
-            let x = SetU32(I { ptr: std::alloc::alloc_zeroed(layout_for_num_u32(1<<(cap+1))) as *mut S});
+            let x = SetU32b(I {
+                ptr: std::alloc::alloc_zeroed(layout_for_num_u32(1 << (cap + 1))) as *mut S,
+            });
             (*x.0.ptr).cap = cap | (1 << (cap + 5));
             x
         }
@@ -696,23 +800,243 @@ impl SetU32 {
 fn table_sizes() {
     let size_without_data = std::mem::size_of::<S>() - 4;
     let size_usize = std::mem::size_of::<usize>();
-    assert_eq!(SetU32::table_with_cap(1).mem_used(),
-               size_usize + size_without_data + 8*2);
-    assert_eq!(SetU32::table_with_cap(2).mem_used(),
-               size_usize + size_without_data + 8*4);
-
-    let v: SetU32 = (1..1000).map(|x| x*64).collect();
+    assert_eq!(
+        SetU32b::table_with_cap(1).mem_used(),
+        size_usize + size_without_data + 8 * 2
+    );
+    assert_eq!(
+        SetU32b::table_with_cap(2).mem_used(),
+        size_usize + size_without_data + 8 * 4
+    );
+
+    let v: SetU32b = (1..1000).map(|x| x * 64).collect();
     assert_eq!(10000 + std::mem::size_of::<usize>(), v.mem_used());
 }
 
-impl Default for SetU32 {
+impl Default for SetU32b {
     fn default() -> Self {
-        SetU32(I { tiny: 0})
+        SetU32b(I { tiny: 0 })
+    }
+}
+
+impl Clone for SetU32b {
+    fn clone(&self) -> Self {
+        let n = self.num_u32();
+        unsafe {
+            if n == 0 {
+                return SetU32b(I { tiny: self.0.tiny });
+            }
+            let tag = self.0.tiny & 3;
+            let ptr = std::alloc::alloc(layout_for_num_u32(n));
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout_for_num_u32(n));
+            }
+            std::ptr::copy_nonoverlapping(
+                (self.0.tiny & !3) as *const u8,
+                ptr,
+                bytes_for_num_u32(n),
+            );
+            SetU32b(I {
+                tiny: ptr as usize | tag,
+            })
+        }
+    }
+}
+
+impl SetU32b {
+    /// Create an empty set with capacity to hold the provided set.
+    ///
+    /// ```
+    /// use tinyset::SetU32b;
+    ///
+    /// let a: SetU32b = (1..300).map(|x| x * 100).collect();
+    /// let mut b = SetU32b::with_capacity_of(&a);
+    /// let before = b.mem_used();
+    /// assert_eq!(b.len(), 0);
+    /// b.extend(a.iter());
+    /// assert_eq!(b.mem_used(), before);
+    /// assert_eq!(b, a);
+    /// ```
+    pub fn with_capacity_of(other: &Self) -> Self {
+        match other.internal() {
+            Internal::Table { a, .. } => SetU32b::table_with_cap(a.len().trailing_zeros()),
+            Internal::Dense { a, .. } => SetU32b::dense_with_cap(a.len() as u32),
+            _ => SetU32b::new(),
+        }
+    }
+}
+
+impl Extend<u32> for SetU32b {
+    /// An empty set is sized for all of the new elements at once, as by
+    /// `collect`.
+    fn extend<T: IntoIterator<Item = u32>>(&mut self, iter: T) {
+        if self.is_empty() {
+            *self = iter.into_iter().collect();
+        } else {
+            for x in iter {
+                self.insert(x);
+            }
+        }
+    }
+}
+
+impl IntoIterator for SetU32b {
+    type Item = u32;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        let sz_left = self.len();
+        if let Internal::Tiny(tiny) = self.internal() {
+            IntoIter {
+                tiny,
+                set: SetU32b::new(),
+                sz_left: 0,
+                whichword: 0,
+                whichbit: 0,
+            }
+        } else {
+            IntoIter {
+                tiny: Tiny { start: 0, bits: 0 },
+                set: self,
+                sz_left,
+                whichword: 0,
+                whichbit: 0,
+            }
+        }
+    }
+}
+
+/// An iterator over a set of `u32`, which owns the set.
+#[derive(Debug, Clone)]
+pub struct IntoIter {
+    tiny: Tiny,
+    set: SetU32b,
+    sz_left: usize,
+    whichword: usize,
+    whichbit: u32,
+}
+
+impl Iterator for IntoIter {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        if let Some(x) = self.tiny.next() {
+            return Some(x);
+        }
+        if self.sz_left == 0 {
+            return None;
+        }
+        // Pick up the heap storage where we left off.
+        let (sz_left, whichword, whichbit) = (self.sz_left, self.whichword, self.whichbit);
+        let (next, whichword, whichbit) = match self.set.internal() {
+            Internal::Dense { a, .. } => {
+                let mut it = DenseIter {
+                    sz_left,
+                    whichword,
+                    whichbit,
+                    a,
+                };
+                (it.next(), it.whichword, it.whichbit)
+            }
+            Internal::Table { a, .. } => {
+                let mut it = TableIter {
+                    sz_left,
+                    whichword,
+                    whichbit,
+                    a,
+                };
+                (it.next(), it.whichword, it.whichbit)
+            }
+            _ => unreachable!(),
+        };
+        self.sz_left -= 1;
+        self.whichword = whichword;
+        self.whichbit = whichbit;
+        next
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.tiny.len() + self.sz_left;
+        (n, Some(n))
+    }
+    #[inline]
+    fn count(self) -> usize {
+        self.tiny.len() + self.sz_left
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde {
+    use super::SetU32b;
+    use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+    impl Serialize for SetU32b {
+        /// The elements are written in ascending order.  There is no
+        /// compact form of this type, so `compactserde` does not change
+        /// the format.
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let mut seq = serializer.serialize_seq(Some(self.len()))?;
+            for e in self.iter_sorted() {
+                seq.serialize_element(&e)?;
+            }
+            seq.end()
+        }
+    }
+
+    struct SetVisitor;
+
+    impl<'de> Visitor<'de> for SetVisitor {
+        type Value = SetU32b;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a set of u32")
+        }
+
+        fn visit_seq<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+        where
+            M: SeqAccess<'de>,
+        {
+            let mut v = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some(elem) = access.next_element()? {
+                v.push(elem);
+            }
+            Ok(v.into_iter().collect())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SetU32b {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_seq(SetVisitor)
+        }
+    }
+
+    #[test]
+    fn serialize_deserialize() {
+        for v in [
+            vec![],
+            vec![3, 1, 2],
+            (0..1000).rev().map(|x| x * 7).collect::<Vec<_>>(),
+            vec![u32::MAX, 0, 5, 1 << 20],
+        ] {
+            let set: SetU32b = v.iter().cloned().collect();
+            let mut sorted = v.clone();
+            sorted.sort();
+            let s = serde_json::to_string(&set).unwrap();
+            assert_eq!(s, serde_json::to_string(&sorted).unwrap());
+            assert_eq!(set, serde_json::from_str(&s).unwrap());
+        }
     }
 }
 
 fn log2(x: u32) -> u32 {
-    std::mem::size_of::<u32>() as u32*8 - x.leading_zeros()
+    std::mem::size_of::<u32>() as u32 * 8 - x.leading_zeros()
 }
 
 #[test]
@@ -723,22 +1047,22 @@ fn test_log2() {
     assert_eq!(3, log2(4));
 }
 
-impl std::iter::FromIterator<u32> for SetU32 {
+impl std::iter::FromIterator<u32> for SetU32b {
     fn from_iter<T>(iter: T) -> Self
-        where
-        T: IntoIterator<Item = u32>
+    where
+        T: IntoIterator<Item = u32>,
     {
         let v: Vec<_> = iter.into_iter().collect();
         if let Some(mx) = v.iter().cloned().max() {
             if let Some(t) = Tiny::from_slice(&v) {
-                SetU32(I { tiny: t.to_usize() })
+                SetU32b(I { tiny: t.to_usize() })
             } else {
                 let mut new = match decide_set_type(mx, v.len() as u32) {
-                    SetType::Dense => SetU32::dense_for_mx(mx),
+                    SetType::Dense => SetU32b::dense_for_mx(mx),
                     SetType::Table => {
                         let cap = {
-                            let mut vv: Vec<_> = v.iter().cloned()
-                                .map(|x| to_key_and_bit(x).0).collect();
+                            let mut vv: Vec<_> =
+                                v.iter().cloned().map(|x| to_key_and_bit(x).0).collect();
                             vv.sort();
                             vv.dedup();
                             // println!("   vv.len is {}", vv.len());
@@ -746,7 +1070,7 @@ impl std::iter::FromIterator<u32> for SetU32 {
                         };
                         // println!("FOO CAP {} from {:?}", cap, v);
                         // let cap = v.len() as u32;
-                        SetU32::table_with_cap(cap)
+                        SetU32b::table_with_cap(cap)
                     }
                 };
                 for x in v.iter().cloned() {
@@ -755,7 +1079,7 @@ impl std::iter::FromIterator<u32> for SetU32 {
                 new
             }
         } else {
-            SetU32(I { tiny: 0})
+            SetU32b(I { tiny: 0 })
         }
     }
 }
@@ -763,10 +1087,10 @@ impl std::iter::FromIterator<u32> for SetU32 {
 #[cfg(test)]
 fn test_a_collect(v: Vec<u32>) {
     println!("test_a_collect({:?})", &v);
-    let s: SetU32 = v.iter().cloned().collect();
+    let s: SetU32b = v.iter().cloned().collect();
     println!("  s is {:?}", s.iter().collect::<Vec<_>>());
     let vv: Vec<_> = s.iter().collect();
-    let ss: SetU32 = vv.iter().cloned().collect();
+    let ss: SetU32b = vv.iter().cloned().collect();
     let vvv: Vec<_> = ss.iter().collect();
     assert_eq!(vv, vvv);
 }
@@ -775,20 +1099,20 @@ fn test_a_collect(v: Vec<u32>) {
 fn test_collect() {
     test_a_collect(vec![]);
     test_a_collect(vec![0]);
-    test_a_collect(vec![0,1<<29]);
-    test_a_collect(vec![0,1<<30,1<<30]);
+    test_a_collect(vec![0, 1 << 29]);
+    test_a_collect(vec![0, 1 << 30, 1 << 30]);
     test_a_collect((0..1024).collect());
 }
 
 const fn bytes_for_num_u32(sz: u32) -> usize {
-    sz as usize*4+std::mem::size_of::<S>()-4
+    sz as usize * 4 + std::mem::size_of::<S>() - 4
 }
 
 unsafe fn layout_for_num_u32(sz: u32) -> std::alloc::Layout {
     std::alloc::Layout::from_size_align_unchecked(bytes_for_num_u32(sz), 4)
 }
 
-impl Drop for SetU32 {
+impl Drop for SetU32b {
     fn drop(&mut self) {
         if unsafe { self.0.tiny != 0 && self.0.tiny & 3 != 1 } {
             let n = self.num_u32();
@@ -805,9 +1129,9 @@ impl Drop for SetU32 {
 #[test]
 fn test_insert_remove() {
     let mut v: Vec<u32> = Vec::new();
-    let mut s = SetU32::new();
+    let mut s = SetU32b::new();
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
-    assert!(!s.insert(5));
+    assert!(s.insert(5));
     v.push(5);
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
 
@@ -816,12 +1140,12 @@ fn test_insert_remove() {
     assert_eq!(v.iter().cloned().max(), s.iter().max());
     assert_eq!(v.iter().cloned().min(), s.iter().min());
 
-    assert!(!s.insert(10));
     assert!(s.insert(10));
+    assert!(!s.insert(10));
     v.push(10);
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
 
-    assert!(!s.insert(30));
+    assert!(s.insert(30));
     v.push(30);
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
     assert_eq!(v.len(), s.iter().count());
@@ -830,7 +1154,7 @@ fn test_insert_remove() {
     assert_eq!(v.iter().cloned().min(), s.iter().min());
 
     println!("inserting 40");
-    assert!(!s.insert(40));
+    assert!(s.insert(40));
     v.push(40);
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
     assert_eq!(v.len(), s.iter().count());
@@ -839,7 +1163,7 @@ fn test_insert_remove() {
     assert_eq!(v.iter().cloned().min(), s.iter().min());
 
     println!("inserting 50");
-    assert!(!s.insert(50));
+    assert!(s.insert(50));
     v.push(50);
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
     assert_eq!(v.len(), s.iter().count());
@@ -857,7 +1181,7 @@ fn test_insert_remove() {
     assert_eq!(v.iter().cloned().min(), s.iter().min());
 
     for i in 0..500 {
-        let n = i*2;
+        let n = i * 2;
         println!("inserting {}", n);
         s.insert(n);
         v.push(n);
@@ -870,11 +1194,11 @@ fn test_insert_remove() {
         assert_eq!(v.iter().cloned().min(), s.iter().min());
     }
 
-    println!("now inserting {}", 1<<20);
-    assert!(!s.insert(1<<20));
-    v.push(1<<20);
+    println!("now inserting {}", 1 << 20);
+    assert!(s.insert(1 << 20));
+    v.push(1 << 20);
     // at this point it should be a table...
-    assert!(s.contains(1<<20));
+    assert!(s.contains(1 << 20));
     let mut vvv = s.iter().collect::<Vec<u32>>();
     vvv.sort();
     println!("  v: {:?}", v);
@@ -902,9 +1226,9 @@ fn test_insert_remove() {
     assert_eq!(v.iter().cloned().min(), s.iter().min());
 
     println!("removing 1<<20");
-    assert!(s.remove(1<<20));
-    v.retain(|x| *x != 1<<20);
-    assert!(!s.contains(1<<20));
+    assert!(s.remove(1 << 20));
+    v.retain(|x| *x != 1 << 20);
+    assert!(!s.contains(1 << 20));
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
     assert_eq!(v.len(), s.iter().count());
     assert_eq!(v.len(), s.len());
@@ -913,7 +1237,7 @@ fn test_insert_remove() {
 
     println!("removing 2 again");
     assert!(!s.remove(2));
-    assert!(!s.contains(1<<20));
+    assert!(!s.contains(1 << 20));
     assert_eq!(&v, &s.iter().collect::<Vec<u32>>());
     assert_eq!(v.len(), s.iter().count());
     assert_eq!(v.len(), s.len());
@@ -928,6 +1252,16 @@ enum Iter<'a> {
     Dense(DenseIter<'a>),
     Table(TableIter<'a>),
 }
+impl<'a> Iter<'a> {
+    fn new(set: &'a SetU32b) -> Self {
+        match set.internal() {
+            Internal::Empty => Iter::Empty,
+            Internal::Tiny(t) => Iter::Tiny(t),
+            Internal::Table { a, sz, .. } => Iter::Table(TableIter::new(sz as usize, a)),
+            Internal::Dense { a, sz } => Iter::Dense(DenseIter::new(sz as usize, a)),
+        }
+    }
+}
 impl<'a> Iterator for Iter<'a> {
     type Item = u32;
     #[inline]
@@ -986,6 +1320,29 @@ impl<'a> Iterator for Iter<'a> {
     }
 }
 
+#[derive(Debug)]
+enum Sorted<'a> {
+    InOrder(Iter<'a>),
+    Table(std::vec::IntoIter<u32>),
+}
+impl<'a> Iterator for Sorted<'a> {
+    type Item = u32;
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            Sorted::InOrder(i) => i.next(),
+            Sorted::Table(i) => i.next(),
+        }
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Sorted::InOrder(i) => i.size_hint(),
+            Sorted::Table(i) => i.size_hint(),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct DenseIter<'a> {
     sz_left: usize,
@@ -1012,15 +1369,15 @@ impl<'a> Iterator for DenseIter<'a> {
                 if *word != 0 {
                     let w = *word as u64 & !((1 << self.whichbit) - 1);
                     let bit = w.trailing_zeros();
-                    let foo = self.whichword as u32;
-                    if w.wrapping_shr(bit+1) == 0 {
+                    let key = self.whichword as u32;
+                    if w.wrapping_shr(bit + 1) == 0 {
                         self.whichword += 1;
                         self.whichbit = 0;
                     } else {
-                        self.whichbit = bit+1;
+                        self.whichbit = bit + 1;
                     }
                     self.sz_left -= 1;
-                    return Some((foo << 5) + bit as u32);
+                    return Some((key << 5) + bit);
                 }
                 self.whichword += 1;
             } else {
@@ -1044,24 +1401,23 @@ impl<'a> Iterator for DenseIter<'a> {
 
 #[test]
 fn test_denseiter() {
-    let v: Vec<u32> = DenseIter::new(5, &[1,1,1,0,1,0,2]).collect();
+    let v: Vec<u32> = DenseIter::new(5, &[1, 1, 1, 0, 1, 0, 2]).collect();
     assert_eq!(&v, &[0, 32, 64, 128, 193]);
 
-    assert_eq!(Some(0), DenseIter::new(5, &[1,1,1,0,1,0,2]).min());
+    assert_eq!(Some(0), DenseIter::new(5, &[1, 1, 1, 0, 1, 0, 2]).min());
 
-    assert_eq!(Some(34), DenseIter::new(5, &[0,4,1,0,1,0,2]).min());
+    assert_eq!(Some(34), DenseIter::new(5, &[0, 4, 1, 0, 1, 0, 2]).min());
 }
 
-
 #[derive(Debug)]
 struct TableIter<'a> {
     sz_left: usize,
     whichword: usize,
     whichbit: u32,
-    a: &'a [(u32,u32)],
+    a: &'a [(u32, u32)],
 }
 impl<'a> TableIter<'a> {
-    fn new(sz_left: usize, a: &'a [(u32,u32)]) -> Self {
+    fn new(sz_left: usize, a: &'a [(u32, u32)]) -> Self {
         TableIter {
             sz_left,
             whichword: 0,
@@ -1079,14 +1435,14 @@ impl<'a> Iterator for TableIter<'a> {
                 if word.1 != 0 {
                     let w = word.1 as u64 & !((1 << self.whichbit) - 1);
                     let bit = w.trailing_zeros();
-                    if w.wrapping_shr(bit+1) == 0 {
+                    if w.wrapping_shr(bit + 1) == 0 {
                         self.whichword += 1;
                         self.whichbit = 0;
                     } else {
-                        self.whichbit = bit+1;
+                        self.whichbit = bit + 1;
                     }
                     self.sz_left -= 1;
-                    return Some(from_key(word.0) | bit as u32);
+                    return Some(from_key(word.0) | bit);
                 }
                 self.whichword += 1;
             } else {
@@ -1104,7 +1460,9 @@ impl<'a> Iterator for TableIter<'a> {
     }
     #[inline]
     fn min(self) -> Option<u32> {
-        self.a.iter().filter(|&x| x != &(0,0))
+        self.a
+            .iter()
+            .filter(|&x| x != &(0, 0))
             .map(|word| from_key(word.0) | word.1.trailing_zeros())
             .min()
     }
@@ -1113,9 +1471,10 @@ impl<'a> Iterator for TableIter<'a> {
         if self.sz_left == 0 {
             None
         } else {
-            self.a.iter()
-                .filter(|&x| x != &(0,0))
-                .map(|word| from_key(word.0) | (31-word.1.leading_zeros()))
+            self.a
+                .iter()
+                .filter(|&x| x != &(0, 0))
+                .map(|word| from_key(word.0) | (31 - word.1.leading_zeros()))
                 .max()
         }
     }
@@ -1123,7 +1482,7 @@ impl<'a> Iterator for TableIter<'a> {
 
 #[test]
 fn test_tableiter() {
-    let v: Vec<u32> = TableIter::new(5, &[(0,1),(1,1)]).collect();
+    let v: Vec<u32> = TableIter::new(5, &[(0, 1), (1, 1)]).collect();
     assert_eq!(&v, &[0, 32]);
 
     // assert_eq!(Some(0), TableIter::new(5, &[1,1,1,0,1,0,2]).min());
@@ -1131,13 +1490,11 @@ fn test_tableiter() {
     // assert_eq!(Some(34), TableIter::new(5, &[0,4,1,0,1,0,2]).min());
 }
 
-
-
 fn p_poverty(k: u32, idx: usize, n: usize) -> usize {
     ((idx % n) + n - (k % n as u32) as usize) % n
 }
 
-#[derive(Debug,Eq,PartialEq,Clone,Copy)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum Inserted {
     EmptySpot(usize),
     Present(usize),
@@ -1158,33 +1515,38 @@ impl Inserted {
 
 /// This inserts k into the array, and requires that there be room for
 /// one more element.  Otherwise, things will be sad.
-fn p_insert_better(k: u32, a: &mut [(u32,u32)]) -> Inserted {
+fn p_insert_better(k: u32, a: &mut [(u32, u32)]) -> Inserted {
     let n = a.len();
     let poverty_limit = n;
     for pov in 0..poverty_limit {
         let ii = ((k as u64 + pov as u64) % n as u64) as usize;
         let ki = a[ii].0;
         let pov_ki = p_poverty(ki, ii, n);
-        if a[ii] == (0,0) {
+        if a[ii] == (0, 0) {
             return Inserted::EmptySpot(ii);
         } else if ki == k {
             // println!("already got a spot");
             return Inserted::Present(ii);
         } else if pov_ki < pov {
             // println!("need to steal from {} < {} at spot {}", pov_ki, pov, ii);
+            // Make sure the displaced entries have somewhere to go
+            // before we disturb anything.
+            if !(1..n).any(|j| a[(ii + j) % n] == (0, 0)) {
+                return Inserted::NoRoom;
+            }
             // need to steal
             let stolen = ii;
             let mut displaced = a[ii];
             // println!("displaced value is {}", displaced);
             let mut pov_displaced = pov_ki;
-            a[stolen] = (0,0);
+            a[stolen] = (0, 0);
 
             for j in 1..poverty_limit {
                 pov_displaced += 1;
-                let jj = (stolen + j) % n as usize;
+                let jj = (stolen + j) % n;
                 let kj = a[jj].0;
                 let pov_kj = p_poverty(kj, jj, n);
-                if a[jj] == (0,0) {
+                if a[jj] == (0, 0) {
                     // We finally found an unoccupied spot!
                     // println!("put the displaced at {}", jj);
                     a[jj] = displaced;
@@ -1205,35 +1567,35 @@ fn p_insert_better(k: u32, a: &mut [(u32,u32)]) -> Inserted {
 /// This inserts k into the array, and requires that there be room for
 /// one more element.  Otherwise, things will be sad.
 #[cfg(test)]
-fn p_insert(k: u32, a: &mut [(u32,u32)]) -> usize {
+fn p_insert(k: u32, a: &mut [(u32, u32)]) -> usize {
     p_insert_better(k, a).expect("p_insert without room")
 }
 
 #[test]
 fn test_insert() {
-    let mut a = [(0,0),(0,0),(0,0),(0,0)];
+    let mut a = [(0, 0), (0, 0), (0, 0), (0, 0)];
     assert_eq!(2, p_insert(2, &mut a));
-    assert_eq!(&a, &[(0,0),(0,0),(0,0),(0,0)]);
+    assert_eq!(&a, &[(0, 0), (0, 0), (0, 0), (0, 0)]);
     for i in 0..10 {
-        assert_eq!((0,0), a[p_insert(i, &mut a)]);
+        assert_eq!((0, 0), a[p_insert(i, &mut a)]);
     }
 
-    let mut a = [(0,0),(0,0),(6,6),(0,0)];
+    let mut a = [(0, 0), (0, 0), (6, 6), (0, 0)];
     assert_eq!(3, p_insert(2, &mut a));
-    assert_eq!(&a, &[(0,0),(0,0),(6,6),(0,0)]);
+    assert_eq!(&a, &[(0, 0), (0, 0), (6, 6), (0, 0)]);
     for i in 0..10 {
-        assert!([0,i].contains(&a[p_insert(i, &mut a)].0));
+        assert!([0, i].contains(&a[p_insert(i, &mut a)].0));
     }
 
-    let mut a = [(0,0),(0,0),(6,6),(3,3)];
+    let mut a = [(0, 0), (0, 0), (6, 6), (3, 3)];
     assert_eq!(3, p_insert(2, &mut a));
-    assert_eq!(&a, &[(3,3),(0,0),(6,6),(0,0)]);
+    assert_eq!(&a, &[(3, 3), (0, 0), (6, 6), (0, 0)]);
     for i in 0..10 {
-        assert!([0,i].contains(&a[p_insert(i, &mut a)].0));
+        assert!([0, i].contains(&a[p_insert(i, &mut a)].0));
     }
 }
 
-#[derive(Debug,Eq,PartialEq,Clone,Copy)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum LookedUp {
     EmptySpot(usize),
     KeyFound(usize),
@@ -1242,11 +1604,7 @@ enum LookedUp {
 impl LookedUp {
     #[cfg(test)]
     fn key_found(self) -> bool {
-        if let LookedUp::KeyFound(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, LookedUp::KeyFound(_))
     }
     #[cfg(test)]
     fn unwrap(self) -> usize {
@@ -1258,11 +1616,11 @@ impl LookedUp {
     }
 }
 
-fn p_lookfor(k: u32, a: &[(u32,u32)]) -> LookedUp {
+fn p_lookfor(k: u32, a: &[(u32, u32)]) -> LookedUp {
     let n = a.len();
     for pov in 0..n {
         let ii = ((k as u64 + pov as u64) % n as u64) as usize;
-        if a[ii] == (0,0) {
+        if a[ii] == (0, 0) {
             // println!("got empty spot at {} for key {}", ii, k);
             return LookedUp::EmptySpot(ii);
         }
@@ -1281,17 +1639,26 @@ fn p_lookfor(k: u32, a: &[(u32,u32)]) -> LookedUp {
 
 #[test]
 fn test_lookfor() {
-    assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[(3,10),(1,5),(2,2)]));
-    assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[(3,10),(0,0),(2,3)]));
-    assert_eq!(LookedUp::KeyFound(3), p_lookfor(7, &[(0,0),(0,0),(0,0),(7,7)]));
+    assert_eq!(
+        LookedUp::NeedInsert,
+        p_lookfor(5, &[(3, 10), (1, 5), (2, 2)])
+    );
+    assert_eq!(
+        LookedUp::NeedInsert,
+        p_lookfor(5, &[(3, 10), (0, 0), (2, 3)])
+    );
+    assert_eq!(
+        LookedUp::KeyFound(3),
+        p_lookfor(7, &[(0, 0), (0, 0), (0, 0), (7, 7)])
+    );
 }
 
-fn p_remove(k: u32, a: &mut [(u32,u32)]) -> bool {
+fn p_remove(k: u32, a: &mut [(u32, u32)]) -> bool {
     let n = a.len();
     for i in 0..n {
         let ii = ((k as u64 + i as u64) % n as u64) as usize;
         // println!("    looking to remove at distance {} slot {}", i, ii);
-        if a[ii] == (0,0) {
+        if a[ii] == (0, 0) {
             return false;
         }
         let ki = a[ii].0;
@@ -1300,7 +1667,7 @@ fn p_remove(k: u32, a: &mut [(u32,u32)]) -> bool {
             return false;
         } else if ki == k {
             // println!("found {} at location {}", k, ii);
-            a[ii] = (0,0);
+            a[ii] = (0, 0);
             // Now we need to return anything that might have been
             // stolen from... to massacre my grammar.
             let mut previous = ii;
@@ -1309,7 +1676,7 @@ fn p_remove(k: u32, a: &mut [(u32,u32)]) -> bool {
                 // println!("looking at removing offset {} at location {}", j, jj);
                 let kj = a[jj].0;
                 let jkj = (((jj + n) as u32 - (kj % n as u32)) % n as u32) as usize;
-                if kj == 0 || jkj == 0 {
+                if a[jj] == (0, 0) || jkj == 0 {
                     // We found an unoccupied spot or a perfectly
                     // happy customer, so nothing else could have been
                     // bumped.
@@ -1317,7 +1684,7 @@ fn p_remove(k: u32, a: &mut [(u32,u32)]) -> bool {
                 }
                 // need to undo some stealing!
                 a[previous] = a[jj];
-                a[jj] = (0,0);
+                a[jj] = (0, 0);
                 previous = jj;
             }
             return true;
@@ -1327,18 +1694,18 @@ fn p_remove(k: u32, a: &mut [(u32,u32)]) -> bool {
 }
 
 #[cfg(test)]
-fn test_p_insert_remove(x: u32, a: &mut [(u32,u32)]) {
+fn test_p_insert_remove(x: u32, a: &mut [(u32, u32)]) {
     println!("test_insert_remove({}, {:?})", x, a);
-    let v: Vec<(u32,u32)> = a.iter().cloned().collect();
+    let v: Vec<(u32, u32)> = a.to_vec();
     assert!(!p_remove(x, a));
-    assert!(!a.contains(&(x,137))); // otherwise the test won't work right.
+    assert!(!a.contains(&(x, 137))); // otherwise the test won't work right.
     assert!(!a.iter().any(|z| z.0 == x)); // otherwise the test won't work right.
     assert!(!p_lookfor(x, a).key_found());
-    a[p_insert(x, a)] = (x,137);
-    assert!(a.contains(&(x,137)));
+    a[p_insert(x, a)] = (x, 137);
+    assert!(a.contains(&(x, 137)));
     println!("  after insertion of {} a is {:?}", x, a);
     assert!(p_lookfor(x, a).key_found());
-    assert_eq!((x,137), a[p_lookfor(x, a).unwrap()]);
+    assert_eq!((x, 137), a[p_lookfor(x, a).unwrap()]);
     assert!(p_remove(x, a));
     println!("  after remove of {} a is {:?}", x, a);
     assert_eq!(a, &v[..]);
@@ -1346,21 +1713,125 @@ fn test_p_insert_remove(x: u32, a: &mut [(u32,u32)]) {
 
 #[test]
 fn test_p_remove() {
-    let mut a = [(0,0),(0,0),(2,1)];
-    a[p_insert(5,&mut a)] = (5,3);
-    assert_eq!(&[(5,3),
-                 (0,0),
-                 (2,1)], &a);
-    p_remove(2,&mut a);
+    let mut a = [(0, 0), (0, 0), (2, 1)];
+    a[p_insert(5, &mut a)] = (5, 3);
+    assert_eq!(&[(5, 3), (0, 0), (2, 1)], &a);
+    p_remove(2, &mut a);
     println!("after removal {:?}", a);
     assert!(p_lookfor(5, &a).key_found());
-    assert_eq!(&[(0,0),
-                 (0,0),
-                 (5,3)], &a);
+    assert_eq!(&[(0, 0), (0, 0), (5, 3)], &a);
+
+    test_p_insert_remove(7, &mut [(0, 0), (0, 0), (0, 0), (0, 0)]);
+
+    test_p_insert_remove(2, &mut [(0, 0), (1, 1), (5, 5), (0, 0)]);
+
+    test_p_insert_remove(5, &mut [(0, 0), (0, 0), (2, 8)]);
+}
+
+#[cfg(test)]
+impl crate::copyset::CopySet for SetU32b {
+    type Item = u32;
+    type Iter = IntoIter;
+    fn ins(&mut self, e: u32) -> bool {
+        self.insert(e)
+    }
+    fn rem(&mut self, e: u32) -> bool {
+        self.remove(e)
+    }
+    fn con(&self, e: u32) -> bool {
+        self.contains(e)
+    }
+    fn vec(&self) -> Vec<u32> {
+        self.iter().collect()
+    }
+    fn ln(&self) -> usize {
+        self.len()
+    }
+    fn it(self) -> Self::Iter {
+        self.into_iter()
+    }
+}
 
-    test_p_insert_remove(7,&mut [(0,0),(0,0),(0,0),(0,0)]);
+#[cfg(test)]
+fn check_against_btreeset(ops: &[(bool, u32)]) {
+    use std::collections::BTreeSet;
+    let mut s = SetU32b::new();
+    let mut b = BTreeSet::new();
+    for &(add, x) in ops {
+        if add {
+            assert_eq!(s.insert(x), b.insert(x));
+        } else {
+            assert_eq!(s.remove(x), b.remove(&x));
+        }
+        assert_eq!(s.len(), b.len());
+    }
+    assert_eq!(
+        s.iter_sorted().collect::<Vec<_>>(),
+        b.iter().cloned().collect::<Vec<_>>()
+    );
+    assert_eq!(s.clone().into_iter().collect::<BTreeSet<_>>(), b);
+    let mut it = s.clone().into_iter();
+    assert_eq!(it.size_hint(), (b.len(), Some(b.len())));
+    it.next();
+    assert_eq!(it.count(), b.len().saturating_sub(1));
+    let c = s.clone();
+    s.insert(12345);
+    assert_eq!(c.iter().collect::<BTreeSet<_>>(), b);
+}
 
-    test_p_insert_remove(2,&mut [(0,0),(1,1),(5,5),(0,0)]);
+#[test]
+fn edges() {
+    crate::copyset::check_set::<SetU32b>(&[u32::MAX]);
+    crate::copyset::check_set::<SetU32b>(&[u32::MAX, u32::MAX - 3, 0]);
+    crate::copyset::check_set::<SetU32b>(&[0, 32, 64]);
+    // Growing from a full table must not lose the entries it displaces.
+    crate::copyset::check_set::<SetU32b>(&[128, 160, 1]);
+    crate::copyset::check_set::<SetU32b>(&(0..3000).map(|x| x * 33).collect::<Vec<_>>());
+    check_against_btreeset(&[
+        (true, 0),
+        (true, 29),
+        (true, 30),
+        (false, 0),
+        (true, u32::MAX),
+    ]);
+}
 
-    test_p_insert_remove(5,&mut [(0,0),(0,0),(2,8)]);
+#[test]
+fn set_methods() {
+    let a: SetU32b = (0..1000).map(|x| x * 3).collect();
+    let b: SetU32b = (0..1000).map(|x| x * 5).collect();
+    assert_eq!(&a & &b, (0..200).map(|x| x * 15).collect());
+    assert_eq!((&a | &b).len(), 1000 + 1000 - 200);
+    assert_eq!((&a - &b).len(), 800);
+    assert_eq!(a.clone(), a);
+    assert!(a < b);
+    let mut c = SetU32b::new();
+    c.extend(b.iter_sorted().collect::<Vec<_>>().into_iter().rev());
+    c.extend(a.iter());
+    assert_eq!(c, &a | &b);
+    assert_eq!(SetU32b::with_capacity_of(&c).len(), 0);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn copycheck_random_sets(slice in prop::collection::vec(1u32..5, 1usize..10)) {
+        crate::copyset::check_set::<SetU32b>(&slice);
+    }
+    #[test]
+    fn copycheck_medium_sets(slice in prop::collection::vec(1u32..255, 1usize..100)) {
+        crate::copyset::check_set::<SetU32b>(&slice);
+    }
+    #[test]
+    fn copycheck_big_sets(slice: Vec<u32>) {
+        crate::copyset::check_set::<SetU32b>(&slice);
+    }
+    #[test]
+    fn matches_btreeset(ops: Vec<(bool, u32)>) {
+        check_against_btreeset(&ops);
+    }
+    #[test]
+    fn matches_btreeset_small(ops in prop::collection::vec((any::<bool>(), 0u32..5000), 0usize..1000)) {
+        check_against_btreeset(&ops);
+    }
 }
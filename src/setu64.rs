@@ -4,6 +4,15 @@
 mod iter;
 pub use iter::IntoIter;
 
+mod view;
+pub use view::SetU64Ref;
+
+mod atomic;
+pub use atomic::AtomicSetU64;
+
+mod cursor;
+pub use cursor::Cursor;
+
 const fn num_bits<T>() -> u64 {
     std::mem::size_of::<T>() as u64 * 8
 }
@@ -159,8 +168,95 @@ pub struct SetU64(*mut S);
 unsafe impl Send for SetU64 {}
 unsafe impl Sync for SetU64 {}
 
-use crate::copyset::impl_set_methods;
-impl_set_methods!(SetU64);
+use crate::copyset::{impl_set_methods, FastOps};
+impl_set_methods!(SetU64, FastOps);
+
+/// Dense sets are combined a whole bitmap at a time.
+impl FastOps for SetU64 {
+    fn fast_eq(&self, other: &Self) -> Option<bool> {
+        let (a, b) = (self.dense_words()?, other.dense_words()?);
+        let n = a.len().min(b.len());
+        // The lengths are already known to be equal, so if the shared
+        // words match there can be no bits set beyond them.
+        Some(a[..n] == b[..n])
+    }
+    fn fast_union(&self, other: &Self) -> Option<Self> {
+        let (a, b) = (self.dense_words()?, other.dense_words()?);
+        let (mut s, rhs) = if a.len() >= b.len() {
+            (self.clone(), other)
+        } else {
+            (other.clone(), self)
+        };
+        s.dense_combine(rhs, crate::kernels::or_into, true);
+        Some(s)
+    }
+    fn fast_intersection(&self, other: &Self) -> Option<Self> {
+        let (a, b) = (self.dense_words()?, other.dense_words()?);
+        let (mut s, rhs) = if a.len() <= b.len() {
+            (self.clone(), other)
+        } else {
+            (other.clone(), self)
+        };
+        s.dense_combine(rhs, crate::kernels::and_into, false);
+        Some(s)
+    }
+    fn fast_difference(&self, other: &Self) -> Option<Self> {
+        other.dense_words()?;
+        self.dense_words()?;
+        let mut s = self.clone();
+        s.dense_combine(other, crate::kernels::andnot_into, true);
+        Some(s)
+    }
+    fn fast_union_with(&mut self, other: &Self) -> bool {
+        match (self.dense_words(), other.dense_words()) {
+            (Some(a), Some(b)) if a.len() >= b.len() => {
+                self.dense_combine(other, crate::kernels::or_into, true)
+            }
+            _ => false,
+        }
+    }
+    fn fast_difference_with(&mut self, other: &Self) -> bool {
+        self.dense_combine(other, crate::kernels::andnot_into, true)
+    }
+}
+
+impl SetU64 {
+    pub(crate) fn dense_words(&self) -> Option<&[u64]> {
+        match self.internal() {
+            Internal::Dense { a, .. } => Some(a),
+            _ => None,
+        }
+    }
+    /// Combines the bitmaps of two dense sets in place, returning false if
+    /// either set is not dense.  Words of `self` beyond the end of `other`
+    /// are kept if `keep_tail`, and cleared otherwise.
+    fn dense_combine(
+        &mut self,
+        other: &SetU64,
+        kernel: fn(&mut [u64], &[u64]) -> usize,
+        keep_tail: bool,
+    ) -> bool {
+        let b = match other.dense_words() {
+            Some(b) => b,
+            None => return false,
+        };
+        if let InternalMut::Dense { sz, a } = self.internal_mut() {
+            let n = a.len().min(b.len());
+            let (head, tail) = a.split_at_mut(n);
+            *sz = kernel(head, &b[..n]);
+            if keep_tail {
+                *sz += crate::kernels::popcount(tail);
+            } else {
+                for w in tail {
+                    *w = 0;
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug)]
@@ -170,11 +266,29 @@ struct S {
 }
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct Sbeginning {
     sz: usize,
     cap: usize,
     bits: u64,
+    /// The key mixed into the hash of the `Heap` and `Big` formats, or zero
+    /// to place elements by their own value.
+    #[cfg(feature = "keyed_hashing")]
+    hashkey: u64,
+}
+
+impl Sbeginning {
+    #[inline]
+    fn hashkey(&self) -> u64 {
+        #[cfg(feature = "keyed_hashing")]
+        {
+            self.hashkey
+        }
+        #[cfg(not(feature = "keyed_hashing"))]
+        {
+            0
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -209,10 +323,11 @@ impl Iterator for Tiny {
         }
     }
     fn count(self) -> usize {
-        self.sz as usize
+        (self.sz - self.sz_spent) as usize
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.sz as usize, Some(self.sz as usize))
+        let left = (self.sz - self.sz_spent) as usize;
+        (left, Some(left))
     }
     fn min(mut self) -> Option<u64> {
         self.next()
@@ -472,16 +587,457 @@ enum InternalMut<'a> {
     },
 }
 
+/// Something that holds a set in our internal representation, either a
+/// [`SetU64`] or a borrowed view of one.
+trait Storage {
+    fn internal(&self) -> Internal<'_>;
+}
+
+impl Storage for SetU64 {
+    #[inline]
+    fn internal(&self) -> Internal<'_> {
+        SetU64::internal(self)
+    }
+}
+
+impl<T: Storage + ?Sized> Storage for &T {
+    #[inline]
+    fn internal(&self) -> Internal<'_> {
+        (**self).internal()
+    }
+}
+
+impl<'a> Internal<'a> {
+    fn from_parts(b: &'a Sbeginning, a: &'a [u64]) -> Self {
+        if b.bits == 0 || b.bits > 64 {
+            Internal::Big { s: b, a }
+        } else if b.bits == 64 {
+            Internal::Dense { sz: b.sz, a }
+        } else {
+            Internal::Heap { s: b, a }
+        }
+    }
+    fn len(&self) -> usize {
+        match *self {
+            Internal::Empty => 0,
+            Internal::Stack(t) => t.sz as usize,
+            Internal::Heap { s, .. } => s.sz,
+            Internal::Big { s, .. } => s.sz,
+            Internal::Dense { sz, .. } => sz,
+        }
+    }
+    fn contains(&self, e: u64) -> bool {
+        match *self {
+            Internal::Empty => false,
+            Internal::Stack(t) => t.contains(e), // t.clone().any(|x| x == e),
+            Internal::Dense { a, .. } => {
+                let key = e >> 6;
+                if let Some(bits) = a.get(key as usize) {
+                    bits & (1 << (e & 63)) != 0
+                } else {
+                    false
+                }
+            }
+            Internal::Heap { s, a } => {
+                if compute_array_bits(e) < s.bits {
+                    // println!("too big a thing");
+                    return false;
+                }
+                let (key, offset) = split_u64(e, s.bits);
+                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits, s.hashkey()) {
+                    a[idx] & (1 << offset) != 0
+                } else {
+                    // println!("did not find key {} from {}: {:#?}", key, e, self);
+                    false
+                }
+            }
+            Internal::Big { s, a } => {
+                if e == s.bits {
+                    return false;
+                }
+                let e = if e == 0 { s.bits } else { e };
+                p_lookfor(e, a, 0, s.hashkey()).key_found()
+            }
+        }
+    }
+    /// Calls `f` with each value and whether it is in the set, stopping
+    /// early if `f` returns false.  Consecutive values that fall in the
+    /// same dense word or heap bucket share a single lookup, so sorted
+    /// input walks through the set in order.
+    fn probe(&self, vals: impl Iterator<Item = u64>, mut f: impl FnMut(u64, bool) -> bool) {
+        match *self {
+            Internal::Dense { a, .. } => {
+                for e in vals {
+                    let bits = a.get((e >> 6) as usize).cloned().unwrap_or(0);
+                    if !f(e, bits & (1 << (e & 63)) != 0) {
+                        return;
+                    }
+                }
+            }
+            Internal::Heap { s, a } => {
+                let mut bucket = None;
+                for e in vals {
+                    let found = if compute_array_bits(e) < s.bits {
+                        false
+                    } else {
+                        let (key, offset) = split_u64(e, s.bits);
+                        let bitmap = match bucket {
+                            Some((k, bitmap)) if k == key => bitmap,
+                            _ => {
+                                let bitmap = match p_lookfor(key, a, s.bits, s.hashkey()) {
+                                    LookedUp::KeyFound(idx) => a[idx],
+                                    _ => 0,
+                                };
+                                bucket = Some((key, bitmap));
+                                bitmap
+                            }
+                        };
+                        bitmap & (1 << offset) != 0
+                    };
+                    if !f(e, found) {
+                        return;
+                    }
+                }
+            }
+            _ => {
+                for e in vals {
+                    if !f(e, self.contains(e)) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How many elements `collect` and `extend` buffer and sort at a time.
+/// This bounds the memory they need beyond that of the set itself.
+const CHUNK: usize = 4096;
+
 impl Extend<u64> for SetU64 {
+    /// Adds the elements a chunk at a time, so the iterator is never
+    /// collected into one big buffer.  Each chunk is sorted and written
+    /// into a table that is grown once for it, with room for as many
+    /// more elements as the iterator's `size_hint` promises.
     fn extend<T: IntoIterator<Item = u64>>(&mut self, iter: T) {
-        for i in iter.into_iter() {
-            self.insert(i);
+        let mut iter = iter.into_iter();
+        let mut buf = Vec::with_capacity(iter.size_hint().0.clamp(1, CHUNK));
+        let mut grown = false;
+        loop {
+            buf.extend(iter.by_ref().take(CHUNK));
+            if buf.is_empty() {
+                if grown {
+                    self.shrink_table();
+                }
+                return;
+            }
+            let taken = buf.len();
+            let ascending = buf.windows(2).all(|w| w[0] < w[1]);
+            if !ascending {
+                buf.sort_unstable();
+                buf.dedup();
+            } else if self.0.is_null() {
+                if let Some(s) = SetU64::presized_dense(&buf, iter.size_hint()) {
+                    *self = s;
+                }
+            }
+            // Expect the rest to repeat themselves as often as this chunk.
+            let more = iter.size_hint().0.saturating_mul(buf.len()) / taken;
+            grown |= self.extend_chunk(&buf, more);
+            buf.clear();
+        }
+    }
+}
+
+impl SetU64 {
+    /// An empty dense set big enough for input that continues the way
+    /// `chunk` started, when `hint` tells us exactly how much more is
+    /// coming.  This saves repeatedly growing the bitmap when collecting
+    /// a long range.
+    fn presized_dense(chunk: &[u64], hint: (usize, Option<usize>)) -> Option<SetU64> {
+        if hint.1 != Some(hint.0) || hint.0 == 0 {
+            return None;
+        }
+        let first = chunk[0];
+        let last = chunk[chunk.len() - 1];
+        let span = (last - first).saturating_add(1);
+        let more = (hint.0 as u64).saturating_mul(span) / chunk.len() as u64;
+        let mx = last.saturating_add(more);
+        // Like collect has always done, only use a bitmap when at least
+        // one in sixteen of the bits will be set.
+        if (chunk.len() + hint.0) as u64 <= mx >> 4 {
+            return None;
+        }
+        Some(SetU64::dense_with_max(mx))
+    }
+
+    /// Adds the elements of `chunk`, which is sorted and deduplicated,
+    /// expecting at least `more` elements to follow it.  Returns whether
+    /// a hash table was grown with room for them.
+    fn extend_chunk(&mut self, mut chunk: &[u64], more: usize) -> bool {
+        while !chunk.is_empty() {
+            match self.internal_mut() {
+                InternalMut::Empty => {
+                    *self = SetU64::from_sorted_iter(chunk.iter().cloned());
+                    return false;
+                }
+                InternalMut::Stack(t) => {
+                    let mut v: Vec<u64> = t.chain(chunk.iter().cloned()).collect();
+                    v.sort_unstable();
+                    v.dedup();
+                    *self = SetU64::from_sorted_iter(v);
+                    return false;
+                }
+                InternalMut::Dense { sz, a } => {
+                    // The elements that fit in the bitmap come first, and
+                    // the rest grow it (or replace it) one at a time.
+                    let fit = chunk.partition_point(|&x| x >> 6 < a.len() as u64);
+                    for &x in &chunk[..fit] {
+                        let bit = 1 << (x & 63);
+                        if a[(x >> 6) as usize] & bit == 0 {
+                            a[(x >> 6) as usize] |= bit;
+                            *sz += 1;
+                        }
+                    }
+                    chunk = &chunk[fit..];
+                    if let Some(&x) = chunk.first() {
+                        self.insert(x);
+                        chunk = &chunk[1..];
+                    }
+                }
+                InternalMut::Heap { .. } | InternalMut::Big { .. } => {
+                    let mut grown = false;
+                    loop {
+                        let (keys, elems) = self.novelty(chunk);
+                        if self.has_room_for(chunk[chunk.len() - 1], keys) {
+                            break;
+                        }
+                        self.regrow(chunk, keys, elems, more);
+                        grown = true;
+                    }
+                    for &x in chunk {
+                        if self.insert_with_room(x).is_none() {
+                            self.insert(x);
+                        }
+                    }
+                    return grown;
+                }
+            }
+        }
+        false
+    }
+
+    /// How many keys and elements of the sorted `chunk` a hash table does
+    /// not yet hold.
+    fn novelty(&self, chunk: &[u64]) -> (usize, usize) {
+        match self.internal() {
+            Internal::Heap { s, a } => {
+                let (mut keys, mut elems) = (0, 0);
+                let mut bucket = None;
+                for &x in chunk {
+                    let (key, offset) = split_u64(x, s.bits);
+                    let word = match bucket {
+                        Some((k, w)) if k == key => w,
+                        _ => {
+                            let w = match p_lookfor(key, a, s.bits, s.hashkey()) {
+                                LookedUp::KeyFound(idx) => a[idx],
+                                _ => {
+                                    keys += 1;
+                                    0
+                                }
+                            };
+                            bucket = Some((key, w));
+                            w
+                        }
+                    };
+                    if word & (1 << offset) == 0 {
+                        elems += 1;
+                    }
+                }
+                (keys, elems)
+            }
+            _ => {
+                let n = chunk.iter().filter(|&&x| !self.contains(x)).count();
+                (n, n)
+            }
+        }
+    }
+
+    /// Whether a hash table can take elements up to `last` that need
+    /// `keys` new words without passing the load that `from_sorted_iter`
+    /// leaves.
+    fn has_room_for(&self, last: u64, keys: usize) -> bool {
+        let room = |sz: usize, a: &[u64]| {
+            let limit = a.len() * 10 / 11;
+            sz + keys <= limit || a.iter().filter(|&&w| w != 0).count() + keys <= limit
+        };
+        match self.internal() {
+            Internal::Heap { s, a } => compute_array_bits(last) >= s.bits && room(s.sz, a),
+            Internal::Big { s, a } => room(s.sz, a),
+            Internal::Dense { a, .. } => last >> 6 < a.len() as u64,
+            Internal::Empty | Internal::Stack(_) => false,
+        }
+    }
+
+    /// Moves a hash table into a bigger table, or into a bitmap if that
+    /// is now more compact, for adding the sorted `chunk`, of which `keys`
+    /// keys and `elems` elements are new, with `more` elements to come.
+    /// The rest of the input is expected to bring new keys and elements
+    /// at the rate `chunk` does, and to keep climbing if `chunk` lies
+    /// beyond the set, and the table grows by at least half when it runs
+    /// out of room, so that a poor guess costs few moves.
+    fn regrow(&mut self, chunk: &[u64], keys: usize, elems: usize, more: usize) {
+        let (first, last) = (chunk[0], chunk[chunk.len() - 1]);
+        let mut mx = self.iter().max().unwrap_or(0);
+        let ascending = first > mx;
+        // Without a hint, expect climbing input to go on as long again.
+        let more = if ascending && more == 0 {
+            self.len() + chunk.len()
+        } else {
+            more
+        };
+        mx = if ascending {
+            let span = (last - first).saturating_mul(more as u64) / chunk.len() as u64;
+            last.saturating_add(span)
+        } else {
+            mx.max(last)
+        };
+        let expected = elems.saturating_mul(more) / chunk.len();
+        let bits = compute_array_bits(mx);
+        let runs = |xs: &mut dyn Iterator<Item = u64>| {
+            let mut prev = None;
+            xs.filter(|&x| {
+                let key = split_u64(x, bits).0;
+                prev.replace(key) != Some(key)
+            })
+            .count()
+        };
+        // Counting runs of equal keys gives an upper bound on how many
+        // words the elements need, which is exact when `bits` is
+        // unchanged.
+        let same_bits = match self.internal() {
+            Internal::Heap { s, .. } => s.bits == bits,
+            _ => bits == 0,
+        };
+        let words = runs(&mut self.iter())
+            + if same_bits {
+                keys
+            } else {
+                runs(&mut chunk.iter().cloned())
+            };
+        // A table that is full grows by half, and one whose `bits` must
+        // change keeps its size, since it will likely fill up next.
+        let floor = if same_bits {
+            self.capacity() * 3 / 2
+        } else {
+            self.capacity()
+        };
+        let cap = ((words + 1) * 11 / 10).max(floor);
+        // Like `from_sorted_iter`, use a bitmap when one in sixteen of its
+        // bits will be set, and like `insert` when it would be smaller
+        // than a table for the elements we have.
+        let len = (self.len() + elems).saturating_add(expected);
+        let mut new = if len as u64 > mx >> 4 || cap as u64 > mx >> 6 {
+            SetU64::dense_with_max(mx)
+        } else {
+            let projected = (words + keys.saturating_mul(more) / chunk.len() + 1) * 11 / 10;
+            SetU64::with_capacity_and_bits(cap.max(projected), bits)
+        };
+        for x in self.iter() {
+            if new.insert_with_room(x).is_none() {
+                new.insert(x);
+            }
+        }
+        *self = new;
+    }
+
+    /// Moves a hash table that is less than half full into one that is
+    /// as full as `from_sorted_iter` leaves it, for when the input has
+    /// repeated itself more than `regrow` expected.
+    fn shrink_table(&mut self) {
+        let (used, bits) = match self.internal() {
+            Internal::Heap { s, a } => (a.iter().filter(|&&w| w != 0).count(), s.bits),
+            Internal::Big { s, .. } => (s.sz, 0),
+            _ => return,
+        };
+        let cap = (used + 1) * 11 / 10;
+        if cap * 2 > self.capacity() {
+            return;
+        }
+        let mut new = SetU64::with_capacity_and_bits(cap, bits);
+        for x in self.iter() {
+            if new.insert_with_room(x).is_none() {
+                new.insert(x);
+            }
+        }
+        *self = new;
+    }
+
+    /// Inserts `e` into a table that has room for it, never growing or
+    /// changing its layout.  Gives `None` when `insert` is needed: for an
+    /// element beyond a bitmap, or the one that a `Big` table uses as its
+    /// stand-in for zero.
+    fn insert_with_room(&mut self, e: u64) -> Option<bool> {
+        match self.internal_mut() {
+            InternalMut::Heap { s, a } => {
+                let (key, offset) = split_u64(e, s.bits);
+                let idx = match p_lookfor(key, a, s.bits, s.hashkey()) {
+                    LookedUp::KeyFound(idx) | LookedUp::EmptySpot(idx) => idx,
+                    LookedUp::NeedInsert => p_insert(key, a, s.bits, s.hashkey()),
+                };
+                let bit = 1 << offset;
+                let added = a[idx] & bit == 0;
+                a[idx] |= key << s.bits | bit;
+                s.sz += added as usize;
+                Some(added)
+            }
+            InternalMut::Big { s, a } => {
+                if e == s.bits {
+                    return None;
+                }
+                let e = if e == 0 { s.bits } else { e };
+                let idx = match p_lookfor(e, a, 0, s.hashkey()) {
+                    LookedUp::KeyFound(_) => return Some(false),
+                    LookedUp::EmptySpot(idx) => idx,
+                    LookedUp::NeedInsert => p_insert(e, a, 0, s.hashkey()),
+                };
+                a[idx] = e;
+                s.sz += 1;
+                Some(true)
+            }
+            InternalMut::Dense { sz, a } => {
+                let w = a.get_mut((e >> 6) as usize)?;
+                let bit = 1 << (e & 63);
+                let added = *w & bit == 0;
+                *w |= bit;
+                *sz += added as usize;
+                Some(added)
+            }
+            InternalMut::Empty | InternalMut::Stack(_) => None,
         }
     }
 }
 
-#[cfg(feature = "compactserde")]
 impl SetU64 {
+    /// Returns the set in the compact layout read by [`SetU64Ref`].
+    ///
+    /// The layout is the in-memory representation of the set, so it
+    /// depends on the platform and is not stable between versions of
+    /// `tinyset`.
+    ///
+    /// ```
+    /// use tinyset::{SetU64, SetU64Ref};
+    ///
+    /// let a: SetU64 = (0..1000).map(|x| x * 3).collect();
+    /// let words = a.to_compact();
+    /// let view = SetU64Ref::new(&words).unwrap();
+    /// assert_eq!(view.len(), 1000);
+    /// assert!(view.contains(999));
+    /// assert!(!view.contains(1000));
+    /// ```
+    pub fn to_compact(&self) -> Vec<u64> {
+        self.to_array()
+    }
     fn to_array(&self) -> Vec<u64> {
         let mut out = Vec::new();
         if self.0 as usize == 0 || self.0 as usize & 7 != 0 {
@@ -492,7 +1048,18 @@ impl SetU64 {
             let a = unsafe { std::slice::from_raw_parts(&s.array as *const u64, b.cap) };
             out.push(b.sz as u64);
             out.push(b.bits as u64);
-            out.extend(a);
+            if b.hashkey() == 0 || b.bits == 64 {
+                out.extend(a);
+            } else {
+                // The compact layout places elements by their own value.
+                let offset = if b.bits < 64 { b.bits } else { 0 };
+                let mut table = vec![0; a.len()];
+                for &x in a.iter().filter(|&&x| x != 0) {
+                    let idx = p_insert(x >> offset, &mut table, offset, 0);
+                    table[idx] = x;
+                }
+                out.extend(table);
+            }
         }
         out
     }
@@ -509,19 +1076,11 @@ impl SetU64 {
                         *o = *i;
                     }
                 }
-                InternalMut::Heap { s, a } => {
-                    s.sz = v[0] as usize;
-                    s.bits = v[1];
-                    for (i, o) in v[2..].iter().zip(a.iter_mut()) {
-                        *o = *i;
-                    }
-                }
-                InternalMut::Big { s, a } => {
+                InternalMut::Heap { s, a } | InternalMut::Big { s, a } => {
                     s.sz = v[0] as usize;
                     s.bits = v[1];
-                    for (i, o) in v[2..].iter().zip(a.iter_mut()) {
-                        *o = *i;
-                    }
+                    let offset = if s.bits < 64 { s.bits } else { 0 };
+                    place_words(a, offset, s.hashkey(), v[2..].iter().cloned());
                 }
             }
             set
@@ -531,7 +1090,20 @@ impl SetU64 {
     }
 }
 
-#[cfg(feature = "compactserde")]
+/// Fills the hash table `a`, whose hash key is `key`, with `words` that
+/// were placed by their own value, as in the compact layout.  With a key
+/// the words must be placed again.
+fn place_words(a: &mut [u64], offset: u64, key: u64, words: impl Iterator<Item = u64>) {
+    if key == 0 {
+        a.iter_mut().zip(words).for_each(|(w, x)| *w = x);
+    } else {
+        for x in words.filter(|&x| x != 0) {
+            let idx = p_insert(x >> offset, a, offset, key);
+            a[idx] = x;
+        }
+    }
+}
+
 #[test]
 fn to_from_array() {
     use std::iter::FromIterator;
@@ -582,13 +1154,14 @@ mod serde {
             }
             seq.end()
         }
+        /// The elements are written in ascending order.
         #[cfg(not(feature = "compactserde"))]
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: Serializer,
         {
             let mut seq = serializer.serialize_seq(Some(self.len()))?;
-            for e in self.iter() {
+            for e in self.iter_sorted() {
                 seq.serialize_element(&e)?;
             }
             seq.end()
@@ -692,6 +1265,25 @@ mod serde {
         let s = serde_json::to_string(&set).unwrap();
         assert_eq!(set, serde_json::from_str(&s).unwrap());
     }
+
+    #[cfg(not(feature = "compactserde"))]
+    #[test]
+    fn serialize_sorted() {
+        for v in [
+            vec![3, 1, 2],
+            (0..1000).rev().map(|x| x * 7).collect::<Vec<_>>(),
+            (0..1000).map(|x| x * 7919 % 100003).collect(),
+            vec![u64::MAX, 0, 5, 1 << 20],
+        ] {
+            let set: SetU64 = v.iter().cloned().collect();
+            let mut sorted = v.clone();
+            sorted.sort();
+            sorted.dedup();
+            let s = serde_json::to_string(&set).unwrap();
+            assert_eq!(s, serde_json::to_string(&sorted).unwrap());
+            assert_eq!(set, serde_json::from_str(&s).unwrap());
+        }
+    }
 }
 
 impl Clone for SetU64 {
@@ -770,13 +1362,7 @@ impl SetU64 {
     /// The number of elements in the set
     #[inline]
     pub fn len(&self) -> usize {
-        match self.internal() {
-            Internal::Empty => 0,
-            Internal::Stack(t) => t.sz as usize,
-            Internal::Heap { s, .. } => s.sz,
-            Internal::Big { s, .. } => s.sz,
-            Internal::Dense { sz, .. } => sz,
-        }
+        self.internal().len()
     }
     /// The capacity of the set
     #[inline]
@@ -789,51 +1375,30 @@ impl SetU64 {
             Internal::Dense { a, .. } => a.len(),
         }
     }
-    /// Print debugging information about this set.
-    pub fn debug_me(&self, msg: &str) {
+    /// The name of the representation and the number of bits in each
+    /// entry of its table that hold a bitmap, for `{:#?}`.
+    pub(crate) fn layout(&self) -> (&'static str, u64) {
         match self.internal() {
-            Internal::Empty => println!("empty set: {}", msg),
-            Internal::Stack(t) => println!("{}: stack {:?} => {:?}", msg, t, t.collect::<Vec<_>>()),
-            Internal::Heap { s, a } => {
-                println!("{}: heap {:?}", msg, s);
-                for (i, x) in a.iter().cloned().enumerate() {
-                    println!(
-                        "      {} (key {} pov {}): {:0b} ({})",
-                        (x >> s.bits) * s.bits,
-                        x >> s.bits,
-                        p_poverty(x >> s.bits, i, a.len()),
-                        x & mask(s.bits as usize),
-                        x
-                    );
-                }
-                println!("    => {:?}", self.iter().collect::<Vec<_>>());
-            }
-            Internal::Big { s, a } => {
-                println!("{}: big {:?}\n    {:?}", msg, s, a);
-                let v: Vec<_> = a.iter().cloned().map(|x| x % a.len() as u64).collect();
-                println!("     >>>{:?}", v);
-            }
-            Internal::Dense { sz, a } => {
-                println!(
-                    "{}: dense {:?}\n    {:?}\n    => {:?}",
-                    msg,
-                    sz,
-                    a,
-                    self.iter().collect::<Vec<u64>>()
-                );
-                println!("    foo {:?}", self.iter());
-            }
+            Internal::Empty => ("empty", 0),
+            Internal::Stack(_) => ("stack", 0),
+            Internal::Heap { s, .. } => ("heap", s.bits),
+            Internal::Big { .. } => ("big", 0),
+            Internal::Dense { .. } => ("dense", 64),
         }
     }
-    /// Tally up how much memory is in use.
+    /// Tally up how much memory is in use, including the set itself.
     #[inline]
     pub fn mem_used(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_bytes()
+    }
+    /// The number of bytes allocated on the heap for this set.
+    #[inline]
+    pub fn heap_bytes(&self) -> usize {
         match self.internal() {
-            Internal::Empty => std::mem::size_of::<Self>(),
-            Internal::Stack(_) => std::mem::size_of::<Self>(),
-            Internal::Heap { s, .. } => std::mem::size_of::<Self>() + s.cap * 8 - 8,
-            Internal::Dense { a, .. } => std::mem::size_of::<Self>() + a.len() * 8 - 8,
-            Internal::Big { s, .. } => std::mem::size_of::<Self>() + s.cap * 8 - 8,
+            Internal::Empty | Internal::Stack(_) => 0,
+            Internal::Heap { a, .. } | Internal::Big { a, .. } | Internal::Dense { a, .. } => {
+                bytes_for_capacity(a.len())
+            }
         }
     }
     fn dense_with_max(mx: u64) -> SetU64 {
@@ -875,10 +1440,18 @@ impl SetU64 {
                 }
                 let x = SetU64(ptr);
                 (*x.0).b.cap = cap;
+                #[cfg(feature = "keyed_hashing")]
+                if bits != 64 {
+                    (*x.0).b.hashkey = crate::rand::hashkey(cap, bits);
+                }
                 (*x.0).b.bits = if bits == 0 {
                     let mut b = 0;
+                    let mut tries = 0;
                     while b <= 64 {
-                        b = crate::rand::rand64(cap, bits);
+                        // A seeded random number depends only on its
+                        // arguments, so vary them when trying again.
+                        b = crate::rand::rand64(cap, tries);
+                        tries += 1;
                     }
                     b
                 } else {
@@ -890,6 +1463,32 @@ impl SetU64 {
             SetU64(0 as *mut S)
         }
     }
+    /// A set of `sz` elements in the dense or hash-table layout given by
+    /// `bits`, whose table is copied from `words`.  The words of a hash
+    /// table are placed by their own value, as in the compact layout.
+    pub(crate) fn from_raw_parts(
+        sz: usize,
+        bits: u64,
+        words: impl ExactSizeIterator<Item = u64>,
+    ) -> SetU64 {
+        debug_assert!(bits > 0 && bits <= 64);
+        if sz == 0 {
+            return SetU64::new();
+        }
+        let mut set = SetU64::with_capacity_and_bits(words.len(), bits);
+        match set.internal_mut() {
+            InternalMut::Dense { sz: n, a } => {
+                a.iter_mut().zip(words).for_each(|(w, x)| *w = x);
+                *n = sz;
+            }
+            InternalMut::Heap { s, a } => {
+                place_words(a, s.bits, s.hashkey(), words);
+                s.sz = sz;
+            }
+            _ => unreachable!(),
+        }
+        set
+    }
     /// An empty set
     #[inline]
     pub const fn new() -> Self {
@@ -915,10 +1514,10 @@ impl SetU64 {
                 let mx = t.max().unwrap();
                 let mx = if e > mx { e } else { mx };
                 *self = Self::with_capacity_and_max(t.sz as usize + 1, mx);
-                // self.debug_me("empty array");
+                // println!("empty array: {:#?}", self);
                 for x in t {
                     self.insert(x);
-                    // self.debug_me(&format!("   ...after inserting {}", x));
+                    // println!("   ...after inserting {}: {:#?}", x, self);
                 }
                 self.insert(e);
                 return true;
@@ -976,18 +1575,18 @@ impl SetU64 {
                         s.cap + 1 + 2 * (crate::rand::rand_usize(s.cap, s.bits) % s.cap),
                         compute_array_bits(e),
                     );
-                    // new.debug_me("\n\nnew set");
+                    // println!("\n\nnew set: {:#?}", new);
                     for d in self.iter() {
                         new.insert(d);
-                        // new.debug_me(&format!("\n -- after inserting {}", d));
+                        // println!("\n -- after inserting {}: {:#?}", d, new);
                     }
                     new.insert(e);
-                    // new.debug_me(&format!("\n -- after inserting {}", e));
+                    // println!("\n -- after inserting {}: {:#?}", e, new);
                     *self = new;
                     return true;
                 }
                 let (key, offset) = split_u64(e, s.bits);
-                match p_lookfor(key, a, s.bits) {
+                match p_lookfor(key, a, s.bits, s.hashkey()) {
                     LookedUp::KeyFound(idx) => {
                         if a[idx] & (1 << offset) != 0 {
                             return false;
@@ -1006,7 +1605,7 @@ impl SetU64 {
                 }
                 // println!("looking for space in sparse... {:?}", a);
                 if a.iter().cloned().any(|x| x == 0) {
-                    let idx = p_insert(key, a, s.bits);
+                    let idx = p_insert(key, a, s.bits, s.hashkey());
                     // println!("about to insert key {} with elem {} at {}",
                     //          key, e, idx);
                     a[idx] = (key << s.bits) | (1 << offset);
@@ -1035,7 +1634,7 @@ impl SetU64 {
                     // A dense set will cost us memory
                     let newcap: usize = s.cap + 1 + (crate::rand::rand_usize(s.cap, s.bits) % s.cap);
                     let mut new = Self::with_capacity_and_bits(newcap, s.bits);
-                    // new.debug_me("initial new");
+                    // println!("initial new: {:#?}", new);
                     for v in self.iter() {
                         new.insert(v);
                     }
@@ -1052,20 +1651,22 @@ impl SetU64 {
                     // which feels like overkill.  But the cost of
                     // changing the "bits" is $O(N)$, so it's worth
                     // a high O(1) cost to reduce collisions.
-                    let had_zero = p_remove(s.bits, a, 0);
+                    let had_zero = p_remove(s.bits, a, 0, s.hashkey());
+                    let mut tries = 0;
                     loop {
-                        let i: u64 = crate::rand::rand64(s.cap, s.bits);
+                        let i: u64 = crate::rand::rand64(s.cap, s.bits.wrapping_add(tries));
                         if i > 64 && !a.iter().any(|&v| v == i) {
                             s.bits = i;
                             break;
                         }
+                        tries += 1;
                     }
                     if had_zero {
-                        a[p_insert(s.bits, a, 0)] = s.bits;
+                        a[p_insert(s.bits, a, 0, s.hashkey())] = s.bits;
                     }
                 }
                 let e = if e == 0 { s.bits } else { e };
-                match p_lookfor(e, a, 0) {
+                match p_lookfor(e, a, 0, s.hashkey()) {
                     LookedUp::KeyFound(_) => {
                         return false;
                     }
@@ -1078,15 +1679,15 @@ impl SetU64 {
                 }
                 // println!("looking for space in... {:?}", a);
                 if a.iter().cloned().any(|x| x == 0) {
-                    // println!("about to insert at {}", p_insert(e, a, 0));
-                    a[p_insert(e, a, 0)] = e;
+                    // println!("about to insert at {}", p_insert(e, a, 0, s.hashkey()));
+                    a[p_insert(e, a, 0, s.hashkey())] = e;
                     s.sz += 1;
                     return true;
                 }
                 // println!("no room in the set... {:?}", a);
                 let newcap: usize = s.cap + 1 + (crate::rand::rand_usize(s.cap, s.bits) % (2 * s.cap));
                 let mut new = Self::with_capacity_and_bits(newcap, s.bits);
-                // new.debug_me("initial new");
+                // println!("initial new: {:#?}", new);
                 match new.internal_mut() {
                     InternalMut::Empty => unreachable!(),
                     InternalMut::Stack(_) => unreachable!(),
@@ -1094,16 +1695,16 @@ impl SetU64 {
                     InternalMut::Heap { .. } => unreachable!(),
                     InternalMut::Big { s: ns, a: na } => {
                         for v in a.iter().cloned().filter(|&x| x != 0) {
-                            na[p_insert(v, na, 0)] = v;
+                            na[p_insert(v, na, 0, ns.hashkey())] = v;
                             // println!("  with {} gives {:?}", v, na);
                             // let v: Vec<_> = na.iter().cloned().map(|x| x % na.len() as u64).collect();
                             // println!("     >>>{:?}", v);
                         }
-                        na[p_insert(e, na, 0)] = e;
+                        na[p_insert(e, na, 0, ns.hashkey())] = e;
                         // println!("  with {} gives {:?}", e, na);
                         ns.sz = s.sz + 1;
                         // println!("  size ends up as {}", ns.sz);
-                        // new.debug_me("aftr growing");
+                        // println!("aftr growing: {:#?}", new);
                     }
                 }
                 *self = new;
@@ -1148,14 +1749,14 @@ impl SetU64 {
                     return false;
                 }
                 let (key, offset) = split_u64(e, s.bits);
-                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
+                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits, s.hashkey()) {
                     if a[idx] & (1 << offset) != 0 {
                         let newa = a[idx] & !(1 << offset);
                         s.sz -= 1;
                         if newa == key << s.bits {
                             // We've removed everything with this key,
                             // so remove the whole key!
-                            p_remove(key, a, s.bits);
+                            p_remove(key, a, s.bits, s.hashkey());
                         } else {
                             a[idx] = newa;
                         }
@@ -1172,7 +1773,7 @@ impl SetU64 {
                     return false;
                 }
                 let e = if e == 0 { s.bits } else { e };
-                let had_e = p_remove(e, a, 0);
+                let had_e = p_remove(e, a, 0, s.hashkey());
                 if had_e {
                     s.sz -= 1;
                 }
@@ -1183,38 +1784,71 @@ impl SetU64 {
 
     /// Contais
     pub fn contains(&self, e: u64) -> bool {
-        match self.internal() {
-            Internal::Empty => false,
-            Internal::Stack(t) => t.contains(e), // t.clone().any(|x| x == e),
-            Internal::Dense { a, .. } => {
-                let key = e >> 6;
-                if let Some(bits) = a.get(key as usize) {
-                    bits & (1 << (e & 63)) != 0
-                } else {
-                    false
-                }
-            }
-            Internal::Heap { s, a } => {
-                if compute_array_bits(e) < s.bits {
-                    // println!("too big a thing");
-                    return false;
-                }
-                let (key, offset) = split_u64(e, s.bits);
-                if let LookedUp::KeyFound(idx) = p_lookfor(key, a, s.bits) {
-                    a[idx] & (1 << offset) != 0
-                } else {
-                    // self.debug_me(&format!("did not find key {} from {}", key, e));
-                    false
-                }
-            }
-            Internal::Big { s, a } => {
-                if e == s.bits {
-                    return false;
-                }
-                let e = if e == 0 { s.bits } else { e };
-                p_lookfor(e, a, 0).key_found()
+        self.internal().contains(e)
+    }
+
+    /// Calls `f` with each value and whether it is in the set, stopping
+    /// early if `f` returns false.
+    pub(crate) fn probe(&self, vals: impl Iterator<Item = u64>, f: impl FnMut(u64, bool) -> bool) {
+        self.internal().probe(vals, f)
+    }
+
+    /// Returns true if the set contains every one of `vals`.
+    ///
+    /// This and the other batch queries are faster than calling
+    /// [`contains`](SetU64::contains) for each value, particularly when
+    /// `vals` is sorted.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let s: SetU64 = (0..100).map(|x| x * 3).collect();
+    /// assert!(s.contains_all(&[0, 3, 297]));
+    /// assert!(!s.contains_all(&[0, 1]));
+    /// assert!(s.contains_any(&[1, 2, 3]));
+    /// assert_eq!(s.count_contained(&[1, 2, 3, 4, 5, 6]), 2);
+    /// assert_eq!(s.filter_contained(&[1, 2, 3, 4, 5, 6]), vec![3, 6]);
+    /// ```
+    pub fn contains_all(&self, vals: &[u64]) -> bool {
+        let mut all = true;
+        self.probe(vals.iter().cloned(), |_, found| {
+            all = found;
+            found
+        });
+        all
+    }
+
+    /// Returns true if the set contains at least one of `vals`.
+    pub fn contains_any(&self, vals: &[u64]) -> bool {
+        let mut any = false;
+        self.probe(vals.iter().cloned(), |_, found| {
+            any = found;
+            !found
+        });
+        any
+    }
+
+    /// Counts how many of `vals` are in the set, including repeats.
+    pub fn count_contained(&self, vals: &[u64]) -> usize {
+        let mut count = 0;
+        self.probe(vals.iter().cloned(), |_, found| {
+            count += found as usize;
+            true
+        });
+        count
+    }
+
+    /// Returns those of `vals` that are in the set, in their original
+    /// order.
+    pub fn filter_contained(&self, vals: &[u64]) -> Vec<u64> {
+        let mut out = Vec::new();
+        self.probe(vals.iter().cloned(), |e, found| {
+            if found {
+                out.push(e);
             }
-        }
+            true
+        });
+        out
     }
 
     /// Clears the set, returning all elements in an iterator.
@@ -1242,13 +1876,7 @@ impl SetU64 {
                 )
             };
             let a = unsafe { std::slice::from_raw_parts(array, b.cap as usize) };
-            if b.bits == 0 || b.bits > 64 {
-                Internal::Big { s: b, a }
-            } else if b.bits == 64 {
-                Internal::Dense { sz: b.sz, a }
-            } else {
-                Internal::Heap { s: b, a }
-            }
+            Internal::from_parts(b, a)
         }
     }
 
@@ -1316,53 +1944,905 @@ impl std::iter::FromIterator<u64> for SetU64 {
     where
         T: IntoIterator<Item = u64>,
     {
-        let mut v: Vec<_> = iter.into_iter().collect();
-        v.sort();
-        v.dedup();
-        if let Some(mx) = v.iter().cloned().max() {
-            if let Some(t) = Tiny::new_sorted_deduped(&v) {
-                SetU64(t.to_usize() as *mut S)
-            } else {
-                if v.len() as u64 > mx >> 4 {
-                    // This should be stored in a dense bitset.
-                    let mut s = SetU64::with_capacity_and_max(v.len(), mx);
-                    for value in v.into_iter() {
-                        s.insert(value);
+        let mut s = SetU64::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl SetU64 {
+    /// Create a set from elements in ascending order.
+    ///
+    /// This avoids the sort done by `collect`, and writes the elements
+    /// directly into their final storage.  Repeated elements are allowed.
+    /// The iterator is traversed more than once, which is why it must be
+    /// `Clone`.
+    ///
+    /// Unsorted input is a bug (caught by a debug assertion), but still
+    /// gives the right set.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let a = SetU64::from_sorted_iter((0..1000).map(|x| x * 5));
+    /// assert_eq!(a, (0..1000).map(|x| x * 5).collect());
+    /// ```
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = u64>,
+        I::IntoIter: Clone,
+    {
+        let iter = iter.into_iter();
+        let mut len = 0;
+        let mut mx = 0;
+        let mut last = None;
+        // Enough room for the largest stack set.
+        let mut small = [0; 7];
+        let mut in_order = true;
+        for x in iter.clone() {
+            if last == Some(x) {
+                continue;
+            }
+            debug_assert!(
+                last.map(|l| l < x).unwrap_or(true),
+                "from_sorted_iter: elements are not sorted"
+            );
+            in_order &= last.map(|l| l < x).unwrap_or(true);
+            if let Some(y) = small.get_mut(len) {
+                *y = x;
+            }
+            len += 1;
+            mx = mx.max(x);
+            last = Some(x);
+        }
+        if len == 0 {
+            return SetU64::new();
+        }
+        if in_order && len <= small.len() {
+            if let Some(t) = Tiny::new_sorted_deduped(&small[..len]) {
+                return SetU64(t.to_usize() as *mut S);
+            }
+        }
+        if len as u64 > mx >> 4 {
+            let mut set = SetU64::with_capacity_and_max(len, mx);
+            if let InternalMut::Dense { sz, a } = set.internal_mut() {
+                for x in iter {
+                    a[(x >> 6) as usize] |= 1 << (x & 63);
+                }
+                *sz = crate::kernels::popcount(a);
+            }
+            return set;
+        }
+        let bits = compute_array_bits(mx);
+        if bits == 0 {
+            let mut set = SetU64::with_capacity_and_bits(len, bits);
+            for x in iter {
+                set.insert(x);
+            }
+            return set;
+        }
+        let mut keys = 0;
+        let mut last = None;
+        for x in iter.clone() {
+            let key = x / bits;
+            if last != Some(key) {
+                keys += 1;
+                last = Some(key);
+            }
+        }
+        let mut set = SetU64::with_capacity_and_bits((keys + 1) * 11 / 10, bits);
+        if let InternalMut::Heap { s, a } = set.internal_mut() {
+            let mut flush = |key: u64, bitmap: u64| {
+                let idx = p_insert(key, a, bits, s.hashkey());
+                a[idx] |= key << bits | bitmap;
+            };
+            let mut current = None;
+            for x in iter {
+                let (key, offset) = split_u64(x, bits);
+                match current {
+                    Some((k, bitmap)) if k == key => current = Some((k, bitmap | 1 << offset)),
+                    _ => {
+                        if let Some((k, bitmap)) = current {
+                            flush(k, bitmap);
+                        }
+                        current = Some((key, 1 << offset));
                     }
-                    return s;
                 }
-                let bits = compute_array_bits(mx);
-                if bits == 0 {
-                    let mut s = SetU64::with_capacity_and_bits(v.len(), bits);
-                    for value in v.into_iter() {
-                        s.insert(value);
+            }
+            if let Some((k, bitmap)) = current {
+                flush(k, bitmap);
+            }
+            s.sz = a
+                .iter()
+                .map(|&w| (w & mask(bits as usize)).count_ones() as usize)
+                .sum();
+        }
+        set
+    }
+
+    /// Create a set from a slice in ascending order.
+    ///
+    /// See [`from_sorted_iter`](SetU64::from_sorted_iter).
+    pub fn from_sorted_slice(v: &[u64]) -> Self {
+        SetU64::from_sorted_iter(v.iter().cloned())
+    }
+
+    /// Insert elements given in ascending order.
+    ///
+    /// An empty set is built as by
+    /// [`from_sorted_iter`](SetU64::from_sorted_iter), and elements that
+    /// fall within an existing dense bitmap are written directly into it.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let mut a: SetU64 = (0..100).collect();
+    /// a.extend_sorted(50..200);
+    /// assert_eq!(a, (0..200).collect());
+    /// ```
+    pub fn extend_sorted<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = u64>,
+        I::IntoIter: Clone,
+    {
+        if self.0.is_null() {
+            *self = SetU64::from_sorted_iter(iter);
+            return;
+        }
+        let mut iter = iter.into_iter();
+        let mut rest = None;
+        if let InternalMut::Dense { sz, a } = self.internal_mut() {
+            for x in iter.by_ref() {
+                if let Some(w) = a.get_mut((x >> 6) as usize) {
+                    let bit = 1 << (x & 63);
+                    if *w & bit == 0 {
+                        *w |= bit;
+                        *sz += 1;
                     }
-                    s
                 } else {
-                    let mut keys: Vec<_> = v.iter().map(|&x| x / bits).collect();
-                    keys.sort();
-                    keys.dedup();
-                    let sz = (keys.len() + 1) * 11 / 10;
-                    let mut s = SetU64::with_capacity_and_bits(sz, bits);
-                    for value in v.into_iter() {
-                        s.insert(value);
+                    rest = Some(x);
+                    break;
+                }
+            }
+        }
+        for x in rest.into_iter().chain(iter) {
+            self.insert(x);
+        }
+    }
+}
+
+#[cfg(test)]
+fn check_from_sorted(mut v: Vec<u64>) {
+    v.sort();
+    let expected: SetU64 = v.iter().cloned().collect();
+    let set = SetU64::from_sorted_slice(&v);
+    assert_eq!(set.len(), expected.len());
+    assert_eq!(set, expected);
+    assert_eq!(set.iter().count(), set.len());
+    for &x in v.iter() {
+        assert!(set.contains(x));
+    }
+    let (a, b) = v.split_at(v.len() / 2);
+    let mut set = SetU64::from_sorted_slice(b);
+    set.extend_sorted(a.iter().cloned());
+    assert_eq!(set, expected);
+    let mut set = SetU64::from_sorted_slice(a);
+    set.extend_sorted(b.iter().cloned());
+    assert_eq!(set, expected);
+}
+
+#[test]
+fn from_sorted() {
+    check_from_sorted(vec![]);
+    check_from_sorted(vec![0]);
+    check_from_sorted(vec![1, 1, 2, 3, 3]);
+    check_from_sorted((0..10000).collect());
+    check_from_sorted((0..10000).map(|x| x * 100).collect());
+    check_from_sorted((0..1000).map(|x| x << 50).collect());
+    check_from_sorted(vec![0, 1 << 63, u64::MAX]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn from_sorted_checks_order() {
+    SetU64::from_sorted_slice(&[3, 2, 1]);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn from_sorted_matches_collect(v: Vec<u64>) {
+        check_from_sorted(v);
+    }
+    #[test]
+    fn from_sorted_matches_collect_small(v in proptest::collection::vec(0u64..5000, 0usize..500)) {
+        check_from_sorted(v);
+    }
+}
+
+#[cfg(test)]
+fn check_batch(elems: &[u64], mut vals: Vec<u64>) {
+    let set: SetU64 = elems.iter().cloned().collect();
+    for _ in 0..2 {
+        let found: Vec<u64> = vals.iter().cloned().filter(|&x| set.contains(x)).collect();
+        assert_eq!(set.filter_contained(&vals), found);
+        assert_eq!(set.count_contained(&vals), found.len());
+        assert_eq!(set.contains_all(&vals), found.len() == vals.len());
+        assert_eq!(set.contains_any(&vals), !found.is_empty());
+        vals.sort_unstable();
+    }
+}
+
+#[test]
+fn batch_queries() {
+    check_batch(&[], vec![]);
+    check_batch(&[], vec![1, 2]);
+    check_batch(&[1, 2, 3], vec![]);
+    check_batch(&[1, 2, 3], vec![3, 2, 1, 1]);
+    let dense: Vec<u64> = (0..1000).filter(|x| x % 3 != 0).collect();
+    check_batch(&dense, (0..2000).rev().collect());
+    let sparse: Vec<u64> = (0..1000).map(|x| x * 37).collect();
+    check_batch(&sparse, (0..40000).step_by(7).collect());
+    let big: Vec<u64> = (0..100).map(|x| (1 << 40) * x + 1).collect();
+    check_batch(
+        &big,
+        big.iter()
+            .map(|&x| x ^ 1)
+            .chain(big.iter().cloned())
+            .collect(),
+    );
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn batch_matches_contains(elems: Vec<u64>, vals: Vec<u64>) {
+        let vals = vals.into_iter().chain(elems.iter().cloned().step_by(2)).collect();
+        check_batch(&elems, vals);
+    }
+    #[test]
+    fn batch_matches_contains_small(elems in proptest::collection::vec(0u64..1000, 0usize..200),
+                                    vals in proptest::collection::vec(0u64..1000, 0usize..200)) {
+        check_batch(&elems, vals);
+    }
+}
+
+#[cfg(test)]
+fn check_ops(a: &[u64], b: &[u64]) {
+    use std::collections::HashSet;
+    let (sa, sb): (SetU64, SetU64) = (a.iter().cloned().collect(), b.iter().cloned().collect());
+    let (ha, hb): (HashSet<u64>, HashSet<u64>) =
+        (a.iter().cloned().collect(), b.iter().cloned().collect());
+    let check = |s: SetU64, h: HashSet<u64>| {
+        assert_eq!(s.len(), h.len());
+        assert_eq!(s.iter().count(), h.len());
+        assert!(s.iter().all(|x| h.contains(&x)));
+        assert_eq!(s, h.iter().cloned().collect());
+    };
+    check(&sa | &sb, &ha | &hb);
+    check(&sa & &sb, &ha & &hb);
+    check(&sa - &sb, &ha - &hb);
+    check(sa.clone() | &sb, &ha | &hb);
+    check(sa.clone() - &sb, &ha - &hb);
+    assert_eq!(sa == sb, ha == hb);
+}
+
+#[test]
+fn set_operations() {
+    check_ops(&[], &[]);
+    check_ops(&[1, 2, 3], &[3, 4]);
+    let dense: Vec<u64> = (0..1000).collect();
+    let evens: Vec<u64> = (0..3000).map(|x| x * 2).collect();
+    assert!(dense
+        .iter()
+        .cloned()
+        .collect::<SetU64>()
+        .dense_words()
+        .is_some());
+    assert!(evens
+        .iter()
+        .cloned()
+        .collect::<SetU64>()
+        .dense_words()
+        .is_some());
+    check_ops(&dense, &evens);
+    check_ops(&evens, &dense);
+    check_ops(&dense, &dense);
+    check_ops(&dense, &dense[..999]);
+    check_ops(&dense, &[1 << 40, 5]);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn set_operations_match_hashset(a in proptest::collection::vec(0u64..3000, 0usize..800),
+                                    b in proptest::collection::vec(0u64..3000, 0usize..800)) {
+        check_ops(&a, &b);
+    }
+    #[test]
+    fn set_operations_on_any_sets(a: Vec<u64>, b: Vec<u64>) {
+        check_ops(&a, &b);
+    }
+}
+
+#[cfg(test)]
+fn hash_of<T: std::hash::Hash>(x: &T) -> u64 {
+    use std::hash::Hasher;
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    x.hash(&mut h);
+    h.finish()
+}
+
+#[test]
+fn hash_ignores_layout() {
+    let small: SetU64 = (0..100).collect();
+    let mut shrunk: SetU64 = (0..100000).collect();
+    for x in 100..100000 {
+        shrunk.remove(x);
+    }
+    let mut dense = SetU64::with_capacity_and_max(100, 10000);
+    dense.extend((0..100).rev());
+    let mut heap = SetU64::with_capacity_and_bits(200, 0);
+    heap.extend(0..100);
+    assert!(dense.dense_words().is_some() && heap.dense_words().is_none());
+    for s in [&shrunk, &dense, &heap] {
+        assert_eq!(&small, s);
+        assert_eq!(hash_of(&small), hash_of(s));
+    }
+    assert_ne!(hash_of(&small), hash_of(&(1..101).collect::<SetU64>()));
+    assert_ne!(
+        hash_of(&SetU64::new()),
+        hash_of(&[0].iter().cloned().collect::<SetU64>())
+    );
+
+    let mut m = std::collections::HashMap::new();
+    m.insert(small, "small");
+    assert_eq!(m.get(&heap), Some(&"small"));
+}
+
+#[cfg(test)]
+fn check_cmp(a: &[u64], b: &[u64]) {
+    use std::collections::BTreeSet;
+    let (sa, sb): (SetU64, SetU64) = (a.iter().cloned().collect(), b.iter().cloned().collect());
+    let (ba, bb): (BTreeSet<u64>, BTreeSet<u64>) =
+        (a.iter().cloned().collect(), b.iter().cloned().collect());
+    assert_eq!(sa.cmp(&sb), ba.cmp(&bb));
+    assert_eq!(sa.partial_cmp(&sb), ba.partial_cmp(&bb));
+}
+
+#[test]
+fn ordering() {
+    check_cmp(&[], &[]);
+    check_cmp(&[], &[0]);
+    check_cmp(&[1, 2], &[1, 2, 3]);
+    check_cmp(&[1, 3], &[1, 2, 3]);
+    let big: Vec<u64> = (0..1000).map(|x| x * 1000).collect();
+    check_cmp(&big, &big[1..]);
+    check_cmp(&big, &big[..999]);
+    let mut sets: Vec<SetU64> = vec![
+        (0..5).collect(),
+        (1..2).collect(),
+        SetU64::new(),
+        (0..3).collect(),
+    ];
+    sets.sort();
+    assert_eq!(sets[0], SetU64::new());
+    assert_eq!(sets[3], (1..2).collect());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn ordering_matches_btreeset(a: Vec<u64>, b: Vec<u64>) {
+        check_cmp(&a, &b);
+    }
+    #[test]
+    fn ordering_matches_btreeset_small(a in proptest::collection::vec(0u64..50, 0usize..20),
+                                       b in proptest::collection::vec(0u64..50, 0usize..20)) {
+        check_cmp(&a, &b);
+    }
+}
+
+/// The index and mask of each word of a bitmap that holds `lo..=hi`.
+fn range_words(lo: u64, hi: u64) -> impl Iterator<Item = (usize, u64)> {
+    let (first, last) = ((lo >> 6) as usize, (hi >> 6) as usize);
+    (first..=last).map(move |i| {
+        let mut m = !0;
+        if i == first {
+            m &= !0 << (lo & 63);
+        }
+        if i == last {
+            m &= !0 >> (63 - (hi & 63));
+        }
+        (i, m)
+    })
+}
+
+/// Sets or clears bits `lo..=hi` of the bitmap `a`, returning how many
+/// bits changed.
+fn fill_bits(a: &mut [u64], lo: u64, hi: u64, set: bool) -> usize {
+    let mut changed = 0;
+    for (i, m) in range_words(lo, hi) {
+        if set {
+            changed += (m & !a[i]).count_ones() as usize;
+            a[i] |= m;
+        } else {
+            changed += (m & a[i]).count_ones() as usize;
+            a[i] &= !m;
+        }
+    }
+    changed
+}
+
+/// The bitmap of a heap bucket `key` that covers `lo..=hi`, which must
+/// overlap the bucket.
+fn bucket_mask(key: u64, bits: u64, lo: u64, hi: u64) -> u64 {
+    let start = key * bits;
+    let from = lo.saturating_sub(start);
+    let to = (hi - start).min(bits - 1);
+    (!0 >> (63 - to)) & (!0 << from)
+}
+
+impl SetU64 {
+    /// Inserts every value in `range`, returning how many were not
+    /// already present.
+    ///
+    /// The layout is chosen up front from the size of the range, and
+    /// dense or heap storage is filled a word at a time rather than
+    /// element by element.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let mut s = SetU64::new();
+    /// assert_eq!(s.insert_range(10..1000), 990);
+    /// assert_eq!(s.insert_range(500..=1000), 1);
+    /// assert_eq!(s, (10..1001).collect());
+    /// ```
+    pub fn insert_range<R: std::ops::RangeBounds<u64>>(&mut self, range: R) -> usize {
+        match crate::copyset::inclusive_bounds(&range, u64::MAX, |&x| x) {
+            Some((lo, hi)) => self.insert_inclusive(lo, hi),
+            None => 0,
+        }
+    }
+
+    /// Removes every value in `range`, returning how many were present.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let mut s: SetU64 = (0..1000).collect();
+    /// assert_eq!(s.remove_range(100..), 900);
+    /// assert_eq!(s, (0..100).collect());
+    /// ```
+    pub fn remove_range<R: std::ops::RangeBounds<u64>>(&mut self, range: R) -> usize {
+        match crate::copyset::inclusive_bounds(&range, u64::MAX, |&x| x) {
+            Some((lo, hi)) => self.remove_inclusive(lo, hi),
+            None => 0,
+        }
+    }
+
+    /// Removes every value in `range`, returning them in an iterator.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let mut s: SetU64 = (0..10).collect();
+    /// let mut gone: Vec<u64> = s.drain_range(3..6).collect();
+    /// gone.sort();
+    /// assert_eq!(gone, vec![3, 4, 5]);
+    /// assert_eq!(s.len(), 7);
+    /// ```
+    pub fn drain_range<R: std::ops::RangeBounds<u64>>(&mut self, range: R) -> IntoIter {
+        let (lo, hi) = match crate::copyset::inclusive_bounds(&range, u64::MAX, |&x| x) {
+            Some(b) => b,
+            None => return SetU64::new().into_iter(),
+        };
+        let drained = match self.dense_words() {
+            Some(a) if lo >> 6 < a.len() as u64 => {
+                let hi = hi.min(a.len() as u64 * 64 - 1);
+                SetU64::from_sorted_iter(
+                    (lo..=hi).filter(|&x| a[(x >> 6) as usize] & 1 << (x & 63) != 0),
+                )
+            }
+            Some(_) => SetU64::new(),
+            None => self.iter().filter(|&x| lo <= x && x <= hi).collect(),
+        };
+        self.remove_inclusive(lo, hi);
+        drained.into_iter()
+    }
+
+    fn insert_inclusive(&mut self, lo: u64, hi: u64) -> usize {
+        let before = self.len();
+        let span = hi - lo;
+        if span < 64 {
+            for x in lo..=hi {
+                self.insert(x);
+            }
+            return self.len() - before;
+        }
+        if let InternalMut::Dense { sz, a } = self.internal_mut() {
+            if hi >> 6 < a.len() as u64 {
+                let added = fill_bits(a, lo, hi, true);
+                *sz += added;
+                return added;
+            }
+        }
+        let mx = self.iter().fold(hi, u64::max);
+        let total = (before as u64).saturating_add(span).saturating_add(1);
+        if total > mx >> 4 {
+            let mut set = SetU64::dense_with_max(mx);
+            if let InternalMut::Dense { sz, a } = set.internal_mut() {
+                match self.dense_words() {
+                    Some(old) => a[..old.len()].copy_from_slice(old),
+                    None => {
+                        for x in self.iter() {
+                            a[(x >> 6) as usize] |= 1 << (x & 63);
+                        }
+                    }
+                }
+                fill_bits(a, lo, hi, true);
+                *sz = crate::kernels::popcount(a);
+            }
+            *self = set;
+            return self.len() - before;
+        }
+        let bits = compute_array_bits(mx);
+        if bits == 0 {
+            for x in lo..=hi {
+                self.insert(x);
+            }
+            return self.len() - before;
+        }
+        // Rebuild as a heap, with room for the existing buckets plus
+        // those of the range.
+        let (klo, khi) = (lo / bits, hi / bits);
+        let mut keys = khi - klo + 1;
+        let mut last = None;
+        for x in self.iter_sorted() {
+            let key = x / bits;
+            if last != Some(key) && (key < klo || key > khi) {
+                keys += 1;
+            }
+            last = Some(key);
+        }
+        let mut set = SetU64::with_capacity_and_bits((keys as usize + 1) * 11 / 10, bits);
+        if let InternalMut::Heap { s, a } = set.internal_mut() {
+            for x in self.iter() {
+                let (key, offset) = split_u64(x, bits);
+                let idx = p_insert(key, a, bits, s.hashkey());
+                a[idx] |= key << bits | 1 << offset;
+            }
+            for key in klo..=khi {
+                let idx = p_insert(key, a, bits, s.hashkey());
+                a[idx] |= key << bits | bucket_mask(key, bits, lo, hi);
+            }
+            s.sz = a
+                .iter()
+                .map(|&w| (w & mask(bits as usize)).count_ones() as usize)
+                .sum();
+        }
+        *self = set;
+        self.len() - before
+    }
+
+    fn remove_inclusive(&mut self, lo: u64, hi: u64) -> usize {
+        match self.internal_mut() {
+            InternalMut::Empty => 0,
+            InternalMut::Dense { sz, a } => {
+                if lo >> 6 >= a.len() as u64 {
+                    return 0;
+                }
+                let removed = fill_bits(a, lo, hi.min(a.len() as u64 * 64 - 1), false);
+                *sz -= removed;
+                removed
+            }
+            InternalMut::Heap { s, a } => {
+                let bits = s.bits;
+                let (klo, khi) = (lo / bits, hi / bits);
+                let mut emptied = Vec::new();
+                let mut removed = 0;
+                let mut clear = |idx: usize, a: &mut [u64]| {
+                    let key = a[idx] >> bits;
+                    let m = bucket_mask(key, bits, lo, hi);
+                    removed += (a[idx] & m).count_ones() as usize;
+                    if a[idx] & mask(bits as usize) & !m == 0 {
+                        emptied.push(key);
+                    } else {
+                        a[idx] &= !m;
+                    }
+                };
+                if khi - klo < a.len() as u64 {
+                    for key in klo..=khi {
+                        if let LookedUp::KeyFound(idx) = p_lookfor(key, a, bits, s.hashkey()) {
+                            clear(idx, a);
+                        }
                     }
-                    s
+                } else {
+                    for idx in 0..a.len() {
+                        let key = a[idx] >> bits;
+                        if a[idx] != 0 && klo <= key && key <= khi {
+                            clear(idx, a);
+                        }
+                    }
+                }
+                for key in emptied {
+                    p_remove(key, a, bits, s.hashkey());
                 }
+                s.sz -= removed;
+                removed
             }
+            InternalMut::Stack(_) | InternalMut::Big { .. } => {
+                let doomed: Vec<u64> = self.iter().filter(|&x| lo <= x && x <= hi).collect();
+                for &x in doomed.iter() {
+                    self.remove(x);
+                }
+                doomed.len()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn check_ranges(elems: &[u64], lo: u64, hi: u64) {
+    use std::collections::BTreeSet;
+    let expected: BTreeSet<u64> = elems.iter().cloned().collect();
+    let inserted: BTreeSet<u64> = expected.iter().cloned().chain(lo..hi).collect();
+    let mut set: SetU64 = elems.iter().cloned().collect();
+    assert_eq!(set.insert_range(lo..hi), inserted.len() - expected.len());
+    assert_eq!(set.len(), inserted.len());
+    assert_eq!(set.iter_sorted().collect::<BTreeSet<_>>(), inserted);
+    assert!(set.contains_all(&(lo..hi).collect::<Vec<_>>()));
+
+    let kept: BTreeSet<u64> = expected
+        .iter()
+        .cloned()
+        .filter(|x| !(lo..hi).contains(x))
+        .collect();
+    let gone: BTreeSet<u64> = expected
+        .iter()
+        .cloned()
+        .filter(|x| (lo..hi).contains(x))
+        .collect();
+    let mut set: SetU64 = elems.iter().cloned().collect();
+    assert_eq!(set.remove_range(lo..hi), gone.len());
+    assert_eq!(set.len(), kept.len());
+    assert_eq!(set.iter().collect::<BTreeSet<_>>(), kept);
+    for x in gone.iter() {
+        assert!(!set.contains(*x));
+    }
+
+    let mut set: SetU64 = elems.iter().cloned().collect();
+    assert_eq!(set.drain_range(lo..hi).collect::<BTreeSet<_>>(), gone);
+    assert_eq!(set.iter().collect::<BTreeSet<_>>(), kept);
+}
+
+#[test]
+fn ranges() {
+    check_ranges(&[], 0, 0);
+    check_ranges(&[], 5, 3);
+    check_ranges(&[1, 2, 3], 0, 10);
+    check_ranges(&[1, 2, 3], 2, 1000);
+    check_ranges(&(0..1000).collect::<Vec<_>>(), 10, 20);
+    check_ranges(&(0..1000).collect::<Vec<_>>(), 63, 129);
+    check_ranges(&(0..1000).collect::<Vec<_>>(), 500, 5000);
+    check_ranges(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 5000, 6000);
+    check_ranges(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 0, 1 << 20);
+    check_ranges(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 5, 100000);
+    check_ranges(
+        &(0..100).map(|x| x << 58).collect::<Vec<_>>(),
+        7 << 58,
+        (7 << 58) + 100,
+    );
+    check_ranges(&[0, 1 << 63], (1 << 40) - 50, (1 << 40) + 5000);
+
+    let mut s = SetU64::new();
+    assert_eq!(s.insert_range(u64::MAX - 100..), 101);
+    assert!(s.contains(u64::MAX));
+    assert_eq!(s.remove_range(..u64::MAX - 50), 50);
+    assert_eq!(s.len(), 51);
+    assert_eq!(s.drain_range(..).count(), 51);
+    assert_eq!(s.len(), 0);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn ranges_match_btreeset(elems: Vec<u64>, lo: u64, len in 0u64..3000) {
+        check_ranges(&elems, lo, lo.saturating_add(len));
+    }
+    #[test]
+    fn ranges_match_btreeset_small(elems in proptest::collection::vec(0u64..5000, 0usize..500),
+                                   lo in 0u64..5000, len in 0u64..3000) {
+        check_ranges(&elems, lo, lo + len);
+    }
+}
+
+impl SetU64 {
+    /// The values in `universe` that are not in the set.
+    ///
+    /// A complement that is dense within `0..universe.end` is built by
+    /// inverting the bitmap a word at a time, and a sparse one from the
+    /// gaps between the elements of the set.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let live: SetU64 = [1, 3, 4].iter().cloned().collect();
+    /// assert_eq!(live.complement(0..6), [0, 2, 5].iter().cloned().collect());
+    /// let all: SetU64 = (0..1000).filter(|&x| x != 500).collect();
+    /// assert_eq!(all.complement(0..1000), Some(500).into_iter().collect());
+    /// ```
+    pub fn complement(&self, universe: std::ops::Range<u64>) -> SetU64 {
+        let (lo, hi) = (universe.start, universe.end);
+        if lo >= hi {
+            return SetU64::new();
+        }
+        let inside = match self.internal() {
+            Internal::Dense { a, .. } if lo >> 6 < a.len() as u64 => {
+                let top = (hi - 1).min(a.len() as u64 * 64 - 1);
+                range_words(lo, top)
+                    .map(|(i, m)| (a[i] & m).count_ones() as usize)
+                    .sum()
+            }
+            Internal::Dense { .. } => 0,
+            _ => self.iter().filter(|&x| lo <= x && x < hi).count(),
+        };
+        let n = hi - lo - inside as u64;
+        if n == 0 {
+            SetU64::new()
+        } else if n > (hi - 1) >> 4 {
+            let mut set = SetU64::dense_with_max(hi - 1);
+            if let InternalMut::Dense { sz, a } = set.internal_mut() {
+                fill_bits(a, lo, hi - 1, true);
+                match self.dense_words() {
+                    Some(old) => {
+                        let n = a.len().min(old.len());
+                        crate::kernels::andnot_into(&mut a[..n], &old[..n]);
+                    }
+                    None => {
+                        for x in self.iter().filter(|&x| lo <= x && x < hi) {
+                            a[(x >> 6) as usize] &= !(1 << (x & 63));
+                        }
+                    }
+                }
+                *sz = crate::kernels::popcount(a);
+            }
+            set
         } else {
-            SetU64(0 as *mut S)
+            let mut gaps = Vec::new();
+            let mut next = lo;
+            for x in self.iter_sorted().filter(|&x| lo <= x && x < hi) {
+                if x > next {
+                    gaps.push(next..x);
+                }
+                next = x + 1;
+            }
+            if next < hi {
+                gaps.push(next..hi);
+            }
+            SetU64::from_sorted_iter(gaps.iter().flat_map(|r| r.clone()))
         }
     }
+
+    /// Replaces the set with the values in `0..n` that are not in it.
+    ///
+    /// A dense set whose complement is also dense is inverted without
+    /// reallocating.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let mut s: SetU64 = (0..100).filter(|x| x % 3 == 0).collect();
+    /// s.complement_in_place(100);
+    /// assert_eq!(s, (0..100).filter(|x| x % 3 != 0).collect());
+    /// ```
+    pub fn complement_in_place(&mut self, n: u64) {
+        if let InternalMut::Dense { sz, a } = self.internal_mut() {
+            let len = a.len() as u64 * 64;
+            let outside = if n < len {
+                fill_bits(a, n, len - 1, false)
+            } else {
+                0
+            };
+            let missing = n.saturating_sub((*sz - outside) as u64);
+            if n > 0 && n <= len && missing > (n - 1) >> 4 {
+                let (head, last) = a.split_at_mut(((n - 1) >> 6) as usize);
+                for w in head.iter_mut().chain(last.iter_mut().take(1)) {
+                    *w = !*w;
+                }
+                fill_bits(a, n, len - 1, false);
+                *sz = missing as usize;
+                return;
+            }
+            *sz -= outside;
+        }
+        *self = self.complement(0..n);
+    }
+}
+
+#[cfg(test)]
+fn check_complement(elems: &[u64], lo: u64, hi: u64) {
+    use std::collections::BTreeSet;
+    let set: SetU64 = elems.iter().cloned().collect();
+    let have: BTreeSet<u64> = elems.iter().cloned().collect();
+    let expected: BTreeSet<u64> = (lo..hi).filter(|x| !have.contains(x)).collect();
+    let c = set.complement(lo..hi);
+    assert_eq!(c.len(), expected.len());
+    assert_eq!(c.iter_sorted().collect::<BTreeSet<_>>(), expected);
+
+    let expected: BTreeSet<u64> = (0..hi).filter(|x| !have.contains(x)).collect();
+    let mut set = set;
+    set.complement_in_place(hi);
+    assert_eq!(set.len(), expected.len());
+    assert_eq!(set.iter_sorted().collect::<BTreeSet<_>>(), expected);
+}
+
+#[test]
+fn complement() {
+    check_complement(&[], 0, 0);
+    check_complement(&[], 10, 5);
+    check_complement(&[], 0, 1000);
+    check_complement(&[1, 3, 4], 0, 6);
+    check_complement(&[1, 3, 4, 1 << 40], 2, 6);
+    check_complement(&(0..1000).collect::<Vec<_>>(), 0, 1000);
+    check_complement(&(0..1000).collect::<Vec<_>>(), 10, 2000);
+    check_complement(
+        &(0..1000).filter(|&x| x != 500).collect::<Vec<_>>(),
+        0,
+        1000,
+    );
+    check_complement(
+        &(0..1000).filter(|&x| x != 500).collect::<Vec<_>>(),
+        100,
+        700,
+    );
+    check_complement(
+        &(0..1000).filter(|x| x % 3 != 0).collect::<Vec<_>>(),
+        0,
+        999,
+    );
+    check_complement(&(0..1000).map(|x| x * 1000).collect::<Vec<_>>(), 0, 10000);
+    check_complement(
+        &(0..5000).filter(|x| x % 100 != 7).collect::<Vec<_>>(),
+        0,
+        4000,
+    );
+    check_complement(
+        &(0..5000).filter(|x| x % 100 != 7).collect::<Vec<_>>(),
+        0,
+        60000,
+    );
+
+    let dense: SetU64 = (0..10000).filter(|x| x % 2 == 0).collect();
+    let mut odd = dense.clone();
+    odd.complement_in_place(10000);
+    assert_eq!(odd.capacity(), dense.capacity());
+    assert_eq!(odd, (0..10000).filter(|x| x % 2 == 1).collect());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn complement_matches_btreeset(elems in proptest::collection::vec(0u64..5000, 0usize..3000),
+                                   lo in 0u64..5000, len in 0u64..5000) {
+        check_complement(&elems, lo, lo + len);
+    }
 }
 
 #[cfg(test)]
 fn test_a_collect(v: Vec<u64>) {
+    // Each hash table has its own key unless they are seeded.
+    #[cfg(feature = "keyed_hashing")]
+    let old = crate::set_seed(Some(0));
     let s: SetU64 = v.iter().cloned().collect();
     let vv: Vec<_> = s.iter().collect();
     let ss: SetU64 = vv.iter().cloned().collect();
     let vvv: Vec<_> = ss.iter().collect();
     assert_eq!(vv, vvv);
+    #[cfg(feature = "keyed_hashing")]
+    crate::set_seed(old);
 }
 
 #[test]
@@ -1380,6 +2860,70 @@ fn test_collect() {
     test_a_collect((0..1024).collect());
 }
 
+#[test]
+fn collect_in_chunks() {
+    // Enough elements, in a scrambled order, to span several chunks.
+    for &stride in &[1u64, 7, 1 << 20, 0x9E37_79B9_7F4A_7C15] {
+        let v: Vec<u64> = (0..3 * CHUNK as u64 + 5)
+            .map(|x| x.wrapping_mul(0x2545_F491_4F6C_DD1D) % 10000)
+            .map(|x| x.wrapping_mul(stride))
+            .collect();
+        let expected: std::collections::HashSet<u64> = v.iter().cloned().collect();
+        let s: SetU64 = v.iter().cloned().collect();
+        assert_eq!(s.len(), expected.len());
+        for &x in expected.iter() {
+            assert!(s.contains(x));
+        }
+        let mut s2 = SetU64::new();
+        s2.extend(v.iter().cloned().filter(|_| true));
+        assert_eq!(s, s2);
+    }
+}
+
+#[test]
+fn collect_range_is_presized() {
+    let n = 100 * CHUNK as u64;
+    let s: SetU64 = (0..n).collect();
+    assert_eq!(s.len(), n as usize);
+    assert_eq!(s, SetU64::from_sorted_iter(0..n));
+    assert!(s.mem_used() <= SetU64::from_sorted_iter(0..n).mem_used());
+    // A range that stops being dense is still right.
+    let s: SetU64 = (0..CHUNK as u64)
+        .chain((1..CHUNK as u64).map(|x| x << 40))
+        .collect();
+    assert_eq!(s.len(), 2 * CHUNK - 1);
+    assert!(s.contains(1 << 40) && s.contains(CHUNK as u64 - 1));
+    // Large but sparse input gets no bitmap.
+    let s: SetU64 = (0..2 * CHUNK as u64).map(|x| (x + 1) << 30).collect();
+    assert!(s.mem_used() < 64 * CHUNK);
+}
+
+#[test]
+fn collect_is_as_compact_as_inserting() {
+    // Scrambled input for each layout, whether or not its length is known.
+    for &m in &[20_000, 1 << 24, 1 << 40, u64::MAX] {
+        let v: Vec<u64> = (0..6 * CHUNK as u64)
+            .map(|x| x.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(29) % m)
+            .collect();
+        let mut inserted = SetU64::new();
+        for &x in v.iter() {
+            inserted.insert(x);
+        }
+        let hinted: SetU64 = v.iter().cloned().collect();
+        let unhinted: SetU64 = v.iter().cloned().filter(|_| true).collect();
+        let mut sorted = v.clone();
+        sorted.sort_unstable();
+        let ideal = SetU64::from_sorted_iter(sorted);
+        assert_eq!(hinted, inserted);
+        assert_eq!(unhinted, inserted);
+        assert!(hinted.mem_used() <= ideal.mem_used() * 9 / 8);
+        assert!(hinted.capacity() <= inserted.capacity() * 5 / 4);
+        assert!(hinted.mem_used() <= inserted.mem_used() * 5 / 4);
+        assert!(unhinted.capacity() <= inserted.capacity() * 2);
+        assert!(unhinted.mem_used() <= inserted.mem_used() * 2);
+    }
+}
+
 fn bytes_for_capacity(sz: usize) -> usize {
     sz * 8 + std::mem::size_of::<S>() - 8
 }
@@ -1406,19 +2950,6 @@ impl Drop for SetU64 {
     }
 }
 
-#[cfg(test)]
-impl heapsize::HeapSizeOf for SetU64 {
-    fn heap_size_of_children(&self) -> usize {
-        match self.internal() {
-            Internal::Empty => 0,
-            Internal::Stack(_) => 0,
-            Internal::Heap { a, .. } => std::mem::size_of::<S>() - 8 + a.len() * 8,
-            Internal::Big { a, .. } => std::mem::size_of::<S>() - 8 + a.len() * 8,
-            Internal::Dense { a, .. } => std::mem::size_of::<S>() - 8 + a.len() * 8,
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1427,19 +2958,19 @@ mod tests {
     fn check_set(elems: &[u64]) {
         println!("\n\ncheck_set {:?}\n", elems);
         let mut s = SetU64::default();
-        s.debug_me("default set");
+        println!("default set: {:#?}", s);
         let mut count = 0;
         for x in elems.iter().cloned() {
             let was_here = s.contains(x);
-            s.debug_me(&format!("\n\n\nabout to insert {}", x));
+            println!("\n\n\nabout to insert {}: {:#?}", x, s);
             let changed_something = s.insert(x);
-            s.debug_me(&format!("\n\nafter inserting {}", x));
+            println!("\n\nafter inserting {}: {:#?}", x, s);
             if changed_something {
                 count += 1;
                 println!("    {} is new now count {}", x, count);
             }
             assert_eq!(!was_here, changed_something);
-            s.debug_me(&format!("after inserting {} length is {}", x, s.len()));
+            println!("after inserting {} length is {}: {:#?}", x, s.len(), s);
             println!("what is this? count {} does it have {}?", count, x);
             assert!(s.contains(x));
             assert_eq!(s.len(), count);
@@ -1451,7 +2982,10 @@ mod tests {
         for x in s.iter() {
             println!("    {}", x);
         }
-        s.debug_me("finally");
+        println!("finally: {:#?}", s);
+        let mut sorted: Vec<_> = s.iter().collect();
+        sorted.sort();
+        assert_eq!(s.iter_sorted().collect::<Vec<_>>(), sorted);
         assert_eq!(s.iter().count(), s.len());
         for x in s.iter() {
             println!("looking for {}", x);
@@ -1468,13 +3002,13 @@ mod tests {
         for x in elems.iter().cloned() {
             println!("removing {}", x);
             s.remove(x);
-            s.debug_me("  after remove");
+            println!("  after remove: {:#?}", s);
         }
         for x in elems.iter().cloned() {
             println!("XXXX looking for {}", x);
             assert!(!s.contains(x));
         }
-        s.debug_me("after everything was removed");
+        println!("after everything was removed: {:#?}", s);
         assert_eq!(s.len(), 0);
         check_size(elems);
     }
@@ -1579,6 +3113,20 @@ mod tests {
         check_tiny_from_vec(vec![1, 130, 131, 132, 133, 251]);
     }
 
+    #[test]
+    fn partly_consumed_tiny() {
+        let mut t = Tiny::from_singleton(2).unwrap();
+        for x in [2, 3, 143, 251, 1].iter().cloned() {
+            t = t.insert(x).unwrap();
+        }
+        let n = t.sz as usize;
+        for left in (0..n).rev() {
+            t.next();
+            assert_eq!(t.size_hint(), (left, Some(left)));
+            assert_eq!(t.count(), left);
+        }
+    }
+
     use proptest::prelude::*;
     proptest! {
         #[test]
@@ -1683,7 +3231,9 @@ mod tests {
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_size() {
-        assert_eq!(std::mem::size_of::<S>(), 32);
+        // The hash key takes another word in the header of a table.
+        let key = 8 * cfg!(feature = "keyed_hashing") as usize;
+        assert_eq!(std::mem::size_of::<S>(), 32 + key);
         collect_size_is(&[0], 8);
         incremental_size_le(&[0], 8);
         collect_size_is(&[], 8);
@@ -1703,12 +3253,12 @@ mod tests {
         incremental_size_le(&(10000..10000 + 7).collect::<Vec<_>>(), 8);
         incremental_size_le(&(100000..100000 + 7).collect::<Vec<_>>(), 8);
 
-        incremental_size_le(&(1..30).collect::<Vec<_>>(), 40);
-        incremental_size_le(&(1..60).collect::<Vec<_>>(), 40);
+        incremental_size_le(&(1..30).collect::<Vec<_>>(), 40 + key);
+        incremental_size_le(&(1..60).collect::<Vec<_>>(), 40 + key);
 
-        incremental_size_le(&(1..160).collect::<Vec<_>>(), 88);
+        incremental_size_le(&(1..160).collect::<Vec<_>>(), 88 + key);
 
-        incremental_size_le(&(0..100).map(|x| x * 10).collect::<Vec<_>>(), 400);
+        incremental_size_le(&(0..100).map(|x| x * 10).collect::<Vec<_>>(), 400 + key);
     }
 
     #[cfg(target_pointer_width = "32")]
@@ -1740,27 +3290,27 @@ mod tests {
         println!("\n\nprimitives: {:?}\n", elems);
         let mut a = vec![0; sz];
         for x in elems.iter().cloned() {
-            if p_lookfor(x, &a, 0).key_found() {
+            if p_lookfor(x, &a, 0, 0).key_found() {
                 println!("we already have {}", x);
             } else {
-                let i = p_insert(x, &mut a, 0);
+                let i = p_insert(x, &mut a, 0, 0);
                 a[i] = x;
             }
             println!("    after inserting {} we have {:?}", x, a);
-            assert!(p_lookfor(x, &a, 0).key_found());
+            assert!(p_lookfor(x, &a, 0, 0).key_found());
         }
         for x in elems.iter().cloned() {
             println!("looking for {}", x);
-            assert!(p_lookfor(x, &a, 0).key_found());
+            assert!(p_lookfor(x, &a, 0, 0).key_found());
         }
         for x in elems.iter().cloned() {
             println!("removing {}", x);
-            p_remove(x, &mut a, 0);
+            p_remove(x, &mut a, 0, 0);
             println!("    after removing {} we have {:?}", x, a);
         }
         for x in elems.iter().cloned() {
             println!("XXXX looking for {}", x);
-            assert!(p_lookfor(x, &a, 0).empty_spot());
+            assert!(p_lookfor(x, &a, 0, 0).empty_spot());
         }
         println!("after everything was removed: {:?}", a);
         for x in a.iter().cloned() {
@@ -1776,18 +3326,31 @@ mod tests {
     }
 }
 
-fn p_poverty(k: u64, idx: usize, n: usize) -> usize {
-    ((idx % n) + n - (k % n as u64) as usize) % n
+/// The index where `k` belongs in a hash table of `n` elements.  A
+/// nonzero `hashkey` is mixed into the hash, so that which elements collide
+/// cannot be predicted without knowing it.
+#[inline]
+fn p_home(k: u64, hashkey: u64, n: usize) -> usize {
+    let h = if hashkey == 0 {
+        k
+    } else {
+        crate::rand::splitmix(k ^ hashkey)
+    };
+    (h % n as u64) as usize
+}
+
+fn p_poverty(k: u64, hashkey: u64, idx: usize, n: usize) -> usize {
+    ((idx % n) + n - p_home(k, hashkey, n)) % n
 }
 
 /// This inserts k into the array, and requires that there be room for
 /// one more element.  Otherwise, things will be sad.
-fn p_insert(k: u64, a: &mut [u64], offset: u64) -> usize {
+fn p_insert(k: u64, a: &mut [u64], offset: u64, hashkey: u64) -> usize {
     let n = a.len();
     for pov in 0..n {
-        let ii = (((k % n as u64) + pov as u64) % n as u64) as usize;
+        let ii = (p_home(k, hashkey, n) + pov) % n;
         let ki = a[ii] >> offset;
-        let pov_ki = p_poverty(ki, ii, n);
+        let pov_ki = p_poverty(ki, hashkey, ii, n);
         if a[ii] == 0 || ki == k {
             // println!("already got a spot");
             return ii;
@@ -1804,7 +3367,7 @@ fn p_insert(k: u64, a: &mut [u64], offset: u64) -> usize {
                 pov_displaced += 1;
                 let jj = (stolen + j) % n;
                 let kj = a[jj] >> offset;
-                let pov_kj = p_poverty(kj, jj, n);
+                let pov_kj = p_poverty(kj, hashkey, jj, n);
                 if a[jj] == 0 {
                     // We finally found an unoccupied spot!
                     // println!("put the displaced at {}", jj);
@@ -1826,24 +3389,24 @@ fn p_insert(k: u64, a: &mut [u64], offset: u64) -> usize {
 #[test]
 fn test_insert() {
     let mut a = [0, 0, 0, 0];
-    assert_eq!(2, p_insert(2, &mut a, 0));
+    assert_eq!(2, p_insert(2, &mut a, 0, 0));
     assert_eq!(&a, &[0, 0, 0, 0]);
     for i in 0..10 {
-        assert_eq!(0, a[p_insert(i, &mut a, 0)]);
+        assert_eq!(0, a[p_insert(i, &mut a, 0, 0)]);
     }
 
     let mut a = [0, 0, 6, 0];
-    assert_eq!(3, p_insert(2, &mut a, 0));
+    assert_eq!(3, p_insert(2, &mut a, 0, 0));
     assert_eq!(&a, &[0, 0, 6, 0]);
     for i in 0..10 {
-        assert!([0, i].contains(&a[p_insert(i, &mut a, 0)]));
+        assert!([0, i].contains(&a[p_insert(i, &mut a, 0, 0)]));
     }
 
     let mut a = [0, 0, 6, 3];
-    assert_eq!(3, p_insert(2, &mut a, 0));
+    assert_eq!(3, p_insert(2, &mut a, 0, 0));
     assert_eq!(&a, &[3, 0, 6, 0]);
     for i in 0..10 {
-        assert!([0, i].contains(&a[p_insert(i, &mut a, 0)]));
+        assert!([0, i].contains(&a[p_insert(i, &mut a, 0, 0)]));
     }
 }
 
@@ -1879,17 +3442,17 @@ impl LookedUp {
     }
 }
 
-fn p_lookfor(k: u64, a: &[u64], offset: u64) -> LookedUp {
+fn p_lookfor(k: u64, a: &[u64], offset: u64, hashkey: u64) -> LookedUp {
     let n = a.len();
     for pov in 0..n {
-        let ii = (((k % n as u64) + pov as u64) % n as u64) as usize;
+        let ii = (p_home(k, hashkey, n) + pov) % n;
         // println!("looking in spot ii = {} with pov={}", ii, pov);
         if a[ii] == 0 {
             // println!("got empty spot at {} for key {}", ii, k);
             return LookedUp::EmptySpot(ii);
         }
         let ki = a[ii] >> offset;
-        let pov_ki = p_poverty(ki, ii, n);
+        let pov_ki = p_poverty(ki, hashkey, ii, n);
         if ki == k {
             // println!("lookfor already got a spot");
             return LookedUp::KeyFound(ii);
@@ -1901,31 +3464,88 @@ fn p_lookfor(k: u64, a: &[u64], offset: u64) -> LookedUp {
     LookedUp::NeedInsert
 }
 
+#[test]
+fn keyed_placement() {
+    // Multiples of the capacity all want the same slot, unless keyed.
+    let n = 1009;
+    let homes = |hashkey| {
+        (1..100u64)
+            .map(|i| p_home(i * n as u64, hashkey, n))
+            .collect::<std::collections::HashSet<_>>()
+            .len()
+    };
+    assert_eq!(homes(0), 1);
+    assert!(homes(0x1234_5678_9abc_def1) > 90);
+    let mut a = vec![0; n];
+    for i in 1..100 {
+        let k = i * n as u64;
+        let idx = p_insert(k, &mut a, 0, 12345);
+        a[idx] = k;
+    }
+    for i in 1..100 {
+        assert!(p_lookfor(i * n as u64, &a, 0, 12345).key_found());
+        assert!(!p_lookfor(i * n as u64, &a, 0, 0).key_found());
+    }
+}
+
+#[cfg(feature = "keyed_hashing")]
+#[test]
+fn keyed_sets() {
+    let keyed = |s: &SetU64| match s.internal() {
+        Internal::Heap { s: b, .. } | Internal::Big { s: b, .. } => b.hashkey() != 0,
+        _ => panic!("expected a hash table"),
+    };
+    for v in [
+        (0..1000).map(|x| x * 1009).collect::<Vec<u64>>(),
+        (1..1000).map(|x| x << 40).collect(),
+    ] {
+        let s: SetU64 = v.iter().cloned().collect();
+        assert!(keyed(&s));
+        // The compact layout is the same as without a key.
+        let words = s.to_compact();
+        let view = SetU64Ref::new(&words).unwrap();
+        assert!(v.iter().all(|&x| view.contains(x)));
+        let mut t = view.to_set();
+        assert!(keyed(&t));
+        assert_eq!(t, s);
+        t.insert(1);
+        assert!(t.contains(1) && v.iter().all(|&x| t.contains(x)));
+    }
+    // Widening a set of u32 places its words with a key.
+    let v: Vec<u32> = (0..1000).map(|x| x * 1009).collect();
+    let mut t = SetU64::from(v.iter().cloned().collect::<crate::SetU32>());
+    assert!(keyed(&t));
+    assert!(v.iter().all(|&x| t.contains(x as u64)));
+    assert_eq!(t.len(), v.len());
+    t.insert(1);
+    assert!(t.contains(1) && v.iter().all(|&x| t.contains(x as u64)));
+}
+
 #[test]
 fn test_lookfor() {
-    assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[3, 1, 2], 0));
-    assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[3, 0, 2], 0));
-    assert_eq!(LookedUp::KeyFound(3), p_lookfor(7, &[0, 0, 0, 7], 0));
+    assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[3, 1, 2], 0, 0));
+    assert_eq!(LookedUp::NeedInsert, p_lookfor(5, &[3, 0, 2], 0, 0));
+    assert_eq!(LookedUp::KeyFound(3), p_lookfor(7, &[0, 0, 0, 7], 0, 0));
 }
 
 /// Remove value `k` from hashmap `a`, where we bit shift by `offset`
 ///
 /// Returns true if the value was found.
-fn p_remove(k: u64, a: &mut [u64], offset: u64) -> bool {
+fn p_remove(k: u64, a: &mut [u64], offset: u64, hashkey: u64) -> bool {
     let n = a.len();
     // Below `i` is the offset from the ideal location of our value, so we start
     // with where it ought to be in the hashmap.
     for i in 0..n {
         // `ii` is the index for the location that is `i` beyond the bucket
         // where the value ought to be.
-        let ii = (((k % n as u64) + i as u64) % n as u64) as usize;
+        let ii = (p_home(k, hashkey, n) + i) % n;
         // println!("    looking to remove at distance {} slot {}", i, ii);
         if a[ii] == 0 {
             // We use a `0` value to indicate an empty bucket.
             return false;
         }
         let ki = a[ii] >> offset;
-        let iki = (((ii + n) as u64 - (ki % n as u64)) % n as u64) as usize;
+        let iki = p_poverty(ki, hashkey, ii, n);
         if i > iki {
             return false;
         } else if ki == k {
@@ -1938,7 +3558,7 @@ fn p_remove(k: u64, a: &mut [u64], offset: u64) -> bool {
                 let jj = (ii + j) % n;
                 // println!("looking at removing offset {} at location {}", j, jj);
                 let kj = a[jj] >> offset;
-                let pov_kj = p_poverty(kj, jj, n);
+                let pov_kj = p_poverty(kj, hashkey, jj, n);
                 if a[jj] == 0 || pov_kj == 0 {
                     // We found an unoccupied spot or a perfectly
                     // happy customer, so nothing else could have been
@@ -1961,15 +3581,15 @@ fn p_remove(k: u64, a: &mut [u64], offset: u64) -> bool {
 fn test_insert_remove(x: u64, a: &mut [u64]) {
     println!("test_insert_remove({}, {:?})", x, a);
     let v: Vec<u64> = a.iter().cloned().collect();
-    assert!(!p_remove(x, a, 0));
+    assert!(!p_remove(x, a, 0, 0));
     assert!(!a.contains(&x)); // otherwise the test won't work right.
-    assert!(!p_lookfor(x, a, 0).key_found());
-    a[p_insert(x, a, 0)] = x;
+    assert!(!p_lookfor(x, a, 0, 0).key_found());
+    a[p_insert(x, a, 0, 0)] = x;
     assert!(a.contains(&x));
     println!("  after insertion of {} a is {:?}", x, a);
-    assert!(p_lookfor(x, a, 0).key_found());
-    assert_eq!(x, a[p_lookfor(x, a, 0).unwrap()]);
-    assert!(p_remove(x, a, 0));
+    assert!(p_lookfor(x, a, 0, 0).key_found());
+    assert_eq!(x, a[p_lookfor(x, a, 0, 0).unwrap()]);
+    assert!(p_remove(x, a, 0, 0));
     println!("  after remove of {} a is {:?}", x, a);
     assert_eq!(a, &v[..]);
 }
@@ -1977,11 +3597,11 @@ fn test_insert_remove(x: u64, a: &mut [u64]) {
 #[test]
 fn test_remove() {
     let mut a = [0, 0, 2];
-    a[p_insert(5, &mut a, 0)] = 5;
+    a[p_insert(5, &mut a, 0, 0)] = 5;
     assert_eq!(&[5, 0, 2], &a);
-    p_remove(2, &mut a, 0);
+    p_remove(2, &mut a, 0, 0);
     println!("after removal {:?}", a);
-    assert!(p_lookfor(5, &a, 0).key_found());
+    assert!(p_lookfor(5, &a, 0, 0).key_found());
     assert_eq!(&[0, 0, 5], &a);
 
     test_insert_remove(7, &mut [0, 0, 0, 0]);
@@ -1994,10 +3614,10 @@ fn test_remove() {
 #[test]
 fn p_remove_from_small_full() {
     let mut a = [258, 260];
-    assert!(!p_remove(2, &mut a, 0));
+    assert!(!p_remove(2, &mut a, 0, 0));
     assert_eq!(a[0], 258);
     assert_eq!(a[1], 260);
-    assert!(p_remove(258, &mut a, 0));
+    assert!(p_remove(258, &mut a, 0, 0));
     assert_eq!(a[0], 260);
     assert_eq!(a[1], 0);
 }
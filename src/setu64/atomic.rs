@@ -0,0 +1,262 @@
+use super::{SetU64, Tiny};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// The value of `word` once the set no longer fits in it.  Real stack sets
+/// always have one of their low three bits set.
+const SPILLED: usize = 8;
+const NUM_SHARDS: usize = 16;
+
+/// A set of `u64` that can be modified concurrently through a shared
+/// reference.
+///
+/// Small sets are stored in a single atomic word, just like a [`SetU64`]
+/// stores them in its pointer, and are updated with compare-and-swap.
+/// Values no larger than the maximum given to
+/// [`with_max`](AtomicSetU64::with_max) are stored in a preallocated
+/// bitmap and inserted with a single `fetch_or`.  Anything else goes to a
+/// set of sharded, locked [`SetU64`]s.
+///
+/// ```
+/// use tinyset::AtomicSetU64;
+///
+/// let visited = AtomicSetU64::with_max(1000);
+/// std::thread::scope(|s| {
+///     for t in 0..4 {
+///         let visited = &visited;
+///         s.spawn(move || {
+///             for x in 0..500 {
+///                 visited.insert(x * 4 + t);
+///             }
+///         });
+///     }
+/// });
+/// assert!(visited.contains(1999));
+/// assert_eq!(visited.snapshot().len(), 2000);
+/// ```
+pub struct AtomicSetU64 {
+    word: AtomicUsize,
+    dense: Box<[AtomicU64]>,
+    shards: Box<[Mutex<SetU64>]>,
+    spill_lock: Mutex<()>,
+}
+
+impl Default for AtomicSetU64 {
+    fn default() -> Self {
+        AtomicSetU64::new()
+    }
+}
+
+impl AtomicSetU64 {
+    /// An empty set
+    pub fn new() -> Self {
+        AtomicSetU64 {
+            word: AtomicUsize::new(0),
+            dense: Box::new([]),
+            shards: (0..NUM_SHARDS).map(|_| Mutex::new(SetU64::new())).collect(),
+            spill_lock: Mutex::new(()),
+        }
+    }
+    /// An empty set with a bitmap preallocated for values up to `mx`.
+    ///
+    /// Larger values may still be inserted, but are slower.
+    pub fn with_max(mx: u64) -> Self {
+        AtomicSetU64 {
+            dense: (0..=mx / 64).map(|_| AtomicU64::new(0)).collect(),
+            ..AtomicSetU64::new()
+        }
+    }
+
+    fn shard(&self, e: u64) -> MutexGuard<'_, SetU64> {
+        let i = (e.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize % NUM_SHARDS;
+        self.shards[i].lock().unwrap()
+    }
+
+    /// Insert and return true if it was not present.
+    ///
+    /// When several threads insert the same value at once, exactly one of
+    /// them sees `true`.
+    pub fn insert(&self, e: u64) -> bool {
+        if let Some(w) = self.dense.get((e >> 6) as usize) {
+            let bit = 1 << (e & 63);
+            return w.fetch_or(bit, Ordering::AcqRel) & bit == 0;
+        }
+        let mut w = self.word.load(Ordering::Acquire);
+        while w != SPILLED {
+            let next = if w == 0 {
+                Tiny::from_singleton(e)
+            } else {
+                let t = Tiny::from_usize(w);
+                if t.contains(e) {
+                    return false;
+                }
+                t.insert(e)
+            };
+            if let Some(t) = next {
+                match self.word.compare_exchange_weak(
+                    w,
+                    t.to_usize(),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => return true,
+                    Err(actual) => w = actual,
+                }
+            } else {
+                self.spill();
+                break;
+            }
+        }
+        self.shard(e).insert(e)
+    }
+
+    /// Move the elements stored in `word` to the shards.
+    fn spill(&self) {
+        let _guard = self.spill_lock.lock().unwrap();
+        let mut w = self.word.load(Ordering::Acquire);
+        while w != SPILLED {
+            // The stack set only ever grows, so these are all members of
+            // the set even if `word` changes before we are done.
+            if w != 0 {
+                for x in Tiny::from_usize(w) {
+                    self.shard(x).insert(x);
+                }
+            }
+            match self
+                .word
+                .compare_exchange(w, SPILLED, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return,
+                Err(actual) => w = actual,
+            }
+        }
+    }
+
+    /// Returns true if the set contains a value.
+    pub fn contains(&self, e: u64) -> bool {
+        if let Some(w) = self.dense.get((e >> 6) as usize) {
+            return w.load(Ordering::Acquire) & (1 << (e & 63)) != 0;
+        }
+        match self.word.load(Ordering::Acquire) {
+            0 => false,
+            SPILLED => self.shard(e).contains(e),
+            w => Tiny::from_usize(w).contains(e),
+        }
+    }
+
+    /// Copy the current contents into a [`SetU64`].
+    ///
+    /// Values that are inserted while the snapshot is being taken may or
+    /// may not be included.
+    pub fn snapshot(&self) -> SetU64 {
+        let mut out = SetU64::new();
+        for (i, w) in self.dense.iter().enumerate() {
+            let mut w = w.load(Ordering::Acquire);
+            while w != 0 {
+                out.insert(((i as u64) << 6) + w.trailing_zeros() as u64);
+                w &= w - 1;
+            }
+        }
+        match self.word.load(Ordering::Acquire) {
+            0 | SPILLED => (),
+            w => out.extend(Tiny::from_usize(w)),
+        }
+        for s in self.shards.iter() {
+            out.extend(s.lock().unwrap().iter());
+        }
+        out
+    }
+
+    /// Tally up how much memory is in use, including the set itself.
+    pub fn mem_used(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_bytes()
+    }
+
+    /// The number of bytes allocated on the heap for this set.  This
+    /// locks each shard in turn.
+    pub fn heap_bytes(&self) -> usize {
+        let shards: usize = self
+            .shards
+            .iter()
+            .map(|s| s.lock().unwrap().heap_bytes())
+            .sum();
+        std::mem::size_of_val(&*self.dense) + std::mem::size_of_val(&*self.shards) + shards
+    }
+}
+
+impl std::fmt::Debug for AtomicSetU64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "AtomicSetU64 {:?}",
+            self.snapshot().iter().collect::<Vec<_>>()
+        )
+    }
+}
+
+impl From<AtomicSetU64> for SetU64 {
+    fn from(s: AtomicSetU64) -> Self {
+        s.snapshot()
+    }
+}
+
+#[cfg(test)]
+fn check_atomic(s: &AtomicSetU64, elems: &[u64]) {
+    let mut expected = std::collections::HashSet::new();
+    for &x in elems {
+        assert_eq!(s.insert(x), expected.insert(x));
+        assert!(s.contains(x));
+    }
+    for &x in elems {
+        assert!(s.contains(x));
+        assert!(!s.contains(x.wrapping_add(1)) || expected.contains(&x.wrapping_add(1)));
+    }
+    let snap = s.snapshot();
+    assert_eq!(snap.len(), expected.len());
+    for x in expected {
+        assert!(snap.contains(x));
+    }
+}
+
+#[test]
+fn atomic_sets() {
+    check_atomic(&AtomicSetU64::new(), &[]);
+    check_atomic(&AtomicSetU64::new(), &[1, 2, 3, 2]);
+    check_atomic(&AtomicSetU64::new(), &(0..1000).collect::<Vec<_>>());
+    check_atomic(&AtomicSetU64::new(), &[0, 1 << 63, u64::MAX]);
+    check_atomic(&AtomicSetU64::with_max(100), &(50..150).collect::<Vec<_>>());
+    check_atomic(&AtomicSetU64::with_max(0), &[0, 1, 1 << 40]);
+}
+
+#[test]
+fn concurrent_inserts() {
+    for mx in [0, 1000] {
+        let s = AtomicSetU64::with_max(mx);
+        let newly = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for x in 0..2000 {
+                        if s.insert(x * 7) {
+                            newly.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(newly.load(Ordering::Relaxed), 2000);
+        assert_eq!(s.snapshot(), (0..2000).map(|x| x * 7).collect());
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn atomic_matches_hashset(v: Vec<u64>) {
+        check_atomic(&AtomicSetU64::new(), &v);
+    }
+    #[test]
+    fn atomic_matches_hashset_small(v in proptest::collection::vec(0u64..300, 0usize..100), mx in 0u64..200) {
+        check_atomic(&AtomicSetU64::with_max(mx), &v);
+    }
+}
@@ -0,0 +1,17 @@
+use super::{mask, unsplit_u64, Internal, SetU64, Tiny};
+use crate::cursor::impl_cursor;
+
+impl_cursor!(SetU64, u64, unsplit_u64, crate::kernels::next_nonzero);
+
+#[test]
+fn cursor() {
+    check_cursor(&[], &[0, 5]);
+    check_cursor(&[3, 1, 2], &[2, 2, 0]);
+    check_cursor(&[0, u64::MAX], &[1, u64::MAX]);
+    let dense: Vec<u64> = (0..10000).filter(|x| x % 1000 < 3).collect();
+    check_cursor(&dense, &[0, 1, 1000, 1002, 1003, 5500, 9002]);
+    let sparse: Vec<u64> = (0..1000).map(|x| x * 1000 + x % 7).collect();
+    check_cursor(&sparse, &[0, 5, 1000, 1001, 50_000, 50_004, 999_999]);
+    let big: Vec<u64> = (0..100).map(|x| x << 58).collect();
+    check_cursor(&big, &[1, 3 << 58, (3 << 58) + 1, 99 << 58]);
+}
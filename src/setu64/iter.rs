@@ -1,43 +1,25 @@
-use std::borrow::Borrow;
-
-use super::{mask, unsplit_u64, Internal, SetU64};
+use super::{mask, unsplit_u64, Internal, SetU64, Storage};
 
 impl SetU64 {
     /// Iterate over
     #[inline]
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = u64> + 'a + std::fmt::Debug {
-        self.inner_iter()
+        Inner::new(self)
     }
-}
-
-impl SetU64 {
-    fn inner_iter(&self) -> Inner<&SetU64> {
-        match self.internal() {
-            Internal::Empty => Inner::empty(self),
-            Internal::Stack(t) => Inner {
-                sz: t.sz as usize,
-                sz_left: t.sz as usize,
-                bits: t.bits as u64,
-                ..Inner::empty(self)
-            },
-            Internal::Heap { s, .. } => Inner {
-                sz: s.sz,
-                sz_left: s.sz,
-                bits: s.bits,
-                ..Inner::empty(self)
-            },
-            Internal::Big { s, .. } => Inner {
-                sz: s.sz,
-                sz_left: s.sz,
-                bits: s.bits,
-                ..Inner::empty(self)
-            },
-            Internal::Dense { sz, .. } => Inner {
-                sz: sz,
-                sz_left: sz,
-                ..Inner::empty(self)
-            },
-        }
+    /// Iterate over the elements in ascending order.
+    ///
+    /// Small and dense sets are stored in order already.  Otherwise this
+    /// sorts the underlying table, which is never larger than the set.
+    ///
+    /// ```
+    /// use tinyset::SetU64;
+    ///
+    /// let a: SetU64 = [30, 1, 2000, 7].iter().cloned().collect();
+    /// assert_eq!(a.iter_sorted().collect::<Vec<_>>(), vec![1, 7, 30, 2000]);
+    /// ```
+    #[inline]
+    pub fn iter_sorted<'a>(&'a self) -> impl Iterator<Item = u64> + 'a + std::fmt::Debug {
+        Sorted::new(self)
     }
 }
 
@@ -46,17 +28,9 @@ impl IntoIterator for SetU64 {
     type IntoIter = IntoIter;
 
     fn into_iter(self) -> IntoIter {
-        let x = self.inner_iter();
-        let inner = Inner {
-            sz: x.sz,
-            sz_left: x.sz_left,
-            bits: x.bits,
-            whichbit: x.whichbit,
-            last: x.last,
-            index: x.index,
-            set: self,
-        };
-        IntoIter { inner }
+        IntoIter {
+            inner: Inner::new(self),
+        }
     }
 }
 
@@ -89,7 +63,7 @@ impl Iterator for IntoIter {
 }
 
 #[derive(Debug, Clone)]
-struct Inner<T: Borrow<SetU64>> {
+pub(super) struct Inner<T: Storage> {
     sz: usize,
     sz_left: usize,
     bits: u64,
@@ -99,12 +73,19 @@ struct Inner<T: Borrow<SetU64>> {
     set: T,
 }
 
-impl<T: Borrow<SetU64>> Inner<T> {
-    fn empty(set: T) -> Self {
+impl<T: Storage> Inner<T> {
+    pub(super) fn new(set: T) -> Self {
+        let (sz, bits) = match set.internal() {
+            Internal::Empty => (0, 0),
+            Internal::Stack(t) => (t.sz as usize, t.bits as u64),
+            Internal::Heap { s, .. } => (s.sz, s.bits),
+            Internal::Big { s, .. } => (s.sz, s.bits),
+            Internal::Dense { sz, .. } => (sz, 0),
+        };
         Inner {
-            sz: 0,
-            sz_left: 0,
-            bits: 0,
+            sz,
+            sz_left: sz,
+            bits,
             whichbit: 0,
             index: 0,
             last: 0,
@@ -113,11 +94,11 @@ impl<T: Borrow<SetU64>> Inner<T> {
     }
 }
 
-impl<T: Borrow<SetU64>> Iterator for Inner<T> {
+impl<T: Storage> Iterator for Inner<T> {
     type Item = u64;
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(_) => {
                 let bitsplits = super::BITSPLITS[self.sz];
@@ -169,27 +150,33 @@ impl<T: Borrow<SetU64>> Iterator for Inner<T> {
                 }
                 None
             }
-            Internal::Dense { a, .. } => loop {
-                if let Some(word) = a.get(self.index) {
-                    while self.whichbit < 64 {
-                        let bit = self.whichbit;
-                        self.whichbit = 1 + bit;
-                        if word & (1 << bit) != 0 {
-                            self.sz_left -= 1;
-                            return Some(((self.index as u64) << 6) + bit as u64);
-                        }
+            Internal::Dense { a, .. } => {
+                if self.sz_left == 0 {
+                    return None;
+                }
+                while let Some(&word) = a.get(self.index) {
+                    // Clear the bits we have already returned.
+                    let word = if self.whichbit < 64 {
+                        word & (!0 << self.whichbit)
+                    } else {
+                        0
+                    };
+                    if word != 0 {
+                        let bit = word.trailing_zeros() as u64;
+                        self.whichbit = bit + 1;
+                        self.sz_left -= 1;
+                        return Some(((self.index as u64) << 6) + bit);
                     }
                     self.whichbit = 0;
-                    self.index += 1;
-                } else {
-                    return None;
+                    self.index = crate::kernels::next_nonzero(a, self.index + 1);
                 }
-            },
+                None
+            }
         }
     }
     #[inline]
     fn last(self) -> Option<Self::Item> {
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(t) => t.max(),
             Internal::Heap { a, .. } => a
@@ -223,7 +210,7 @@ impl<T: Borrow<SetU64>> Iterator for Inner<T> {
         if self.sz_left == 0 {
             return None;
         }
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(t) => t.min(),
             Internal::Heap { a, .. } => {
@@ -254,7 +241,7 @@ impl<T: Borrow<SetU64>> Iterator for Inner<T> {
         if self.sz_left == 0 {
             return None;
         }
-        match self.set.borrow().internal() {
+        match self.set.internal() {
             Internal::Empty => None,
             Internal::Stack(t) => t.max(),
             Internal::Heap { a, .. } => {
@@ -295,3 +282,89 @@ impl<T: Borrow<SetU64>> Iterator for Inner<T> {
         (self.sz_left, Some(self.sz_left))
     }
 }
+
+/// An iterator in ascending order.
+#[derive(Debug, Clone)]
+pub(super) enum Sorted<T: Storage> {
+    /// The set is stored in order, so we can just iterate over it.
+    InOrder(Inner<T>),
+    /// The (sorted) nonzero words of a hash table of bitmaps.
+    Heap {
+        bits: u64,
+        words: std::vec::IntoIter<u64>,
+        key: u64,
+        bitmap: u64,
+        sz_left: usize,
+    },
+    /// The sorted elements of a big set.
+    Big(std::vec::IntoIter<u64>),
+}
+
+impl<T: Storage> Sorted<T> {
+    pub(super) fn new(set: T) -> Self {
+        match set.internal() {
+            Internal::Heap { s, a } => {
+                // The key is in the high bits, so sorting the words sorts
+                // the bitmaps.
+                let mut words: Vec<u64> = a.iter().cloned().filter(|&x| x != 0).collect();
+                words.sort_unstable();
+                Sorted::Heap {
+                    bits: s.bits,
+                    words: words.into_iter(),
+                    key: 0,
+                    bitmap: 0,
+                    sz_left: s.sz,
+                }
+            }
+            Internal::Big { s, a } => {
+                let mut v: Vec<u64> = a
+                    .iter()
+                    .cloned()
+                    .filter(|&x| x != 0)
+                    .map(|x| if x == s.bits { 0 } else { x })
+                    .collect();
+                v.sort_unstable();
+                Sorted::Big(v.into_iter())
+            }
+            Internal::Empty | Internal::Stack(_) | Internal::Dense { .. } => {
+                Sorted::InOrder(Inner::new(set))
+            }
+        }
+    }
+}
+
+impl<T: Storage> Iterator for Sorted<T> {
+    type Item = u64;
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        match self {
+            Sorted::InOrder(i) => i.next(),
+            Sorted::Heap {
+                bits,
+                words,
+                key,
+                bitmap,
+                sz_left,
+            } => {
+                while *bitmap == 0 {
+                    let x = words.next()?;
+                    *key = x >> *bits;
+                    *bitmap = x & mask(*bits as usize);
+                }
+                let offset = bitmap.trailing_zeros() as u64;
+                *bitmap &= *bitmap - 1;
+                *sz_left -= 1;
+                Some(unsplit_u64(*key, offset, *bits))
+            }
+            Sorted::Big(v) => v.next(),
+        }
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Sorted::InOrder(i) => i.size_hint(),
+            Sorted::Heap { sz_left, .. } => (*sz_left, Some(*sz_left)),
+            Sorted::Big(v) => v.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use super::iter::Inner;
+use super::{
+    compute_array_bits, mask, p_lookfor, Internal, LookedUp, Sbeginning, SetU64, Storage, Tiny,
+};
+
+/// A read-only view of a [`SetU64`] stored in its compact layout.
+///
+/// The layout is the one produced by [`SetU64::to_compact`], and is
+/// intended for sets that are precomputed and stored in a file which is
+/// then memory mapped.  A `SetU64Ref` never allocates: it answers queries
+/// directly from the borrowed buffer, which is validated once when the view
+/// is created.
+///
+/// ```
+/// use tinyset::{SetU64, SetU64Ref};
+///
+/// let a: SetU64 = [1, 5, 1 << 40].iter().cloned().collect();
+/// let words = a.to_compact();
+/// let view = SetU64Ref::new(&words).unwrap();
+/// assert!(view.contains(1 << 40));
+/// assert_eq!(view.min(), Some(1));
+/// assert_eq!(view.max(), Some(1 << 40));
+/// assert_eq!(view.to_set(), a);
+/// ```
+#[derive(Clone, Copy)]
+pub struct SetU64Ref<'a> {
+    words: &'a [u64],
+    b: Sbeginning,
+}
+
+impl<'a> Storage for SetU64Ref<'a> {
+    #[inline]
+    fn internal(&self) -> Internal<'_> {
+        match self.words.len() {
+            1 if self.words[0] == 0 => Internal::Empty,
+            1 => Internal::Stack(Tiny::from_usize(self.words[0] as usize)),
+            _ => Internal::from_parts(&self.b, &self.words[2..]),
+        }
+    }
+}
+
+impl<'a> SetU64Ref<'a> {
+    /// Interpret `words` as a set in the compact layout.
+    ///
+    /// Returns `None` if `words` is not a valid set.
+    pub fn new(words: &'a [u64]) -> Option<Self> {
+        let b = match words.len() {
+            0 | 2 => return None,
+            1 => {
+                let w = words[0];
+                if w > usize::MAX as u64 || (w != 0 && w & 7 == 0) {
+                    // This would be a pointer rather than a set.
+                    return None;
+                }
+                Sbeginning {
+                    sz: 0,
+                    cap: 0,
+                    bits: 0,
+                    #[cfg(feature = "keyed_hashing")]
+                    hashkey: 0,
+                }
+            }
+            n => {
+                if words[0] > usize::MAX as u64 {
+                    return None;
+                }
+                Sbeginning {
+                    sz: words[0] as usize,
+                    cap: n - 2,
+                    bits: words[1],
+                    #[cfg(feature = "keyed_hashing")]
+                    hashkey: 0,
+                }
+            }
+        };
+        let view = SetU64Ref { words, b };
+        if view.is_valid() {
+            Some(view)
+        } else {
+            None
+        }
+    }
+
+    /// Interpret `bytes` as a set in the compact layout, using the native
+    /// byte order.
+    ///
+    /// Returns `None` if `bytes` is not aligned for `u64`, or is not a
+    /// valid set.
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.as_ptr() as usize % std::mem::align_of::<u64>() != 0 || bytes.len() % 8 != 0 {
+            return None;
+        }
+        // SAFETY: the pointer is aligned, the length is a whole number of
+        // `u64`, and any bit pattern is a valid `u64`.
+        let words =
+            unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u64, bytes.len() / 8) };
+        SetU64Ref::new(words)
+    }
+
+    fn is_valid(&self) -> bool {
+        match self.internal() {
+            Internal::Empty | Internal::Stack(_) => true,
+            Internal::Dense { sz, a } => {
+                a.iter().map(|w| w.count_ones() as usize).sum::<usize>() == sz
+            }
+            Internal::Heap { s, a } => {
+                let mut count = 0;
+                for (i, &x) in a.iter().enumerate() {
+                    if x == 0 {
+                        continue;
+                    }
+                    let key = x >> s.bits;
+                    let bitmap = x & mask(s.bits as usize);
+                    if bitmap == 0
+                        || p_lookfor(key, a, s.bits, s.hashkey()) != LookedUp::KeyFound(i)
+                    {
+                        return false;
+                    }
+                    let highest = 63 - bitmap.leading_zeros() as u64;
+                    match key.checked_mul(s.bits).and_then(|k| k.checked_add(highest)) {
+                        Some(mx) if compute_array_bits(mx) >= s.bits => (),
+                        _ => return false,
+                    }
+                    count += bitmap.count_ones() as usize;
+                }
+                count == s.sz
+            }
+            Internal::Big { s, a } => {
+                let mut count = 0;
+                for (i, &x) in a.iter().enumerate() {
+                    if x == 0 {
+                        continue;
+                    }
+                    if p_lookfor(x, a, 0, s.hashkey()) != LookedUp::KeyFound(i) {
+                        return false;
+                    }
+                    count += 1;
+                }
+                count == s.sz
+            }
+        }
+    }
+
+    /// The number of elements in the set
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.internal().len()
+    }
+    /// Returns true if the set is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns true if the set contains a value.
+    #[inline]
+    pub fn contains(&self, e: u64) -> bool {
+        self.internal().contains(e)
+    }
+    /// Iterate over the elements, in no particular order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = u64> + 'a + std::fmt::Debug {
+        Inner::new(*self)
+    }
+    /// The smallest element of the set.
+    pub fn min(&self) -> Option<u64> {
+        self.iter().min()
+    }
+    /// The largest element of the set.
+    pub fn max(&self) -> Option<u64> {
+        self.iter().max()
+    }
+    /// Copy the viewed set into a [`SetU64`].
+    pub fn to_set(&self) -> SetU64 {
+        SetU64::from_array(self.words)
+    }
+}
+
+impl<'a> std::fmt::Debug for SetU64Ref<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "SetU64Ref {:?}", self.iter().collect::<Vec<_>>())
+    }
+}
+
+impl<'a> PartialEq<SetU64> for SetU64Ref<'a> {
+    fn eq(&self, other: &SetU64) -> bool {
+        self.len() == other.len() && self.iter().all(|x| other.contains(x))
+    }
+}
+
+#[cfg(test)]
+fn check_view(v: &[u64]) {
+    let set: SetU64 = v.iter().cloned().collect();
+    let words = set.to_compact();
+    let view = SetU64Ref::new(&words).unwrap();
+    assert_eq!(view, set);
+    assert_eq!(view.len(), set.len());
+    assert_eq!(view.min(), v.iter().cloned().min());
+    assert_eq!(view.max(), v.iter().cloned().max());
+    for &x in v {
+        assert!(view.contains(x));
+        assert!(!view.contains(x.wrapping_add(1)) || set.contains(x.wrapping_add(1)));
+    }
+    assert_eq!(view.to_set(), set);
+    let bytes = unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) };
+    assert_eq!(SetU64Ref::from_bytes(bytes).unwrap(), set);
+    if bytes.len() > 8 {
+        assert!(SetU64Ref::from_bytes(&bytes[1..]).is_none());
+    }
+}
+
+#[test]
+fn views() {
+    check_view(&[]);
+    check_view(&[0]);
+    check_view(&[1, 2, 3]);
+    check_view(&(0..1000).collect::<Vec<_>>());
+    check_view(&(0..1000).map(|x| x * 100).collect::<Vec<_>>());
+    check_view(&[0, 1 << 63, u64::MAX, 7]);
+}
+
+#[test]
+fn invalid_views() {
+    assert!(SetU64Ref::new(&[]).is_none());
+    assert!(SetU64Ref::new(&[8]).is_none());
+    assert!(SetU64Ref::new(&[0, 0]).is_none());
+    // A dense set whose size does not match its bitmap.
+    assert!(SetU64Ref::new(&[3, 64, 0b11]).is_none());
+    assert!(SetU64Ref::new(&[2, 64, 0b11]).is_some());
+
+    let set: SetU64 = (0..100).map(|x| x * 1000).collect();
+    let mut words = set.to_compact();
+    assert!(SetU64Ref::new(&words).is_some());
+    words[0] += 1;
+    assert!(SetU64Ref::new(&words).is_none());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn view_matches_set(v: Vec<u64>) {
+        check_view(&v);
+    }
+    #[test]
+    fn view_matches_small_set(v in proptest::collection::vec(0u64..5000, 0usize..200)) {
+        check_view(&v);
+    }
+}
@@ -54,8 +54,29 @@ impl Default for SetUsize {
     }
 }
 
-use crate::copyset::impl_set_methods;
-impl_set_methods!(SetUsize);
+use crate::copyset::{impl_set_methods, FastOps};
+impl_set_methods!(SetUsize, FastOps);
+
+impl FastOps for SetUsize {
+    fn fast_eq(&self, other: &Self) -> Option<bool> {
+        self.0.fast_eq(&other.0)
+    }
+    fn fast_union(&self, other: &Self) -> Option<Self> {
+        self.0.fast_union(&other.0).map(SetUsize)
+    }
+    fn fast_intersection(&self, other: &Self) -> Option<Self> {
+        self.0.fast_intersection(&other.0).map(SetUsize)
+    }
+    fn fast_difference(&self, other: &Self) -> Option<Self> {
+        self.0.fast_difference(&other.0).map(SetUsize)
+    }
+    fn fast_union_with(&mut self, other: &Self) -> bool {
+        self.0.fast_union_with(&other.0)
+    }
+    fn fast_difference_with(&mut self, other: &Self) -> bool {
+        self.0.fast_difference_with(&other.0)
+    }
+}
 
 impl SetUsize {
     /// Creates an empty set..
@@ -82,11 +103,66 @@ impl SetUsize {
     pub fn capacity(&self) -> usize {
         self.0.capacity()
     }
+    /// The name of the representation and its number of bits, for `{:#?}`.
+    pub(crate) fn layout(&self) -> (&'static str, u64) {
+        self.0.layout()
+    }
+    /// The total memory used by the set (including stack)
+    #[inline]
+    pub fn mem_used(&self) -> usize {
+        self.0.mem_used()
+    }
+    /// The number of bytes allocated on the heap for this set.
+    #[inline]
+    pub fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
     /// Returns true if the set contains a value.
     #[inline]
     pub fn contains(&self, value: usize) -> bool {
         self.0.contains(value as Item)
     }
+    /// Returns true if the set contains every one of `vals`.
+    ///
+    /// See [`SetU64::contains_all`](crate::SetU64::contains_all).
+    pub fn contains_all(&self, vals: &[usize]) -> bool {
+        let mut all = true;
+        self.0.probe(vals.iter().map(|&x| x as Item), |_, found| {
+            all = found;
+            found
+        });
+        all
+    }
+    /// Returns true if the set contains at least one of `vals`.
+    pub fn contains_any(&self, vals: &[usize]) -> bool {
+        let mut any = false;
+        self.0.probe(vals.iter().map(|&x| x as Item), |_, found| {
+            any = found;
+            !found
+        });
+        any
+    }
+    /// Counts how many of `vals` are in the set, including repeats.
+    pub fn count_contained(&self, vals: &[usize]) -> usize {
+        let mut count = 0;
+        self.0.probe(vals.iter().map(|&x| x as Item), |_, found| {
+            count += found as usize;
+            true
+        });
+        count
+    }
+    /// Returns those of `vals` that are in the set, in their original
+    /// order.
+    pub fn filter_contained(&self, vals: &[usize]) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.0.probe(vals.iter().map(|&x| x as Item), |e, found| {
+            if found {
+                out.push(e as usize);
+            }
+            true
+        });
+        out
+    }
     /// Removes an element, and returns true if that element was present.
     #[inline]
     pub fn remove(&mut self, value: usize) -> bool {
@@ -97,23 +173,76 @@ impl SetUsize {
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
         self.0.iter().map(|x| x as usize)
     }
+    /// Iterate in ascending order
+    #[inline]
+    pub fn iter_sorted<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.0.iter_sorted().map(|x| x as usize)
+    }
     /// Drain
     #[inline]
     pub fn drain<'a>(&'a mut self) -> impl Iterator<Item = usize> + 'a {
         self.0.drain().map(|x| x as usize)
     }
+    /// Inserts every value in `range`, returning how many were not
+    /// already present.
+    ///
+    /// ```
+    /// use tinyset::SetUsize;
+    ///
+    /// let mut s = SetUsize::new();
+    /// assert_eq!(s.insert_range(10..1000), 990);
+    /// assert_eq!(s.remove_range(..500), 490);
+    /// assert_eq!(s.drain_range(900..).count(), 100);
+    /// assert_eq!(s, (500..900).collect());
+    /// ```
+    pub fn insert_range<R: std::ops::RangeBounds<usize>>(&mut self, range: R) -> usize {
+        self.0.insert_range(item_bounds(&range))
+    }
+    /// Removes every value in `range`, returning how many were present.
+    pub fn remove_range<R: std::ops::RangeBounds<usize>>(&mut self, range: R) -> usize {
+        self.0.remove_range(item_bounds(&range))
+    }
+    /// Removes every value in `range`, returning them in an iterator.
+    pub fn drain_range<R: std::ops::RangeBounds<usize>>(&mut self, range: R) -> IntoIter {
+        IntoIter(self.0.drain_range(item_bounds(&range)))
+    }
+    /// The values in `universe` that are not in the set.
+    ///
+    /// ```
+    /// use tinyset::SetUsize;
+    ///
+    /// let mut live: SetUsize = [1, 3, 4].iter().cloned().collect();
+    /// assert_eq!(live.complement(0..6), [0, 2, 5].iter().cloned().collect());
+    /// live.complement_in_place(5);
+    /// assert_eq!(live, [0, 2].iter().cloned().collect());
+    /// ```
+    pub fn complement(&self, universe: std::ops::Range<usize>) -> Self {
+        SetUsize(
+            self.0
+                .complement(universe.start as Item..universe.end as Item),
+        )
+    }
+    /// Replaces the set with the values in `0..n` that are not in it.
+    pub fn complement_in_place(&mut self, n: usize) {
+        self.0.complement_in_place(n as Item)
+    }
+}
+
+fn item_bounds<R: std::ops::RangeBounds<usize>>(
+    range: &R,
+) -> (std::ops::Bound<Item>, std::ops::Bound<Item>) {
+    use std::ops::Bound;
+    let conv = |b: Bound<&usize>| match b {
+        Bound::Included(&x) => Bound::Included(x as Item),
+        Bound::Excluded(&x) => Bound::Excluded(x as Item),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (conv(range.start_bound()), conv(range.end_bound()))
 }
 
 impl std::iter::FromIterator<usize> for SetUsize {
     fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
-        let iter = iter.into_iter();
-        // FIXME: It would be nice to cleverly allocate with the right capacity.
-        // let (sz,_) = iter.size_hint();
-        let mut c = SetUsize::new();
-        for i in iter {
-            c.insert(i);
-        }
-        c
+        SetUsize(iter.into_iter().map(|x| x as Item).collect())
     }
 }
 
@@ -164,9 +293,7 @@ impl IntoIterator for SetUsize {
 
 impl Extend<usize> for SetUsize {
     fn extend<T: IntoIterator<Item = usize>>(&mut self, iter: T) {
-        for i in iter.into_iter() {
-            self.insert(i);
-        }
+        self.0.extend(iter.into_iter().map(|x| x as Item))
     }
 }
 #[cfg(feature = "serde")]
@@ -234,6 +361,31 @@ impl crate::copyset::CopySet for SetUsize {
     }
 }
 
+#[test]
+fn batch_queries() {
+    let s: SetUsize = (0..100).map(|x| x * 2).collect();
+    assert!(s.contains_all(&[0, 2, 198]));
+    assert!(!s.contains_all(&[0, 1]));
+    assert!(s.contains_any(&[1, 3, 4]));
+    assert!(!s.contains_any(&[1, 3, 200]));
+    assert_eq!(s.count_contained(&[4, 4, 5]), 2);
+    assert_eq!(s.filter_contained(&[9, 8, 7, 6]), vec![8, 6]);
+}
+
+#[test]
+fn ranges() {
+    let mut s: SetUsize = (0..10).collect();
+    assert_eq!(s.insert_range(5..=200), 191);
+    assert_eq!(s.len(), 201);
+    assert_eq!(s.remove_range(100..150), 50);
+    let mut gone: Vec<usize> = s.drain_range(190..).collect();
+    gone.sort();
+    assert_eq!(gone, (190..=200).collect::<Vec<_>>());
+    assert_eq!(s, (0..100).chain(150..190).collect());
+    assert_eq!(s.insert_range(usize::MAX - 1..), 2);
+    assert!(s.contains(usize::MAX));
+}
+
 #[cfg(test)]
 use proptest::prelude::*;
 #[cfg(test)]
@@ -0,0 +1,285 @@
+//! Randomized checks for set types, enabled by the `testing` feature.
+//!
+//! These are the differential checks that `tinyset` runs on its own set
+//! types, comparing every operation against a [`HashSet`].  They work
+//! with any [`TinySet`], so they can be pointed at a wrapper around one
+//! of the sets in this crate, and [`check_fits64`] exercises a [`Set64`]
+//! of your own [`Fits64`] type.
+//!
+//! The sets in this crate choose their capacities at random as they grow,
+//! which means a failure might depend on more than the sequence of
+//! operations.  Every check here takes a seed, which fixes both the
+//! operations and those capacities, so a failure can be reproduced by
+//! running the check again with the seed it reports.
+//!
+//! ```
+//! use tinyset::{testing, Fits64};
+//!
+//! #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+//! struct Id(u32);
+//!
+//! impl Fits64 for Id {
+//!     unsafe fn from_u64(x: u64) -> Self {
+//!         Id(x as u32)
+//!     }
+//!     fn to_u64(self) -> u64 {
+//!         self.0 as u64
+//!     }
+//! }
+//!
+//! for seed in 0..10 {
+//!     testing::check_fits64(seed, 1000, |x| Id(x as u32));
+//! }
+//! ```
+
+use crate::rand::splitmix;
+use crate::{Fits64, Set64, TinySet};
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// Run `f` with the capacities of sets that grow on this thread
+/// determined by `seed`, as with [`set_seed`](crate::set_seed), rather
+/// than chosen at random.
+///
+/// The previous seed is restored when `f` returns, even if it panics.
+///
+/// ```
+/// use tinyset::{testing, SetU64};
+///
+/// let fill = || (0..1000).map(|x| x * 12345).collect::<SetU64>().capacity();
+/// assert_eq!(testing::with_seed(7, fill), testing::with_seed(7, fill));
+/// ```
+pub fn with_seed<R>(seed: u64, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<u64>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            crate::set_seed(self.0);
+        }
+    }
+    let _restore = Restore(crate::set_seed(Some(seed)));
+    f()
+}
+
+/// Check that a set built from `elems` agrees with a [`HashSet`], both as
+/// it is built and as it is emptied again.
+pub fn check_set<S>(elems: &[S::Item])
+where
+    S: TinySet,
+    S::Item: Eq + Hash + Debug,
+{
+    let mut s = S::default();
+    let mut model = HashSet::new();
+    for &e in elems {
+        assert_eq!(s.insert(e), model.insert(e), "insert {:?}", e);
+        assert_eq!(s.len(), model.len());
+        assert!(s.contains(e), "missing {:?} after inserting it", e);
+    }
+    check_model(&s, &model);
+    check_algebra(&s, &s.clone(), &model, &model);
+    for &e in elems {
+        assert_eq!(s.remove(e), model.remove(&e), "remove {:?}", e);
+        assert_eq!(s.len(), model.len());
+        assert!(!s.contains(e), "found {:?} after removing it", e);
+    }
+    assert!(s.is_empty());
+    assert_eq!(s.iter().count(), 0);
+}
+
+/// Apply `n` random inserts, removes and lookups to a set, checking each
+/// result and the contents of the set against a [`HashSet`].
+///
+/// Each element is `elem(x)`, where `x` is random and sometimes small,
+/// sometimes close to `u64::MAX` and sometimes arbitrary, so that every
+/// representation of the set is reached.  A panic reports `seed`, which
+/// reproduces the failure.
+pub fn check_random<S>(seed: u64, n: usize, mut elem: impl FnMut(u64) -> S::Item)
+where
+    S: TinySet,
+    S::Item: Eq + Hash + Debug,
+{
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        with_seed(seed, || {
+            let mut rng = Rng(seed);
+            let mut s = S::default();
+            let mut model = HashSet::new();
+            let mut other = S::default();
+            let mut other_model = HashSet::new();
+            for i in 0..n {
+                let r = rng.next();
+                let e = elem(rng.value());
+                match r % 8 {
+                    0..=3 => assert_eq!(s.insert(e), model.insert(e), "insert {:?}", e),
+                    4 | 5 => assert_eq!(s.remove(e), model.remove(&e), "remove {:?}", e),
+                    _ => assert_eq!(s.contains(e), model.contains(&e), "contains {:?}", e),
+                }
+                assert_eq!(s.len(), model.len());
+                if r & (1 << 20) != 0 {
+                    other.insert(e);
+                    other_model.insert(e);
+                }
+                if i % 64 == 63 {
+                    check_model(&s, &model);
+                }
+            }
+            check_model(&s, &model);
+            check_algebra(&s, &other, &model, &other_model);
+            let drained: HashSet<_> = s.drain().collect();
+            assert_eq!(drained, model);
+            assert!(s.is_empty());
+        })
+    }));
+    if let Err(e) = result {
+        eprintln!("tinyset::testing::check_random failed with seed {}", seed);
+        std::panic::resume_unwind(e);
+    }
+}
+
+/// Check that `elem` produces values that round-trip through
+/// [`Fits64`], and run [`check_random`] on a [`Set64`] of them.
+pub fn check_fits64<T>(seed: u64, n: usize, mut elem: impl FnMut(u64) -> T)
+where
+    T: Fits64 + Eq + Hash + Debug,
+{
+    let mut rng = Rng(!seed);
+    for _ in 0..n {
+        crate::set64::test_fits64(elem(rng.value()));
+    }
+    check_random::<Set64<T>>(seed, n, elem);
+}
+
+/// Check the contents of `s` without modifying it.
+fn check_model<S>(s: &S, model: &HashSet<S::Item>)
+where
+    S: TinySet,
+    S::Item: Eq + Hash + Debug,
+{
+    assert_eq!(s.len(), model.len());
+    assert_eq!(s.is_empty(), model.is_empty());
+    let mut seen = HashSet::new();
+    for e in s.iter() {
+        assert!(model.contains(&e), "iterated over extra element {:?}", e);
+        assert!(seen.insert(e), "iterated over {:?} twice", e);
+    }
+    assert_eq!(seen.len(), model.len());
+    for &e in model {
+        assert!(s.contains(e), "missing {:?}", e);
+    }
+    let c = s.clone();
+    assert_eq!(c.iter().collect::<HashSet<_>>(), seen);
+    let mut empty = S::with_capacity_of(s);
+    assert!(empty.is_empty());
+    for &e in model {
+        empty.insert(e);
+    }
+    assert_eq!(empty.len(), model.len());
+}
+
+fn check_algebra<S>(a: &S, b: &S, ma: &HashSet<S::Item>, mb: &HashSet<S::Item>)
+where
+    S: TinySet,
+    S::Item: Eq + Hash + Debug,
+{
+    check_model(&a.union(b), &ma.union(mb).cloned().collect());
+    check_model(&a.intersection(b), &ma.intersection(mb).cloned().collect());
+    check_model(&a.difference(b), &ma.difference(mb).cloned().collect());
+    check_model(&b.difference(a), &mb.difference(ma).cloned().collect());
+    assert_eq!(a.is_subset(b), ma.is_subset(mb));
+    assert_eq!(a.is_superset(b), ma.is_superset(mb));
+    assert_eq!(a.is_disjoint(b), ma.is_disjoint(mb));
+}
+
+/// The random operations and elements of the checks.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        splitmix(self.0)
+    }
+    /// A value that is small, near `u64::MAX` or arbitrary.  The small
+    /// ones fill dense and heap sets, and collide often enough to test
+    /// removal.
+    fn value(&mut self) -> u64 {
+        let r = self.next();
+        match r >> 61 {
+            0 | 1 => r % 64,
+            2 | 3 => r % 4096,
+            4 => r % (1 << 20),
+            5 => u64::MAX - r % 64,
+            6 => ((r % 64) << 32) | (r % 8),
+            _ => r,
+        }
+    }
+}
+
+#[test]
+fn seeded_capacity() {
+    let fill = || {
+        let s: crate::SetU64 = (0..1000).map(|x| x * 12345).collect();
+        (s.capacity(), s.iter().collect::<Vec<_>>())
+    };
+    assert_eq!(with_seed(3, fill), with_seed(3, fill));
+    let current = crate::rand::thread_seed;
+    with_seed(3, || {
+        assert_eq!(with_seed(4, current), Some(4));
+        assert_eq!(current(), Some(3));
+        let _ = std::panic::catch_unwind(|| with_seed(5, || panic!()));
+        assert_eq!(current(), Some(3));
+    });
+    assert_eq!(current(), None);
+}
+
+#[test]
+fn crate_sets() {
+    for seed in 0..20 {
+        check_random::<crate::SetU64>(seed, 2000, |x| x);
+        check_random::<crate::SetU32>(seed, 2000, |x| x as u32);
+        check_random::<crate::SetU32b>(seed, 2000, |x| x as u32);
+        check_random::<crate::SetUsize>(seed, 2000, |x| x as usize);
+        check_fits64(seed, 2000, |x| x as i64);
+        check_fits64(seed, 2000, |x| x as i8);
+        check_fits64(seed, 2000, |x| {
+            char::from_u32(x as u32 % 0x11_0000).unwrap_or('x')
+        });
+    }
+    check_set::<crate::SetU64>(&[0, 1, 2, u64::MAX, 1 << 40, 0]);
+    check_set::<crate::SetU32>(&(0..5000).map(|x| x * 7).collect::<Vec<_>>());
+    check_set::<crate::SetU32b>(&[u32::MAX, 0, 31, 32, u32::MAX]);
+}
+
+#[test]
+#[should_panic]
+fn catches_bad_sets() {
+    /// A set that forgets its largest elements.
+    #[derive(Default, Clone)]
+    struct Lossy(crate::SetU64);
+    impl TinySet for Lossy {
+        type Item = u64;
+        fn insert(&mut self, e: u64) -> bool {
+            e < 1 << 40 && self.0.insert(e)
+        }
+        fn remove(&mut self, e: u64) -> bool {
+            self.0.remove(e)
+        }
+        fn contains(&self, e: u64) -> bool {
+            self.0.contains(e)
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+            Box::new(self.0.iter())
+        }
+        fn drain(&mut self) -> Box<dyn Iterator<Item = u64> + '_> {
+            Box::new(self.0.drain())
+        }
+        fn with_capacity_of(other: &Self) -> Self {
+            Lossy(crate::SetU64::with_capacity_of(&other.0))
+        }
+        fn mem_used(&self) -> usize {
+            self.0.mem_used()
+        }
+    }
+    check_random::<Lossy>(0, 1000, |x| x);
+}
@@ -179,11 +179,11 @@ fn collision_panic() {
     set.remove(187);
     set.insert(186);
     set.remove(186);
-    set.debug_me("dense?");
+    println!("dense? {:#?}", set);
     set.insert(258);
-    set.debug_me("big?");
+    println!("big? {:#?}", set);
     set.insert(260);
-    set.debug_me("full?");
+    println!("full? {:#?}", set);
     println!("Just to be clear, the set is {set:?}");
     set.remove(2);
 }
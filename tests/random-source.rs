@@ -0,0 +1,59 @@
+//! The random source is shared by the whole process, so this test has a
+//! binary of its own.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tinyset::{OsSeeded, RandomSource, SetU64};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn counting() -> u64 {
+    let n = CALLS.fetch_add(1, Ordering::Relaxed) as u64;
+    // The output function of SplitMix64, so that the numbers vary.
+    let z = n.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn draw(source: &dyn RandomSource) -> Vec<u64> {
+    (0..100).map(|_| source.next_u64()).collect()
+}
+
+/// Insert large elements one at a time, so that the set's table grows
+/// by random amounts.
+fn grow() -> SetU64 {
+    let mut s = SetU64::new();
+    for x in 0..1000u64 {
+        s.insert(x.wrapping_mul(0x9e3779b97f4a7c15));
+    }
+    s
+}
+
+#[test]
+fn sources() {
+    let a = draw(&OsSeeded);
+    assert_ne!(a, draw(&OsSeeded));
+    let other = std::thread::spawn(|| draw(&OsSeeded)).join().unwrap();
+    assert_ne!(a, other);
+    #[cfg(feature = "rand")]
+    assert_ne!(draw(&tinyset::RandSource), draw(&tinyset::RandSource));
+
+    // A seed takes precedence over the source, so clear any default one.
+    tinyset::set_global_seed(None);
+    tinyset::set_random_source(Some(&counting));
+    assert_eq!(grow().len(), 1000);
+    let calls = CALLS.load(Ordering::Relaxed);
+    assert!(calls > 0);
+
+    let old = tinyset::set_seed(Some(1));
+    grow();
+    assert_eq!(CALLS.load(Ordering::Relaxed), calls);
+    tinyset::set_seed(old);
+
+    tinyset::set_random_source(None);
+    grow();
+    assert_eq!(CALLS.load(Ordering::Relaxed), calls);
+    if cfg!(feature = "deterministic_iteration") {
+        tinyset::set_global_seed(Some(0));
+    }
+}